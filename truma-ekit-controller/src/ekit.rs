@@ -1,14 +1,86 @@
-use crate::{heating::HeatingCoil, overtemperature_protection::OvertemperatureProtection};
+use crate::{
+    frost_protection::FrostProtection, heating::HeatingCoil,
+    overtemperature_protection::OvertemperatureProtection, power_budget::limited_run_mode,
+    thermostat::Thermostat,
+};
 use embedded_hal::digital::v2::OutputPin;
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use truma_ekit_core::{
-    ekit::{EKit as EKitCore, EKitSystemRunMode, EKitUserRunMode},
+    clock::{Clock, SystemClock},
+    control::{self, is_plausible_ambient, SetpointBounds, SetpointError, SETPOINT_BOUNDS},
+    ekit::{EKit as EKitCore, EKitEvent, EKitFault, EKitRelay, EKitSystemRunMode, EKitUserRunMode},
+    events::EventBus,
     measurement::Formatter,
-    peripherals::fan::Fan,
+    peripherals::{fan::Fan, relay::RelayStats},
     types::Temperature,
 };
 
+/// The longest boost that can be requested, to limit running at full capacity unattended.
+pub const MAX_BOOST_DURATION: Duration = Duration::from_secs(2 * 60 * 60);
+/// The time span of the reported ambient temperatures that are kept, to determine their rate of change.
+const AMBIENT_HISTORY_WINDOW: Duration = Duration::from_secs(10 * 60);
+
 pub trait EKit: EKitCore + Send {
-    fn set_output_temperature(&mut self, output_temperature: Option<Temperature>);
+    /// Set the output temperature of the e-kit, as read by each of the output temperature sensors.
+    fn set_output_temperatures(&mut self, readings: &[Option<Temperature>]);
+
+    /// Returns the most recently measured output temperature.
+    fn output_temperature(&self) -> Option<Temperature>;
+
+    /// Set the ambient temperature, as reported by the thermostat (measured at `measured_at`).
+    fn report_ambient_temperature(&mut self, temperature: Temperature, measured_at: Instant);
+
+    /// Run the e-kit on the controller, to reach the requested temperature.
+    ///
+    /// Ends once a run mode is requested. Returns an error if the requested temperature is out of the setpoint bounds.
+    fn set_setpoint(&mut self, setpoint: Temperature) -> Result<(), SetpointError>;
+
+    /// Returns the requested temperature, if the controller runs the e-kit on it.
+    fn setpoint(&self) -> Option<Temperature>;
+
+    /// Request the run mode the thermostat decided on, as it does periodically.
+    ///
+    /// Ignored while the controller runs the e-kit on a setpoint, which only a user request ends.
+    fn request_periodic_run_mode(&mut self, run_mode: EKitUserRunMode);
+
+    /// Set the lowest and highest temperatures that can be requested.
+    ///
+    /// The current requested temperature, if any, is kept within the new bounds.
+    fn set_setpoint_bounds(&mut self, setpoint_bounds: SetpointBounds);
+
+    /// Returns the lowest and highest temperatures that can be requested.
+    fn setpoint_bounds(&self) -> SetpointBounds;
+
+    /// Request the run mode needed to reach the requested temperature, if the controller runs the e-kit on it.
+    fn run_thermostat(&mut self, now: Instant);
+
+    /// Heat if needed to keep the ambient temperature above the frost protection setpoint, if requested.
+    fn run_frost_protection(&mut self, now: Instant);
+
+    /// Returns the time remaining until the boost ends, if boosting.
+    fn boost_remaining(&self, now: Instant) -> Option<Duration>;
+
+    /// End the boost once its duration has elapsed at `now`.
+    fn run_boost(&mut self, now: Instant);
+
+    /// Returns the current system run mode.
+    fn run_mode(&self) -> EKitSystemRunMode;
+
+    /// Limit the system run mode to stay within the power budget.
+    fn set_run_mode_limit(&mut self, limit: Option<EKitSystemRunMode>);
+
+    /// Returns `true` if the requested run mode has been downgraded to stay within the power budget.
+    fn is_power_limited(&self) -> bool;
+
+    /// Returns the bus on which the e-kit publishes its events.
+    fn events(&self) -> EventBus<EKitEvent>;
+
+    /// Returns the accumulated usage of the e-kit relays.
+    fn relay_stats(&self) -> [(EKitRelay, RelayStats); 3];
 }
 
 pub struct EKitLocal<F, C1, C2>
@@ -18,10 +90,29 @@ where
     C2: OutputPin,
 {
     run_mode: EKitSystemRunMode,
+    requested_run_mode: EKitSystemRunMode,
+    run_mode_limit: Option<EKitSystemRunMode>,
+    /// The most recently requested user run mode, reverted to after a boost.
+    user_run_mode: EKitUserRunMode,
+    /// The end of the boost, if boosting.
+    boost_until: Option<Instant>,
+    output_temperature: Option<Temperature>,
+    /// The ambient temperatures reported by the thermostat within the history window, and when they were measured,
+    /// oldest first.
+    ambient_history: VecDeque<(Instant, Temperature)>,
+    /// The lowest and highest temperatures that can be requested.
+    setpoint_bounds: SetpointBounds,
+    /// Set while the controller runs the e-kit on a requested temperature.
+    thermostat: Option<Thermostat>,
+    /// Active while frost protection has been requested.
+    frost_protection: Option<FrostProtection>,
     fan: Fan<F>,
     heating_coil1: HeatingCoil<C1>,
     heating_coil2: HeatingCoil<C2>,
     overtemperature_protection: OvertemperatureProtection,
+    events: EventBus<EKitEvent>,
+    /// The clock of the requests that don't pass the current time (e.g. a boost).
+    clock: Arc<dyn Clock>,
 }
 
 impl<F, C1, C2> EKitLocal<F, C1, C2>
@@ -37,23 +128,53 @@ where
     ) -> Self {
         let mut ekit = EKitLocal {
             run_mode: EKitSystemRunMode::Off,
+            requested_run_mode: EKitSystemRunMode::Off,
+            run_mode_limit: None,
+            user_run_mode: EKitUserRunMode::Off,
+            boost_until: None,
+            output_temperature: None,
+            ambient_history: VecDeque::new(),
+            setpoint_bounds: SETPOINT_BOUNDS,
+            thermostat: None,
+            frost_protection: None,
             fan,
             heating_coil1,
             heating_coil2,
             overtemperature_protection: OvertemperatureProtection::inactive(),
+            events: EventBus::new(),
+            clock: Arc::new(SystemClock),
         };
         ekit.enter_run_mode(EKitSystemRunMode::Off);
         ekit
     }
 
+    /// Use the given clock for the requests that don't pass the current time (e.g. a boost).
+    #[cfg(test)]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
     /// Returns `true` if the e-kit is currently turned on.
     #[allow(dead_code)]
     pub fn is_on(&self) -> bool {
         !matches!(self.run_mode, EKitSystemRunMode::Off)
     }
 
-    /// Set the output temperature of the e-kit.
+    /// Set the output temperature of the e-kit, as read by a single sensor.
+    #[cfg(test)]
     pub fn set_output_temperature(&mut self, output_temperature: Option<Temperature>) {
+        self.set_output_temperatures(&[output_temperature]);
+    }
+
+    /// Set the output temperature of the e-kit, as read by each of the output temperature sensors.
+    pub fn set_output_temperatures(&mut self, readings: &[Option<Temperature>]) {
+        let was_active = self.overtemperature_protection.is_active();
+        let sensor_fault = self.overtemperature_protection.sensor_fault();
+        let output_temperature = self
+            .overtemperature_protection
+            .output_temperatures_changed(readings);
+
         if let Some(output_temperature) = output_temperature {
             let formatter = Formatter::with_precision(2);
             log::info!(
@@ -62,20 +183,309 @@ where
             );
         }
 
-        self.overtemperature_protection
-            .output_temperature_changed(output_temperature);
+        self.output_temperature = output_temperature;
+
+        // published every second, kept in a separate history to not push out the other events
+        for (sensor, reading) in readings.iter().enumerate() {
+            if reading.is_none() {
+                self.events
+                    .publish_transient(EKitEvent::OutputTemperatureSensorFailed { sensor });
+            }
+        }
+        self.events.publish_transient(EKitEvent::OutputTemperature {
+            temperature: output_temperature,
+        });
+        match self.overtemperature_protection.sensor_fault() {
+            // forces cooldown, even if already cooling down
+            Some(fault) if Some(fault) != sensor_fault => {
+                self.events.publish(EKitEvent::Fault { fault });
+            }
+            _ if !was_active && self.overtemperature_protection.is_active() => {
+                let fault = match output_temperature {
+                    Some(_) => EKitFault::Overtemperature,
+                    None => EKitFault::OutputTemperatureUnavailable,
+                };
+                self.events.publish(EKitEvent::Fault { fault });
+            }
+            _ => {}
+        }
 
         self.update_run_mode(None);
     }
 
+    /// Set the ambient temperature, as reported by the thermostat (measured at `measured_at`).
+    ///
+    /// Implausible temperatures are ignored, so they age out like missing reports.
+    pub fn report_ambient_temperature(&mut self, temperature: Temperature, measured_at: Instant) {
+        if !is_plausible_ambient(temperature) {
+            log::warn!("ignoring implausible ambient temperature {:?}", temperature);
+            return;
+        }
+        if self
+            .reported_ambient()
+            .is_some_and(|(last_measured_at, _)| measured_at <= last_measured_at)
+        {
+            log::debug!("ignoring ambient temperature measured before the last report");
+            return;
+        }
+        self.ambient_history.push_back((measured_at, temperature));
+        while let Some((oldest_measured_at, _)) = self.ambient_history.front() {
+            if measured_at.saturating_duration_since(*oldest_measured_at) <= AMBIENT_HISTORY_WINDOW
+            {
+                break;
+            }
+            self.ambient_history.pop_front();
+        }
+    }
+
+    /// Returns the ambient temperature reported last by the thermostat, and when it was measured.
+    fn reported_ambient(&self) -> Option<(Instant, Temperature)> {
+        self.ambient_history.back().copied()
+    }
+
+    /// Run the e-kit on the controller, to reach the requested temperature.
+    ///
+    /// Ends once a run mode is requested. Returns an error if the requested temperature is out of the setpoint bounds.
+    pub fn set_setpoint(&mut self, setpoint: Temperature) -> Result<(), SetpointError> {
+        match &mut self.thermostat {
+            Some(thermostat) => thermostat.set_setpoint(setpoint)?,
+            None => {
+                self.thermostat = Some(Thermostat::new(setpoint, self.setpoint_bounds)?);
+                log::info!("running on setpoint");
+                self.frost_protection = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the requested temperature, if the controller runs the e-kit on it.
+    pub fn setpoint(&self) -> Option<Temperature> {
+        self.thermostat
+            .as_ref()
+            .map(|thermostat| thermostat.setpoint())
+    }
+
+    /// Request the run mode the thermostat decided on, as it does periodically.
+    ///
+    /// Ignored while the controller runs the e-kit on a setpoint, which only a user request ends.
+    pub fn request_periodic_run_mode(&mut self, run_mode: EKitUserRunMode) {
+        if self.thermostat.is_some() {
+            log::debug!(
+                "running on setpoint, ignoring periodic request for {:?}",
+                run_mode
+            );
+            return;
+        }
+        // repeats aren't published, so they don't push the other events out of the history (or count as requests)
+        let changed = run_mode != self.user_run_mode;
+        self.request_user_run_mode_published(run_mode, changed);
+    }
+
+    /// Request the e-kit user run mode, publishing the request if `publish` is set.
+    fn request_user_run_mode_published(&mut self, run_mode: EKitUserRunMode, publish: bool) {
+        if publish {
+            log::info!("request user run mode {:?}", run_mode);
+        } else {
+            log::debug!("request user run mode {:?}", run_mode);
+        }
+
+        let accepted = if self.boost_until.is_some() {
+            // applied once the boost ends
+            log::info!("boost active, deferring request");
+            true
+        } else {
+            self.apply_user_run_mode(run_mode)
+        };
+        if accepted {
+            self.user_run_mode = run_mode;
+            if self.thermostat.take().is_some() {
+                log::info!("no longer running on setpoint");
+            }
+        }
+
+        if publish {
+            self.events
+                .publish(EKitEvent::RunModeRequested { run_mode, accepted });
+        }
+    }
+
+    /// Set the lowest and highest temperatures that can be requested.
+    ///
+    /// The current requested temperature, if any, is kept within the new bounds.
+    pub fn set_setpoint_bounds(&mut self, setpoint_bounds: SetpointBounds) {
+        self.setpoint_bounds = setpoint_bounds;
+        if let Some(thermostat) = &mut self.thermostat {
+            thermostat.set_setpoint_bounds(setpoint_bounds);
+        }
+    }
+
+    /// Request the run mode needed to reach the requested temperature, if the controller runs the e-kit on it.
+    pub fn run_thermostat(&mut self, now: Instant) {
+        let thermostat = match &mut self.thermostat {
+            Some(thermostat) => thermostat,
+            None => return,
+        };
+
+        let reported_ambient = self.ambient_history.back().copied();
+        let rate_of_change = control::rate_of_change(self.ambient_history.iter().copied());
+        // runs at full capacity while boosting
+        let current_mode = if self.boost_until.is_some() {
+            EKitUserRunMode::Full
+        } else {
+            self.user_run_mode
+        };
+
+        let was_unavailable = thermostat.is_ambient_unavailable();
+        let run_mode = thermostat.control(reported_ambient, rate_of_change, current_mode, now);
+        if !was_unavailable && thermostat.is_ambient_unavailable() {
+            self.events.publish(EKitEvent::Fault {
+                fault: EKitFault::AmbientTemperatureUnavailable,
+            });
+        }
+
+        if self.boost_until.is_some() {
+            // applied once the boost ends
+            self.user_run_mode = run_mode;
+        } else if run_mode != self.user_run_mode && self.apply_user_run_mode(run_mode) {
+            // like a requested run mode, not resumed after overtemperature protection until it changes
+            self.user_run_mode = run_mode;
+        }
+    }
+
+    /// Heat if needed to keep the ambient temperature above the frost protection setpoint, if requested.
+    pub fn run_frost_protection(&mut self, now: Instant) {
+        let run_mode = self.run_mode;
+        let output_temperature = self
+            .output_temperature
+            .filter(|_| run_mode == EKitSystemRunMode::Off);
+        let ambient =
+            FrostProtection::ambient_temperature(self.reported_ambient(), output_temperature, now);
+        let frost_protection = match &mut self.frost_protection {
+            Some(frost_protection) => frost_protection,
+            None => return,
+        };
+
+        if run_mode == EKitSystemRunMode::Cooldown {
+            // cooling down while heating means overtemperature protection kicked in
+            if frost_protection.is_heating() {
+                frost_protection.lock_out(now);
+            }
+            return;
+        }
+
+        let was_heating = frost_protection.is_heating();
+        let requested_run_mode = frost_protection.update(ambient, now);
+        if frost_protection.is_heating() != was_heating {
+            // turning off enters cooldown, like any other request
+            self.request_run_mode(requested_run_mode);
+        }
+    }
+
+    /// Run at full capacity for the given duration from `now`, or end the boost early for a zero duration.
+    ///
+    /// Returns `false` if the request was denied, e.g. because the duration exceeds the maximum.
+    pub fn start_boost(&mut self, duration: Duration, now: Instant) -> bool {
+        if duration.is_zero() {
+            if self.boost_until.is_some() {
+                self.end_boost();
+            }
+            return true;
+        }
+
+        let until = match now.checked_add(duration) {
+            Some(until) if duration <= MAX_BOOST_DURATION => until,
+            _ => {
+                log::warn!("boost for {:?} exceeds the maximum duration", duration);
+                return false;
+            }
+        };
+        log::info!("boost for {:?}", duration);
+        if !self.request_run_mode(EKitSystemRunMode::Full) {
+            return false;
+        }
+        // frost protection resumes after the boost, if still requested
+        self.frost_protection = None;
+        self.boost_until = Some(until);
+        true
+    }
+
+    /// Returns the time remaining until the boost ends, if boosting.
+    pub fn boost_remaining(&self, now: Instant) -> Option<Duration> {
+        self.boost_until
+            .map(|until| until.saturating_duration_since(now))
+    }
+
+    /// End the boost once its duration has elapsed at `now`.
+    pub fn run_boost(&mut self, now: Instant) {
+        match self.boost_until {
+            Some(_) if self.run_mode == EKitSystemRunMode::Cooldown => {
+                // like any other run mode, don't resume after overtemperature protection kicked in
+                log::warn!("overtemperature protection interrupted boost");
+                self.boost_until = None;
+            }
+            Some(until) if now >= until => self.end_boost(),
+            _ => {}
+        }
+    }
+
+    /// End the boost, reverting to the most recently requested user run mode.
+    fn end_boost(&mut self) {
+        log::info!("boost ended, reverting to {:?}", self.user_run_mode);
+        self.boost_until = None;
+        self.apply_user_run_mode(self.user_run_mode);
+    }
+
+    /// Apply the user run mode.
+    ///
+    /// Returns `false` if the request was denied.
+    fn apply_user_run_mode(&mut self, run_mode: EKitUserRunMode) -> bool {
+        if run_mode == EKitUserRunMode::FrostProtection && self.frost_protection.is_some() {
+            // keep running, so its lockout and minimum runtime aren't reset by repeated requests
+            return true;
+        }
+
+        let accepted = self.request_run_mode(match run_mode {
+            EKitUserRunMode::Off | EKitUserRunMode::FrostProtection => EKitSystemRunMode::Off,
+            EKitUserRunMode::Cool => EKitSystemRunMode::Cool,
+            EKitUserRunMode::Half => EKitSystemRunMode::Half,
+            EKitUserRunMode::Full => EKitSystemRunMode::Full,
+        });
+
+        if accepted {
+            // frost protection starts idle, and decides whether to heat on the next run
+            self.frost_protection = match run_mode {
+                EKitUserRunMode::FrostProtection => Some(FrostProtection::new()),
+                _ => None,
+            };
+        }
+        accepted
+    }
+
+    /// Limit the e-kit run mode to stay within the power budget.
+    pub fn set_run_mode_limit(&mut self, limit: Option<EKitSystemRunMode>) {
+        if limit != self.run_mode_limit {
+            log::info!("run mode limit changed to {:?}", limit);
+            self.run_mode_limit = limit;
+            // apply the new limit to the requested run mode
+            self.update_run_mode(Some(self.requested_run_mode));
+        }
+    }
+
+    /// Returns `true` if the requested run mode has been downgraded to stay within the power budget.
+    pub fn is_power_limited(&self) -> bool {
+        self.limited(self.requested_run_mode) != self.requested_run_mode
+    }
+
     /// Request the e-kit run mode.
-    fn request_run_mode(&mut self, run_mode: EKitSystemRunMode) {
+    ///
+    /// Returns `false` if the request was denied.
+    fn request_run_mode(&mut self, run_mode: EKitSystemRunMode) -> bool {
         log::info!("request system run mode {:?}", run_mode);
 
         // disallow changing the run mode when cooldown is active
         if matches!(self.run_mode, EKitSystemRunMode::Cooldown) {
             log::info!("cooldown active, request denied");
-            return;
+            return false;
         }
 
         if matches!(run_mode, EKitSystemRunMode::Off) {
@@ -83,20 +493,41 @@ where
         }
 
         self.update_run_mode(Some(run_mode));
+        true
     }
 
     /// Update the e-kit run mode.
     ///
-    /// Overtempetature protection forced run mode takes priority over `requested_run_mode`.
+    /// Overtempetature protection forced run mode takes priority over `requested_run_mode`, which is limited to the
+    /// run mode limit.
     fn update_run_mode(&mut self, requested_run_mode: Option<EKitSystemRunMode>) {
         if let Some(forced_mode) = self.overtemperature_protection.forced_run_mode() {
             log::info!("forcing run mode {:?}", forced_mode);
+            // don't resume the previously requested run mode once the limit changes
+            self.requested_run_mode = forced_mode;
             self.enter_run_mode(forced_mode);
         } else if let Some(requested_mode) = requested_run_mode {
-            self.enter_run_mode(requested_mode);
+            self.requested_run_mode = requested_mode;
+            let run_mode = self.limited(requested_mode);
+            if run_mode != requested_mode {
+                log::info!("limiting run mode {:?} to {:?}", requested_mode, run_mode);
+                self.events.publish(EKitEvent::RunModeLimited {
+                    requested: requested_mode,
+                    limited_to: run_mode,
+                });
+            }
+            self.enter_run_mode(run_mode);
         };
     }
 
+    /// Returns the run mode, downgraded to the run mode limit (if any).
+    fn limited(&self, run_mode: EKitSystemRunMode) -> EKitSystemRunMode {
+        match self.run_mode_limit {
+            Some(limit) => limited_run_mode(run_mode, limit),
+            None => run_mode,
+        }
+    }
+
     /// Enter the e-kit run mode.
     fn enter_run_mode(&mut self, run_mode: EKitSystemRunMode) {
         log::info!("entering run mode {:?}", run_mode);
@@ -127,10 +558,41 @@ where
             }
         }
 
+        if run_mode != self.run_mode {
+            self.events.publish(EKitEvent::RunModeChanged {
+                from: self.run_mode,
+                to: run_mode,
+            });
+
+            let before = closed_relays(self.run_mode);
+            let after = closed_relays(run_mode);
+            for ((relay, was_closed), (_, closed)) in before.into_iter().zip(after) {
+                if was_closed != closed {
+                    self.events
+                        .publish(EKitEvent::RelayChanged { relay, closed });
+                }
+            }
+        }
+
         self.run_mode = run_mode;
     }
 }
 
+/// Returns which relays are closed in the given run mode.
+pub fn closed_relays(run_mode: EKitSystemRunMode) -> [(EKitRelay, bool); 3] {
+    let (fan, coil1, coil2) = match run_mode {
+        EKitSystemRunMode::Off => (false, false, false),
+        EKitSystemRunMode::Cooldown | EKitSystemRunMode::Cool => (true, false, false),
+        EKitSystemRunMode::Half => (true, true, false),
+        EKitSystemRunMode::Full => (true, true, true),
+    };
+    [
+        (EKitRelay::Fan, fan),
+        (EKitRelay::Coil1, coil1),
+        (EKitRelay::Coil2, coil2),
+    ]
+}
+
 impl<F, C1, C2> EKitCore for EKitLocal<F, C1, C2>
 where
     F: OutputPin,
@@ -138,14 +600,13 @@ where
     C2: OutputPin,
 {
     fn request_user_run_mode(&mut self, run_mode: EKitUserRunMode) {
-        log::info!("request user run mode {:?}", run_mode);
+        self.request_user_run_mode_published(run_mode, true);
+    }
 
-        self.request_run_mode(match run_mode {
-            EKitUserRunMode::Off => EKitSystemRunMode::Off,
-            EKitUserRunMode::Cool => EKitSystemRunMode::Cool,
-            EKitUserRunMode::Half => EKitSystemRunMode::Half,
-            EKitUserRunMode::Full => EKitSystemRunMode::Full,
-        })
+    fn request_boost(&mut self, duration: Duration) {
+        if !self.start_boost(duration, self.clock.now()) {
+            log::info!("boost denied");
+        }
     }
 }
 
@@ -155,53 +616,128 @@ where
     C1: OutputPin + Send,
     C2: OutputPin + Send,
 {
-    fn set_output_temperature(&mut self, output_temperature: Option<Temperature>) {
-        EKitLocal::set_output_temperature(self, output_temperature);
+    fn set_output_temperatures(&mut self, readings: &[Option<Temperature>]) {
+        EKitLocal::set_output_temperatures(self, readings);
+    }
+
+    fn output_temperature(&self) -> Option<Temperature> {
+        self.output_temperature
+    }
+
+    fn report_ambient_temperature(&mut self, temperature: Temperature, measured_at: Instant) {
+        EKitLocal::report_ambient_temperature(self, temperature, measured_at);
+    }
+
+    fn set_setpoint(&mut self, setpoint: Temperature) -> Result<(), SetpointError> {
+        EKitLocal::set_setpoint(self, setpoint)
+    }
+
+    fn setpoint(&self) -> Option<Temperature> {
+        EKitLocal::setpoint(self)
+    }
+
+    fn request_periodic_run_mode(&mut self, run_mode: EKitUserRunMode) {
+        EKitLocal::request_periodic_run_mode(self, run_mode);
+    }
+
+    fn set_setpoint_bounds(&mut self, setpoint_bounds: SetpointBounds) {
+        EKitLocal::set_setpoint_bounds(self, setpoint_bounds);
+    }
+
+    fn setpoint_bounds(&self) -> SetpointBounds {
+        self.setpoint_bounds
+    }
+
+    fn run_thermostat(&mut self, now: Instant) {
+        EKitLocal::run_thermostat(self, now);
+    }
+
+    fn run_frost_protection(&mut self, now: Instant) {
+        EKitLocal::run_frost_protection(self, now);
+    }
+
+    fn boost_remaining(&self, now: Instant) -> Option<Duration> {
+        EKitLocal::boost_remaining(self, now)
+    }
+
+    fn run_boost(&mut self, now: Instant) {
+        EKitLocal::run_boost(self, now);
+    }
+
+    fn run_mode(&self) -> EKitSystemRunMode {
+        self.run_mode
+    }
+
+    fn set_run_mode_limit(&mut self, limit: Option<EKitSystemRunMode>) {
+        EKitLocal::set_run_mode_limit(self, limit);
+    }
+
+    fn is_power_limited(&self) -> bool {
+        EKitLocal::is_power_limited(self)
+    }
+
+    fn events(&self) -> EventBus<EKitEvent> {
+        self.events.clone()
+    }
+
+    fn relay_stats(&self) -> [(EKitRelay, RelayStats); 3] {
+        [
+            (EKitRelay::Fan, self.fan.relay().stats()),
+            (EKitRelay::Coil1, self.heating_coil1.relay().stats()),
+            (EKitRelay::Coil2, self.heating_coil2.relay().stats()),
+        ]
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use embedded_hal::digital::v2::StatefulOutputPin;
-    use truma_ekit_core::peripherals::relay::Relay;
+    use assert_approx_eq::assert_approx_eq;
+    use std::sync::mpsc::Receiver;
+    use truma_ekit_core::{
+        events::Sequenced,
+        peripherals::relay::Relay,
+        testing::{secs, FakeClock, RecordingPin, Scenario},
+        types::UnitTemperature,
+        util::{celsius, celsius_delta},
+    };
 
     #[test]
     fn is_initially_turned_off() {
         assert!(!EKitLocal::new(
-            Fan::new(Relay::connected_to(TestPin(false))),
-            HeatingCoil::new(Relay::connected_to(TestPin(false))),
-            HeatingCoil::new(Relay::connected_to(TestPin(false))),
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
         )
         .is_on());
         assert!(!EKitLocal::new(
-            Fan::new(Relay::connected_to(TestPin(true))),
-            HeatingCoil::new(Relay::connected_to(TestPin(false))),
-            HeatingCoil::new(Relay::connected_to(TestPin(false))),
+            Fan::new(Relay::connected_to(RecordingPin::new(true))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
         )
         .is_on());
         assert!(!EKitLocal::new(
-            Fan::new(Relay::connected_to(TestPin(false))),
-            HeatingCoil::new(Relay::connected_to(TestPin(true))),
-            HeatingCoil::new(Relay::connected_to(TestPin(false))),
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(true))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
         )
         .is_on());
         assert!(!EKitLocal::new(
-            Fan::new(Relay::connected_to(TestPin(false))),
-            HeatingCoil::new(Relay::connected_to(TestPin(false))),
-            HeatingCoil::new(Relay::connected_to(TestPin(true))),
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(true))),
         )
         .is_on());
         assert!(!EKitLocal::new(
-            Fan::new(Relay::connected_to(TestPin(true))),
-            HeatingCoil::new(Relay::connected_to(TestPin(true))),
-            HeatingCoil::new(Relay::connected_to(TestPin(true))),
+            Fan::new(Relay::connected_to(RecordingPin::new(true))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(true))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(true))),
         )
         .is_on());
         assert!(!EKitLocal::new(
-            Fan::new(Relay::connected_to(TestPin(true))),
-            HeatingCoil::new(Relay::connected_to(TestPin(false))),
-            HeatingCoil::new(Relay::connected_to(TestPin(true))),
+            Fan::new(Relay::connected_to(RecordingPin::new(true))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(true))),
         )
         .is_on());
     }
@@ -209,9 +745,9 @@ mod tests {
     #[test]
     fn update_run_mode_prioritizes_overtemperature_protection() {
         let mut ekit = EKitLocal::new(
-            Fan::new(Relay::connected_to(TestPin(false))),
-            HeatingCoil::new(Relay::connected_to(TestPin(false))),
-            HeatingCoil::new(Relay::connected_to(TestPin(false))),
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
         );
 
         ekit.update_run_mode(None);
@@ -229,9 +765,9 @@ mod tests {
     #[test]
     fn overtemperature_protection() {
         let mut ekit = EKitLocal::new(
-            Fan::new(Relay::connected_to(TestPin(false))),
-            HeatingCoil::new(Relay::connected_to(TestPin(false))),
-            HeatingCoil::new(Relay::connected_to(TestPin(false))),
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
         );
 
         ekit.run_mode = EKitSystemRunMode::Cool;
@@ -273,9 +809,9 @@ mod tests {
     #[test]
     fn turns_peripherals_on_and_off() {
         let mut ekit = EKitLocal::new(
-            Fan::new(Relay::connected_to(TestPin(false))),
-            HeatingCoil::new(Relay::connected_to(TestPin(false))),
-            HeatingCoil::new(Relay::connected_to(TestPin(false))),
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
         );
 
         ekit.enter_run_mode(EKitSystemRunMode::Cool);
@@ -314,29 +850,572 @@ mod tests {
         );
     }
 
-    struct TestPin(bool);
+    #[test]
+    fn publishes_run_mode_and_relay_changes() {
+        let mut ekit = EKitLocal::new(
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+        );
+        let events = ekit.events.subscribe();
+
+        ekit.enter_run_mode(EKitSystemRunMode::Half);
+        ekit.enter_run_mode(EKitSystemRunMode::Half);
+        ekit.enter_run_mode(EKitSystemRunMode::Cool);
+
+        assert_eq!(
+            events.try_iter().map(|e| e.event).collect::<Vec<_>>(),
+            vec![
+                EKitEvent::RunModeChanged {
+                    from: EKitSystemRunMode::Off,
+                    to: EKitSystemRunMode::Half
+                },
+                EKitEvent::RelayChanged {
+                    relay: EKitRelay::Fan,
+                    closed: true
+                },
+                EKitEvent::RelayChanged {
+                    relay: EKitRelay::Coil1,
+                    closed: true
+                },
+                EKitEvent::RunModeChanged {
+                    from: EKitSystemRunMode::Half,
+                    to: EKitSystemRunMode::Cool
+                },
+                EKitEvent::RelayChanged {
+                    relay: EKitRelay::Coil1,
+                    closed: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn publishes_run_mode_requests() {
+        let mut ekit = EKitLocal::new(
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+        );
+        let events = ekit.events.subscribe();
+
+        ekit.request_user_run_mode(EKitUserRunMode::Half);
+        ekit.overtemperature_protection.enter();
+        ekit.update_run_mode(None);
+        ekit.request_user_run_mode(EKitUserRunMode::Full);
+
+        let requests = events
+            .try_iter()
+            .filter(|e| matches!(e.event, EKitEvent::RunModeRequested { .. }))
+            .map(|e| e.event)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            vec![
+                EKitEvent::RunModeRequested {
+                    run_mode: EKitUserRunMode::Half,
+                    accepted: true
+                },
+                EKitEvent::RunModeRequested {
+                    run_mode: EKitUserRunMode::Full,
+                    accepted: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn publishes_faults() {
+        let mut ekit = EKitLocal::new(
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+        );
+        let events = ekit.events.subscribe();
+        let faults = |events: &Receiver<Sequenced<EKitEvent>>| {
+            events
+                .try_iter()
+                .filter_map(|e| match e.event {
+                    EKitEvent::Fault { fault } => Some(fault),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
 
-    impl OutputPin for TestPin {
-        type Error = std::convert::Infallible;
+        ekit.set_output_temperature(Some(celsius(100.0)));
+        ekit.set_output_temperature(Some(celsius(100.0)));
+        assert_eq!(faults(&events), vec![EKitFault::Overtemperature]);
 
-        fn set_high(&mut self) -> Result<(), Self::Error> {
-            self.0 = true;
-            Ok(())
+        ekit.set_output_temperature(Some(celsius(20.0)));
+        ekit.set_output_temperature(None);
+        assert_eq!(
+            faults(&events),
+            vec![EKitFault::OutputTemperatureUnavailable]
+        );
+
+        ekit.overtemperature_protection =
+            OvertemperatureProtection::inactive().with_disagreement_tolerance(celsius_delta(15.0));
+        ekit.set_output_temperatures(&[Some(celsius(20.0)), Some(celsius(60.0))]);
+        ekit.set_output_temperatures(&[Some(celsius(20.0)), Some(celsius(60.0))]);
+        assert_eq!(
+            faults(&events),
+            vec![EKitFault::OutputTemperatureSensorsDisagree]
+        );
+        // the highest reading is used
+        assert_eq!(ekit.output_temperature, Some(celsius(60.0)));
+    }
+
+    #[test]
+    fn keeps_history_of_reported_ambient() {
+        let mut ekit = EKitLocal::new(
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+        );
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // reported every 30 s, rising by 0.5 °C per minute
+        for secs in (0..=900).step_by(30) {
+            ekit.report_ambient_temperature(celsius(15.0 + secs as f32 / 120.0), at(secs));
         }
+        // a report measured before the last one is ignored
+        ekit.report_ambient_temperature(celsius(30.0), at(890));
+
+        assert_eq!(ekit.reported_ambient(), Some((at(900), celsius(22.5))));
+        assert_eq!(
+            ekit.ambient_history.front(),
+            Some(&(at(300), celsius(17.5)))
+        );
+        let rate = control::rate_of_change(ekit.ambient_history.iter().copied()).unwrap();
+        assert_approx_eq!(
+            rate.converted_to(UnitTemperature::celsius().delta()).value,
+            0.5,
+            1e-3
+        );
+    }
+
+    #[test]
+    fn sensor_faults_force_cooldown() {
+        let mut ekit = EKitLocal::new(
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+        );
+        ekit.overtemperature_protection =
+            OvertemperatureProtection::inactive().with_disagreement_tolerance(celsius_delta(15.0));
+        ekit.set_output_temperatures(&[Some(celsius(30.0)), Some(celsius(32.0))]);
+        ekit.request_user_run_mode(EKitUserRunMode::Full);
+        assert_eq!(ekit.run_mode, EKitSystemRunMode::Full);
+
+        // one of the sensors fails, which the other one covers
+        ekit.set_output_temperatures(&[Some(celsius(40.0)), None]);
+        assert_eq!(ekit.run_mode, EKitSystemRunMode::Full);
+
+        // both sensors fail
+        ekit.set_output_temperatures(&[None, None]);
+        assert_eq!(ekit.run_mode, EKitSystemRunMode::Cooldown);
+
+        // turns off once the sensors can be read again and the output has cooled down
+        ekit.set_output_temperatures(&[Some(celsius(45.0)), Some(celsius(44.0))]);
+        assert_eq!(ekit.run_mode, EKitSystemRunMode::Off);
+
+        // the sensors disagree
+        ekit.set_output_temperatures(&[Some(celsius(30.0)), Some(celsius(32.0))]);
+        ekit.request_user_run_mode(EKitUserRunMode::Full);
+        assert_eq!(ekit.run_mode, EKitSystemRunMode::Full);
+        ekit.set_output_temperatures(&[Some(celsius(30.0)), Some(celsius(70.0))]);
+        assert_eq!(ekit.run_mode, EKitSystemRunMode::Cooldown);
+    }
+
+    #[test]
+    fn publishes_sensor_failures() {
+        let mut ekit = EKitLocal::new(
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+        );
+        let events = ekit.events.subscribe();
+
+        // published even though the other sensor covers it
+        ekit.set_output_temperatures(&[Some(celsius(30.0)), None]);
+        assert_eq!(ekit.output_temperature, Some(celsius(30.0)));
+        assert_eq!(
+            events
+                .try_iter()
+                .filter(|e| matches!(e.event, EKitEvent::OutputTemperatureSensorFailed { .. }))
+                .map(|e| e.event)
+                .collect::<Vec<_>>(),
+            vec![EKitEvent::OutputTemperatureSensorFailed { sensor: 1 }]
+        );
+    }
+
+    #[test]
+    fn limits_run_mode_to_power_budget() {
+        let mut ekit = EKitLocal::new(
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+        );
+        let events = ekit.events.subscribe();
+
+        ekit.set_run_mode_limit(Some(EKitSystemRunMode::Half));
+        ekit.request_user_run_mode(EKitUserRunMode::Full);
+        assert_eq!(ekit.run_mode, EKitSystemRunMode::Half);
+        assert!(ekit.is_power_limited());
+        assert!(events.try_iter().any(|e| e.event
+            == EKitEvent::RunModeLimited {
+                requested: EKitSystemRunMode::Full,
+                limited_to: EKitSystemRunMode::Half
+            }));
+
+        // shed another coil
+        ekit.set_run_mode_limit(Some(EKitSystemRunMode::Cool));
+        assert_eq!(ekit.run_mode, EKitSystemRunMode::Cool);
+
+        // restore the requested run mode once the budget allows it
+        ekit.set_run_mode_limit(Some(EKitSystemRunMode::Full));
+        assert_eq!(ekit.run_mode, EKitSystemRunMode::Full);
+        assert!(!ekit.is_power_limited());
+    }
+
+    #[test]
+    fn does_not_resume_run_mode_after_overtemperature_protection() {
+        let mut ekit = EKitLocal::new(
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+        );
+
+        ekit.set_run_mode_limit(Some(EKitSystemRunMode::Half));
+        ekit.request_user_run_mode(EKitUserRunMode::Full);
+        ekit.set_output_temperature(Some(celsius(100.0)));
+        ekit.set_output_temperature(Some(celsius(20.0)));
+        assert_eq!(ekit.run_mode, EKitSystemRunMode::Off);
+
+        ekit.set_output_temperature(Some(celsius(20.0)));
+        ekit.set_run_mode_limit(Some(EKitSystemRunMode::Full));
+        assert_eq!(ekit.run_mode, EKitSystemRunMode::Off);
+    }
+
+    #[test]
+    fn scenario_cooldown_on_overtemperature() {
+        type TestEKit = EKitLocal<RecordingPin, RecordingPin, RecordingPin>;
+
+        let (fan, coil1, coil2) = (
+            RecordingPin::new(false),
+            RecordingPin::new(false),
+            RecordingPin::new(false),
+        );
+        let mut ekit = EKitLocal::new(
+            Fan::new(Relay::connected_to(fan.clone())),
+            HeatingCoil::new(Relay::connected_to(coil1.clone())),
+            HeatingCoil::new(Relay::connected_to(coil2.clone())),
+        );
 
-        fn set_low(&mut self) -> Result<(), Self::Error> {
-            self.0 = false;
-            Ok(())
+        Scenario::new()
+            .at(secs(0), |ekit: &mut TestEKit, _| {
+                ekit.request_user_run_mode(EKitUserRunMode::Half)
+            })
+            .every(secs(1), secs(0), secs(29), |ekit, _| {
+                ekit.set_output_temperature(Some(celsius(60.0)))
+            })
+            .expect(secs(29), |ekit| ekit.run_mode, EKitSystemRunMode::Half)
+            .at(secs(30), |ekit, _| {
+                ekit.set_output_temperature(Some(celsius(95.0)))
+            })
+            .expect(secs(30), |ekit| ekit.run_mode, EKitSystemRunMode::Cooldown)
+            .every(secs(1), secs(31), secs(89), |ekit, _| {
+                ekit.set_output_temperature(Some(celsius(70.0)))
+            })
+            .expect(secs(89), |ekit| ekit.run_mode, EKitSystemRunMode::Cooldown)
+            .at(secs(90), |ekit, _| {
+                ekit.set_output_temperature(Some(celsius(45.0)))
+            })
+            .expect(secs(90), |ekit| ekit.run_mode, EKitSystemRunMode::Off)
+            .run(&mut ekit, &FakeClock::new());
+
+        // the fan kept running during cooldown, the coil was turned off immediately
+        assert_eq!(fan.transitions(), 2);
+        assert_eq!(coil1.transitions(), 2);
+        assert_eq!(coil2.transitions(), 0);
+        assert!(!fan.is_high() && !coil1.is_high());
+    }
+
+    #[test]
+    fn scenario_frost_protection() {
+        type TestEKit = EKitLocal<RecordingPin, RecordingPin, RecordingPin>;
+
+        let mut ekit = EKitLocal::new(
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+        );
+        fn run(ekit: &mut TestEKit, output_temperature: f32, now: Instant) {
+            ekit.set_output_temperature(Some(celsius(output_temperature)));
+            ekit.run_frost_protection(now);
         }
+
+        Scenario::new()
+            .at(secs(0), |ekit: &mut TestEKit, now| {
+                ekit.request_user_run_mode(EKitUserRunMode::FrostProtection);
+                run(ekit, 8.0, now);
+            })
+            // the output temperature is used while the e-kit is off
+            .expect(secs(0), |ekit| ekit.run_mode, EKitSystemRunMode::Off)
+            .at(secs(60), |ekit, now| run(ekit, 4.5, now))
+            .expect(secs(60), |ekit| ekit.run_mode, EKitSystemRunMode::Half)
+            // requesting it again (e.g. by the thermostat, periodically) keeps heating
+            .at(secs(90), |ekit, now| {
+                ekit.request_user_run_mode(EKitUserRunMode::FrostProtection);
+                run(ekit, 40.0, now);
+            })
+            .expect(secs(90), |ekit| ekit.run_mode, EKitSystemRunMode::Half)
+            // the ambient temperature reported by the thermostat is used while heating
+            .at(secs(120), |ekit, now| {
+                ekit.report_ambient_temperature(celsius(8.0), now);
+                run(ekit, 40.0, now);
+            })
+            .expect(secs(120), |ekit| ekit.run_mode, EKitSystemRunMode::Half)
+            // stops once the minimum runtime has elapsed, cooling down first
+            .at(secs(660), |ekit, now| {
+                ekit.report_ambient_temperature(celsius(8.0), now);
+                run(ekit, 40.0, now);
+            })
+            .expect(secs(660), |ekit| ekit.run_mode, EKitSystemRunMode::Cooldown)
+            .at(secs(661), |ekit, now| run(ekit, 20.0, now))
+            .expect(secs(661), |ekit| ekit.run_mode, EKitSystemRunMode::Off)
+            // starts again when it gets cold, until overtemperature protection kicks in
+            .at(secs(700), |ekit, now| {
+                ekit.report_ambient_temperature(celsius(4.0), now);
+                run(ekit, 10.0, now);
+            })
+            .expect(secs(700), |ekit| ekit.run_mode, EKitSystemRunMode::Half)
+            .at(secs(710), |ekit, now| run(ekit, 95.0, now))
+            .expect(secs(710), |ekit| ekit.run_mode, EKitSystemRunMode::Cooldown)
+            .at(secs(720), |ekit, now| run(ekit, 45.0, now))
+            .expect(secs(720), |ekit| ekit.run_mode, EKitSystemRunMode::Off)
+            // locked out for a while, even though it's still cold and it's requested again
+            .at(secs(1300), |ekit, now| {
+                ekit.request_user_run_mode(EKitUserRunMode::FrostProtection);
+                ekit.report_ambient_temperature(celsius(4.0), now);
+                run(ekit, 10.0, now);
+            })
+            .expect(secs(1300), |ekit| ekit.run_mode, EKitSystemRunMode::Off)
+            .at(secs(1310), |ekit, now| {
+                ekit.report_ambient_temperature(celsius(4.0), now);
+                run(ekit, 10.0, now);
+            })
+            .expect(secs(1310), |ekit| ekit.run_mode, EKitSystemRunMode::Half)
+            // any other request turns frost protection off
+            .at(secs(1320), |ekit, now| {
+                ekit.request_user_run_mode(EKitUserRunMode::Cool);
+                run(ekit, 10.0, now);
+            })
+            .expect(secs(1320), |ekit| ekit.run_mode, EKitSystemRunMode::Cool)
+            .run(&mut ekit, &FakeClock::new());
     }
 
-    impl StatefulOutputPin for TestPin {
-        fn is_set_high(&self) -> Result<bool, Self::Error> {
-            Ok(self.0)
+    #[test]
+    fn scenario_follows_clock() {
+        type TestEKit = EKitLocal<RecordingPin, RecordingPin, RecordingPin>;
+
+        let clock = FakeClock::new();
+        let relay =
+            || Relay::connected_to(RecordingPin::new(false)).with_clock(Arc::new(clock.clone()));
+        let mut ekit = EKitLocal::new(
+            Fan::new(relay()),
+            HeatingCoil::new(relay()),
+            HeatingCoil::new(relay()),
+        )
+        .with_clock(Arc::new(clock.clone()));
+        let closed_duration =
+            |ekit: &TestEKit| EKit::relay_stats(ekit).map(|(_, stats)| stats.closed_duration);
+
+        Scenario::new()
+            .at(secs(0), |ekit: &mut TestEKit, _| {
+                ekit.set_output_temperature(Some(celsius(30.0)));
+                ekit.request_user_run_mode(EKitUserRunMode::Half);
+            })
+            .expect(secs(60), closed_duration, [secs(60), secs(60), secs(0)])
+            // the boost starts at the time of the request
+            .at(secs(60), |ekit, _| EKitCore::request_boost(ekit, secs(120)))
+            .at(secs(100), |ekit, now| {
+                assert_eq!(ekit.boost_remaining(now), Some(secs(80)))
+            })
+            .expect(secs(100), closed_duration, [secs(100), secs(100), secs(40)])
+            .run(&mut ekit, &clock);
+    }
+
+    #[test]
+    fn scenario_boost() {
+        type TestEKit = EKitLocal<RecordingPin, RecordingPin, RecordingPin>;
+
+        let mut ekit = EKitLocal::new(
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+        );
+        fn run(ekit: &mut TestEKit, now: Instant) {
+            ekit.set_output_temperature(Some(celsius(40.0)));
+            ekit.run_boost(now);
         }
 
-        fn is_set_low(&self) -> Result<bool, Self::Error> {
-            Ok(!self.0)
+        Scenario::new()
+            .at(secs(0), |ekit: &mut TestEKit, _| {
+                ekit.request_user_run_mode(EKitUserRunMode::Half)
+            })
+            // too long, rejected without changing the run mode
+            .at(secs(5), |ekit, now| {
+                assert!(!ekit.start_boost(MAX_BOOST_DURATION + secs(1), now));
+                assert!(!ekit.start_boost(Duration::MAX, now));
+            })
+            .expect(secs(5), |ekit| ekit.run_mode, EKitSystemRunMode::Half)
+            .expect(secs(5), |ekit| ekit.boost_until, None)
+            .at(secs(10), |ekit, now| {
+                assert!(ekit.start_boost(secs(60), now));
+            })
+            .expect(secs(10), |ekit| ekit.run_mode, EKitSystemRunMode::Full)
+            // requests during the boost are applied once it ends
+            .at(secs(20), |ekit, _| {
+                ekit.request_user_run_mode(EKitUserRunMode::Cool)
+            })
+            .every(secs(10), secs(20), secs(70), run)
+            .expect(secs(60), |ekit| ekit.run_mode, EKitSystemRunMode::Full)
+            .at(secs(60), |ekit, now| {
+                assert_eq!(ekit.boost_remaining(now), Some(secs(10)))
+            })
+            .expect(secs(70), |ekit| ekit.run_mode, EKitSystemRunMode::Cool)
+            .expect(secs(70), |ekit| ekit.boost_until, None)
+            // a zero duration ends the boost early
+            .at(secs(80), |ekit, now| {
+                assert!(ekit.start_boost(secs(60), now));
+                assert!(ekit.start_boost(secs(0), now));
+            })
+            .expect(secs(80), |ekit| ekit.run_mode, EKitSystemRunMode::Cool)
+            // overtemperature protection interrupts the boost
+            .at(secs(90), |ekit, now| {
+                assert!(ekit.start_boost(secs(60), now));
+                ekit.set_output_temperature(Some(celsius(95.0)));
+                ekit.run_boost(now);
+            })
+            .expect(secs(90), |ekit| ekit.boost_until, None)
+            .at(secs(100), |ekit, now| {
+                ekit.set_output_temperature(Some(celsius(45.0)));
+                ekit.run_boost(now);
+            })
+            .expect(secs(100), |ekit| ekit.run_mode, EKitSystemRunMode::Off)
+            .run(&mut ekit, &FakeClock::new());
+    }
+
+    #[test]
+    fn scenario_runs_on_setpoint() {
+        type TestEKit = EKitLocal<RecordingPin, RecordingPin, RecordingPin>;
+
+        let mut ekit = EKitLocal::new(
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+        );
+        let events = ekit.events.subscribe();
+        fn run(ekit: &mut TestEKit, now: Instant) {
+            ekit.set_output_temperature(Some(celsius(40.0)));
+            ekit.run_thermostat(now);
         }
+
+        Scenario::new()
+            .at(secs(0), |ekit: &mut TestEKit, now| {
+                // invalid or out of bounds, not run on
+                assert!(ekit.set_setpoint(celsius(f32::NAN)).is_err());
+                assert!(ekit.set_setpoint(celsius(80.0)).is_err());
+                assert_eq!(ekit.setpoint(), None);
+                ekit.set_setpoint(celsius(21.0)).unwrap();
+                ekit.report_ambient_temperature(celsius(18.0), now);
+            })
+            .every(secs(10), secs(0), secs(200), run)
+            .expect(secs(0), |ekit| ekit.run_mode, EKitSystemRunMode::Full)
+            .at(secs(60), |ekit, now| {
+                ekit.report_ambient_temperature(celsius(20.0), now)
+            })
+            .expect(secs(70), |ekit| ekit.run_mode, EKitSystemRunMode::Half)
+            // implausible readings are ignored, rather than heated on
+            .at(secs(120), |ekit, now| {
+                ekit.report_ambient_temperature(celsius(f32::NAN), now);
+                ekit.report_ambient_temperature(celsius(f32::NEG_INFINITY), now);
+                ekit.report_ambient_temperature(celsius(-1e30), now);
+            })
+            .expect(secs(130), |ekit| ekit.run_mode, EKitSystemRunMode::Half)
+            // turns off once the ambient temperature is outdated
+            .expect(secs(180), |ekit| ekit.run_mode, EKitSystemRunMode::Half)
+            .expect(secs(190), |ekit| ekit.run_mode, EKitSystemRunMode::Cooldown)
+            .expect(secs(200), |ekit| ekit.run_mode, EKitSystemRunMode::Off)
+            // a reading measured a while ago isn't used
+            .at(secs(205), |ekit, now| {
+                ekit.report_ambient_temperature(celsius(15.0), now - secs(150));
+                run(ekit, now);
+            })
+            .expect(secs(205), |ekit| ekit.run_mode, EKitSystemRunMode::Off)
+            .at(secs(210), |ekit, now| {
+                ekit.report_ambient_temperature(celsius(15.0), now - secs(30));
+                run(ekit, now);
+            })
+            .expect(secs(210), |ekit| ekit.run_mode, EKitSystemRunMode::Full)
+            // the thermostat repeating its run mode doesn't take over
+            .at(secs(215), |ekit, now| {
+                ekit.request_periodic_run_mode(EKitUserRunMode::Off);
+                run(ekit, now);
+            })
+            .expect(secs(215), |ekit| ekit.run_mode, EKitSystemRunMode::Full)
+            .expect(secs(215), |ekit| ekit.setpoint(), Some(celsius(21.0)))
+            // requesting a run mode takes over
+            .at(secs(220), |ekit, now| {
+                ekit.request_user_run_mode(EKitUserRunMode::Cool);
+                ekit.run_thermostat(now);
+            })
+            .expect(secs(220), |ekit| ekit.run_mode, EKitSystemRunMode::Cool)
+            .expect(secs(220), |ekit| ekit.setpoint(), None)
+            // without a setpoint, the thermostat's requests are applied
+            .at(secs(230), |ekit, now| {
+                ekit.request_periodic_run_mode(EKitUserRunMode::Half);
+                ekit.run_thermostat(now);
+            })
+            .expect(secs(230), |ekit| ekit.run_mode, EKitSystemRunMode::Half)
+            .run(&mut ekit, &FakeClock::new());
+
+        assert!(events.try_iter().any(|e| e.event
+            == EKitEvent::Fault {
+                fault: EKitFault::AmbientTemperatureUnavailable
+            }));
+    }
+
+    #[test]
+    fn publishes_periodic_requests_only_on_change() {
+        let mut ekit = EKitLocal::new(
+            Fan::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+            HeatingCoil::new(Relay::connected_to(RecordingPin::new(false))),
+        );
+        ekit.set_output_temperature(Some(celsius(20.0)));
+        let events = ekit.events.subscribe();
+        let requests = |events: &Receiver<Sequenced<EKitEvent>>| {
+            events
+                .try_iter()
+                .filter(|e| matches!(e.event, EKitEvent::RunModeRequested { .. }))
+                .count()
+        };
+
+        ekit.request_periodic_run_mode(EKitUserRunMode::Half);
+        ekit.request_periodic_run_mode(EKitUserRunMode::Half);
+        ekit.request_periodic_run_mode(EKitUserRunMode::Half);
+        assert_eq!(requests(&events), 1);
+        assert_eq!(ekit.run_mode, EKitSystemRunMode::Half);
+
+        ekit.request_periodic_run_mode(EKitUserRunMode::Full);
+        assert_eq!(requests(&events), 1);
+        assert_eq!(ekit.run_mode, EKitSystemRunMode::Full);
+
+        // user requests are always published
+        ekit.request_user_run_mode(EKitUserRunMode::Full);
+        assert_eq!(requests(&events), 1);
     }
 }
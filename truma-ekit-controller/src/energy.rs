@@ -0,0 +1,276 @@
+use std::time::{Duration, Instant};
+use truma_ekit_core::{
+    ekit::{EKitRelay, EKitSystemRunMode},
+    peripherals::relay::RelayStats,
+    storage::Storage,
+    throttle::Throttle,
+    types::{Energy, Power, UnitPower},
+    util::{watt_hours, watts},
+};
+
+/// The power drawn by the fan.
+const FAN_POWER: Power = watts(25.0);
+/// The power drawn by a single heating coil.
+const COIL_POWER: Power = watts(900.0);
+/// The length of a day, as measured since the controller started, while the wall clock has not been set.
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+/// The interval at which the energy used since the last reset is persisted.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// The storage key of the energy used since the last reset (in Wh).
+const TOTAL_KEY: &str = "energy.total";
+
+/// Returns the power drawn by the peripheral driven by the relay.
+pub fn relay_power(relay: EKitRelay) -> Power {
+    match relay {
+        EKitRelay::Fan => FAN_POWER,
+        EKitRelay::Coil1 | EKitRelay::Coil2 => COIL_POWER,
+    }
+}
+
+/// Returns the power drawn by the e-kit in the given run mode.
+pub fn run_mode_power(run_mode: EKitSystemRunMode) -> Power {
+    match run_mode {
+        EKitSystemRunMode::Off => watts(0.0),
+        EKitSystemRunMode::Cooldown | EKitSystemRunMode::Cool => FAN_POWER,
+        EKitSystemRunMode::Half => FAN_POWER + COIL_POWER,
+        EKitSystemRunMode::Full => FAN_POWER + COIL_POWER + COIL_POWER,
+    }
+}
+
+/// Estimates the energy used by the e-kit from the on-time of its relays.
+pub struct EnergyMeter {
+    storage: Storage,
+    throttle: Throttle,
+    counter: EnergyCounter,
+}
+
+impl EnergyMeter {
+    pub fn new(storage: Storage, now: Instant) -> Self {
+        let total_wh = match storage.get(TOTAL_KEY) {
+            Ok(total_wh) => total_wh.unwrap_or_default(),
+            Err(e) => {
+                log::error!("failed to restore energy usage ({})", e);
+                0.0
+            }
+        };
+
+        EnergyMeter {
+            storage,
+            throttle: Throttle::one_run_per(PERSIST_INTERVAL),
+            counter: EnergyCounter::new(total_wh, now),
+        }
+    }
+
+    /// Returns the estimated energy used today.
+    pub fn today(&self) -> Energy {
+        self.counter.today()
+    }
+
+    /// Returns the estimated energy used since the last reset.
+    pub fn total(&self) -> Energy {
+        self.counter.total()
+    }
+
+    /// Update the energy usage from the accumulated relay usage at `now`, on the `local_day` (in days since the Unix
+    /// epoch) if the wall clock has been set.
+    pub fn update(
+        &mut self,
+        stats: [(EKitRelay, RelayStats); 3],
+        now: Instant,
+        local_day: Option<i64>,
+    ) {
+        self.counter.update(stats, now, local_day);
+
+        let storage = &mut self.storage;
+        let total_wh = self.counter.total_wh;
+        self.throttle.throttle(|| Self::persist(storage, total_wh));
+    }
+
+    /// Reset the energy used since the last reset.
+    pub fn reset(&mut self) {
+        log::info!("resetting energy usage");
+        self.counter.total_wh = 0.0;
+        Self::persist(&mut self.storage, self.counter.total_wh);
+    }
+
+    fn persist(storage: &mut Storage, total_wh: f64) {
+        if let Err(e) = storage.set(TOTAL_KEY, &total_wh) {
+            log::error!("failed to persist energy usage ({})", e);
+        }
+    }
+}
+
+/// Accumulates the energy used per day and since the last reset.
+///
+/// A day ends at local midnight once the wall clock has been set, and 24 hours after it started before that.
+/// Accumulated in Wh as `f64`, as the small amounts used between updates would be lost once an `f32` total grows large.
+struct EnergyCounter {
+    last_stats: Option<[(EKitRelay, RelayStats); 3]>,
+    day_started: Instant,
+    local_day: Option<i64>,
+    today_wh: f64,
+    total_wh: f64,
+}
+
+impl EnergyCounter {
+    fn new(total_wh: f64, now: Instant) -> Self {
+        EnergyCounter {
+            last_stats: None,
+            day_started: now,
+            local_day: None,
+            today_wh: 0.0,
+            total_wh,
+        }
+    }
+
+    fn today(&self) -> Energy {
+        watt_hours(self.today_wh as f32)
+    }
+
+    fn total(&self) -> Energy {
+        watt_hours(self.total_wh as f32)
+    }
+
+    fn update(
+        &mut self,
+        stats: [(EKitRelay, RelayStats); 3],
+        now: Instant,
+        local_day: Option<i64>,
+    ) {
+        match local_day {
+            // the day in progress when the clock is set is counted until midnight
+            Some(local_day) => {
+                if matches!(self.local_day, Some(day) if day != local_day) {
+                    self.today_wh = 0.0;
+                }
+                self.local_day = Some(local_day);
+            }
+            None => {
+                while now.saturating_duration_since(self.day_started) >= DAY {
+                    self.day_started += DAY;
+                    self.today_wh = 0.0;
+                }
+            }
+        }
+
+        // the first stats only serve as a reference, as they include the usage before the controller started
+        if let Some(last_stats) = self.last_stats {
+            for ((relay, stats), (_, last_stats)) in stats.iter().zip(last_stats) {
+                let on_time = stats
+                    .closed_duration
+                    .saturating_sub(last_stats.closed_duration);
+                let power_w = relay_power(*relay).converted_to(UnitPower::watts()).value;
+                let used_wh = f64::from(power_w) * on_time.as_secs_f64() / 3600.0;
+                self.today_wh += used_wh;
+                self.total_wh += used_wh;
+            }
+        }
+        self.last_stats = Some(stats);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use truma_ekit_core::types::UnitEnergy;
+
+    fn stats(fan: u64, coil1: u64, coil2: u64) -> [(EKitRelay, RelayStats); 3] {
+        let stats = |secs| RelayStats {
+            cycles: 0,
+            closed_duration: Duration::from_secs(secs),
+        };
+        [
+            (EKitRelay::Fan, stats(fan)),
+            (EKitRelay::Coil1, stats(coil1)),
+            (EKitRelay::Coil2, stats(coil2)),
+        ]
+    }
+
+    fn wh(energy: Energy) -> f32 {
+        energy.converted_to(UnitEnergy::watt_hours()).value
+    }
+
+    #[test]
+    fn run_mode_power_adds_up_relays() {
+        assert_eq!(run_mode_power(EKitSystemRunMode::Off), watts(0.0));
+        assert_eq!(run_mode_power(EKitSystemRunMode::Cooldown), watts(25.0));
+        assert_eq!(run_mode_power(EKitSystemRunMode::Half), watts(925.0));
+        assert_eq!(run_mode_power(EKitSystemRunMode::Full), watts(1825.0));
+    }
+
+    #[test]
+    fn counts_energy_from_relay_on_time() {
+        let now = Instant::now();
+        let mut counter = EnergyCounter::new(100.0, now);
+
+        // usage from before the start is not counted
+        counter.update(stats(3600, 3600, 0), now, None);
+        assert_approx_eq!(wh(counter.today()), 0.0);
+
+        // half an hour in half mode
+        counter.update(stats(5400, 5400, 0), now + Duration::from_secs(1800), None);
+        assert_approx_eq!(wh(counter.today()), 462.5);
+        assert_approx_eq!(wh(counter.total()), 562.5);
+    }
+
+    #[test]
+    fn resets_today_every_day() {
+        let now = Instant::now();
+        let mut counter = EnergyCounter::new(0.0, now);
+
+        counter.update(stats(0, 0, 0), now, None);
+        counter.update(
+            stats(3600, 3600, 3600),
+            now + Duration::from_secs(3600),
+            None,
+        );
+        assert_approx_eq!(wh(counter.today()), 1825.0);
+
+        counter.update(stats(3600, 3600, 3600), now + DAY, None);
+        assert_approx_eq!(wh(counter.today()), 0.0);
+        assert_approx_eq!(wh(counter.total()), 1825.0);
+    }
+
+    #[test]
+    fn resets_today_at_local_midnight_once_clock_is_set() {
+        let now = Instant::now();
+        let mut counter = EnergyCounter::new(0.0, now);
+
+        counter.update(stats(0, 0, 0), now, None);
+        counter.update(stats(3600, 0, 0), now + Duration::from_secs(3600), None);
+
+        // the usage before the clock was set counts towards the day it was set on
+        counter.update(
+            stats(7200, 0, 0),
+            now + Duration::from_secs(7200),
+            Some(100),
+        );
+        assert_approx_eq!(wh(counter.today()), 50.0);
+
+        // no longer reset 24 hours after the start
+        counter.update(stats(7200, 0, 0), now + DAY, Some(100));
+        assert_approx_eq!(wh(counter.today()), 50.0);
+
+        counter.update(
+            stats(7200, 0, 0),
+            now + DAY + Duration::from_secs(3600),
+            Some(101),
+        );
+        assert_approx_eq!(wh(counter.today()), 0.0);
+        assert_approx_eq!(wh(counter.total()), 50.0);
+    }
+
+    #[test]
+    fn keeps_small_amounts_with_large_total() {
+        let now = Instant::now();
+        let mut counter = EnergyCounter::new(1_000_000.0, now);
+
+        // the fan runs for an hour, updated every second
+        for secs in 0..=3600 {
+            counter.update(stats(secs, 0, 0), now + Duration::from_secs(secs), None);
+        }
+        assert_approx_eq!(counter.total_wh - 1_000_000.0, 25.0, 1e-6);
+        assert_approx_eq!(counter.today_wh, 25.0, 1e-6);
+    }
+}
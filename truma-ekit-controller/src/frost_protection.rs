@@ -0,0 +1,191 @@
+use std::time::{Duration, Instant};
+use truma_ekit_core::{
+    ekit::EKitSystemRunMode,
+    types::{Temperature, TemperatureDelta},
+    util::{celsius, celsius_delta},
+};
+
+/// Heating will be started if the ambient temperature is less than or equal to this setpoint.
+const FROST_SETPOINT: Temperature = celsius(5.0);
+/// Heating will be stopped once the ambient temperature is this much above the setpoint.
+const FROST_HYSTERESIS: TemperatureDelta = celsius_delta(2.0);
+/// The run mode used to heat.
+const FROST_RUN_MODE: EKitSystemRunMode = EKitSystemRunMode::Half;
+/// Once started, heating runs for at least this long, to avoid switching the relays on and off rapidly.
+const MIN_RUNTIME: Duration = Duration::from_secs(10 * 60);
+/// After overtemperature protection interrupted heating, heating isn't started again for this long.
+const OVERTEMPERATURE_LOCKOUT: Duration = Duration::from_secs(10 * 60);
+/// The maximum age of the ambient temperature reported by the thermostat.
+const MAX_AMBIENT_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// Keeps the ambient temperature above the frost protection setpoint, without a thermostat.
+#[derive(Debug)]
+pub struct FrostProtection {
+    setpoint: Temperature,
+    hysteresis: TemperatureDelta,
+    min_runtime: Duration,
+    heating_since: Option<Instant>,
+    locked_out_until: Option<Instant>,
+}
+
+impl Default for FrostProtection {
+    fn default() -> Self {
+        FrostProtection::new()
+    }
+}
+
+impl FrostProtection {
+    pub fn new() -> Self {
+        FrostProtection {
+            setpoint: FROST_SETPOINT,
+            hysteresis: FROST_HYSTERESIS,
+            min_runtime: MIN_RUNTIME,
+            heating_since: None,
+            locked_out_until: None,
+        }
+    }
+
+    /// Returns `true` if frost protection is currently heating.
+    pub fn is_heating(&self) -> bool {
+        self.heating_since.is_some()
+    }
+
+    /// Returns the ambient temperature frost protection is based on, if known.
+    ///
+    /// The ambient temperature reported by the thermostat is used as long as it's recent enough. Otherwise, the
+    /// output temperature is used, which is only representative of the ambient temperature while the e-kit is off.
+    pub fn ambient_temperature(
+        reported_ambient: Option<(Instant, Temperature)>,
+        output_temperature_while_off: Option<Temperature>,
+        now: Instant,
+    ) -> Option<Temperature> {
+        reported_ambient
+            .filter(|(reported_at, _)| {
+                now.saturating_duration_since(*reported_at) <= MAX_AMBIENT_AGE
+            })
+            .map(|(_, temperature)| temperature)
+            .or(output_temperature_while_off)
+    }
+
+    /// Stop heating because overtemperature protection kicked in, and don't start again for a while.
+    pub fn lock_out(&mut self, now: Instant) {
+        log::warn!("overtemperature protection interrupted frost protection");
+        self.heating_since = None;
+        self.locked_out_until = Some(now + OVERTEMPERATURE_LOCKOUT);
+    }
+
+    /// Signals that the ambient temperature (if known) has been updated at `now`.
+    ///
+    /// Returns the e-kit system run mode requested by frost protection.
+    pub fn update(&mut self, ambient: Option<Temperature>, now: Instant) -> EKitSystemRunMode {
+        match self.heating_since {
+            Some(heating_since) => {
+                // keep heating until warm enough, or until the ambient temperature is unknown, but at least for the minimum runtime
+                let min_runtime_elapsed =
+                    now.saturating_duration_since(heating_since) >= self.min_runtime;
+                let warm = ambient
+                    .is_none_or(|temperature| temperature >= self.setpoint + self.hysteresis);
+                if min_runtime_elapsed && warm {
+                    log::info!("frost protection stops heating");
+                    self.heating_since = None;
+                }
+            }
+            None => {
+                let locked_out = self.locked_out_until.is_some_and(|until| now < until);
+                let cold = ambient.is_some_and(|temperature| temperature <= self.setpoint);
+                if cold && !locked_out {
+                    log::info!("frost protection starts heating");
+                    self.heating_since = Some(now);
+                }
+            }
+        }
+
+        if self.is_heating() {
+            FROST_RUN_MODE
+        } else {
+            EKitSystemRunMode::Off
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heats_with_hysteresis() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut sub = FrostProtection::new();
+
+        assert_eq!(
+            sub.update(Some(celsius(5.1)), at(0)),
+            EKitSystemRunMode::Off
+        );
+        assert_eq!(sub.update(Some(celsius(5.0)), at(1)), FROST_RUN_MODE);
+        assert_eq!(sub.update(Some(celsius(6.9)), at(700)), FROST_RUN_MODE);
+        assert_eq!(
+            sub.update(Some(celsius(7.0)), at(701)),
+            EKitSystemRunMode::Off
+        );
+        assert_eq!(
+            sub.update(Some(celsius(5.1)), at(702)),
+            EKitSystemRunMode::Off
+        );
+    }
+
+    #[test]
+    fn heats_for_minimum_runtime() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut sub = FrostProtection::new();
+
+        assert_eq!(sub.update(Some(celsius(4.0)), at(0)), FROST_RUN_MODE);
+        assert_eq!(sub.update(Some(celsius(10.0)), at(60)), FROST_RUN_MODE);
+        assert_eq!(sub.update(None, at(599)), FROST_RUN_MODE);
+        // stops once the minimum runtime has elapsed, also when the ambient temperature is unknown
+        assert_eq!(sub.update(None, at(600)), EKitSystemRunMode::Off);
+        // doesn't start without knowing the ambient temperature
+        assert_eq!(sub.update(None, at(601)), EKitSystemRunMode::Off);
+    }
+
+    #[test]
+    fn locks_out_after_overtemperature() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut sub = FrostProtection::new();
+
+        assert_eq!(sub.update(Some(celsius(0.0)), at(0)), FROST_RUN_MODE);
+        sub.lock_out(at(60));
+        assert!(!sub.is_heating());
+        assert_eq!(
+            sub.update(Some(celsius(0.0)), at(659)),
+            EKitSystemRunMode::Off
+        );
+        assert_eq!(sub.update(Some(celsius(0.0)), at(660)), FROST_RUN_MODE);
+    }
+
+    #[test]
+    fn prefers_recent_reported_ambient() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let reported = Some((at(0), celsius(3.0)));
+
+        assert_eq!(
+            FrostProtection::ambient_temperature(reported, Some(celsius(8.0)), at(600)),
+            Some(celsius(3.0))
+        );
+        assert_eq!(
+            FrostProtection::ambient_temperature(reported, Some(celsius(8.0)), at(601)),
+            Some(celsius(8.0))
+        );
+        assert_eq!(
+            FrostProtection::ambient_temperature(reported, None, at(601)),
+            None
+        );
+        assert_eq!(
+            FrostProtection::ambient_temperature(None, None, at(0)),
+            None
+        );
+    }
+}
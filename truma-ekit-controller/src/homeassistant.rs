@@ -0,0 +1,360 @@
+use crate::ekit::closed_relays;
+use serde_json::json;
+use truma_ekit_core::{
+    ekit::{EKitEvent, EKitFault, EKitRelay, EKitSystemRunMode, EKitUserRunMode},
+    types::{Temperature, UnitTemperature},
+};
+
+/// The prefix Home Assistant listens to for MQTT discovery messages.
+const DISCOVERY_PREFIX: &str = "homeassistant";
+/// The identifier of the e-kit device in Home Assistant.
+const DEVICE_ID: &str = "truma_ekit";
+
+/// The topic on which the availability of the e-kit is published.
+pub const AVAILABILITY_TOPIC: &str = "truma-ekit/status";
+/// The topic on which user run mode requests are received.
+pub const RUN_MODE_COMMAND_TOPIC: &str = "truma-ekit/run-mode/set";
+/// The topic on which the system run mode is published.
+const RUN_MODE_STATE_TOPIC: &str = "truma-ekit/run-mode";
+/// The topic on which the output temperature is published (in °C).
+const OUTPUT_TEMPERATURE_TOPIC: &str = "truma-ekit/output-temperature";
+/// The topic on which faults are published.
+const FAULT_TOPIC: &str = "truma-ekit/fault";
+
+const PAYLOAD_ONLINE: &str = "online";
+pub const PAYLOAD_OFFLINE: &str = "offline";
+
+#[derive(PartialEq, Debug)]
+pub struct Message {
+    pub topic: String,
+    pub payload: String,
+    pub retain: bool,
+}
+
+impl Message {
+    fn retained(topic: impl Into<String>, payload: impl Into<String>) -> Self {
+        Message {
+            topic: topic.into(),
+            payload: payload.into(),
+            retain: true,
+        }
+    }
+
+    fn transient(topic: impl Into<String>, payload: impl Into<String>) -> Self {
+        Message {
+            topic: topic.into(),
+            payload: payload.into(),
+            retain: false,
+        }
+    }
+}
+
+/// Returns the Home Assistant discovery messages for all e-kit entities.
+pub fn discovery_messages() -> Vec<Message> {
+    let device = json!({
+        "identifiers": [DEVICE_ID],
+        "name": "Truma E-Kit",
+        "manufacturer": "Truma",
+        "model": "E-Kit",
+    });
+
+    let mut messages = vec![
+        discovery_message(
+            "select",
+            "run_mode",
+            json!({
+                "name": "Run mode",
+                "command_topic": RUN_MODE_COMMAND_TOPIC,
+                "options": EKitUserRunMode::all().map(user_run_mode_name),
+                "optimistic": true,
+                "icon": "mdi:radiator",
+            }),
+            &device,
+        ),
+        discovery_message(
+            "sensor",
+            "system_run_mode",
+            json!({
+                "name": "System run mode",
+                "state_topic": RUN_MODE_STATE_TOPIC,
+                "icon": "mdi:state-machine",
+            }),
+            &device,
+        ),
+        discovery_message(
+            "sensor",
+            "output_temperature",
+            json!({
+                "name": "Output temperature",
+                "state_topic": OUTPUT_TEMPERATURE_TOPIC,
+                "device_class": "temperature",
+                "state_class": "measurement",
+                "unit_of_measurement": "°C",
+            }),
+            &device,
+        ),
+        discovery_message(
+            "sensor",
+            "fault",
+            json!({
+                "name": "Last fault",
+                "state_topic": FAULT_TOPIC,
+                "icon": "mdi:alert",
+            }),
+            &device,
+        ),
+    ];
+
+    messages.extend(EKitRelay::all().map(|relay| {
+        discovery_message(
+            "binary_sensor",
+            relay.name(),
+            json!({
+                "name": relay_display_name(relay),
+                "state_topic": relay_topic(relay),
+                "device_class": "power",
+            }),
+            &device,
+        )
+    }));
+
+    messages
+}
+
+/// Returns the message announcing that the e-kit is available.
+pub fn availability_message() -> Message {
+    Message::retained(AVAILABILITY_TOPIC, PAYLOAD_ONLINE)
+}
+
+/// Returns the messages to publish the state change described by `event`.
+pub fn state_messages(event: &EKitEvent) -> Vec<Message> {
+    match *event {
+        EKitEvent::RunModeChanged { to, .. } => {
+            vec![Message::retained(
+                RUN_MODE_STATE_TOPIC,
+                system_run_mode_name(to),
+            )]
+        }
+        EKitEvent::RelayChanged { relay, closed } => {
+            vec![Message::retained(
+                relay_topic(relay),
+                if closed { "ON" } else { "OFF" },
+            )]
+        }
+        EKitEvent::OutputTemperature {
+            temperature: Some(temperature),
+        } => {
+            let celsius = temperature.converted_to(UnitTemperature::celsius());
+            vec![Message::retained(
+                OUTPUT_TEMPERATURE_TOPIC,
+                format!("{:.2}", celsius.value),
+            )]
+        }
+        EKitEvent::OutputTemperature { temperature: None }
+        | EKitEvent::RunModeRequested { .. }
+        | EKitEvent::OutputTemperatureSensorFailed { .. }
+        | EKitEvent::RunModeLimited { .. } => vec![],
+        EKitEvent::Fault { fault } => {
+            vec![Message::transient(FAULT_TOPIC, fault_description(fault))]
+        }
+    }
+}
+
+/// Returns the messages to publish the current state, e.g. after (re)connecting to the broker, as the state is
+/// otherwise only published when it changes.
+pub fn snapshot_messages(
+    run_mode: EKitSystemRunMode,
+    output_temperature: Option<Temperature>,
+) -> Vec<Message> {
+    let mut messages = state_messages(&EKitEvent::RunModeChanged {
+        from: run_mode,
+        to: run_mode,
+    });
+    for (relay, closed) in closed_relays(run_mode) {
+        messages.extend(state_messages(&EKitEvent::RelayChanged { relay, closed }));
+    }
+    messages.extend(state_messages(&EKitEvent::OutputTemperature {
+        temperature: output_temperature,
+    }));
+    messages
+}
+
+/// Parses a run mode command payload.
+pub fn parse_run_mode_command(payload: &[u8]) -> Option<EKitUserRunMode> {
+    let payload = std::str::from_utf8(payload).ok()?.trim();
+    EKitUserRunMode::all()
+        .into_iter()
+        .find(|run_mode| user_run_mode_name(*run_mode).eq_ignore_ascii_case(payload))
+}
+
+fn discovery_message(
+    component: &str,
+    object_id: &str,
+    mut config: serde_json::Value,
+    device: &serde_json::Value,
+) -> Message {
+    config["unique_id"] = format!("{}_{}", DEVICE_ID, object_id).into();
+    config["availability_topic"] = AVAILABILITY_TOPIC.into();
+    config["device"] = device.clone();
+    Message::retained(
+        format!(
+            "{}/{}/{}/{}/config",
+            DISCOVERY_PREFIX, component, DEVICE_ID, object_id
+        ),
+        config.to_string(),
+    )
+}
+
+fn relay_topic(relay: EKitRelay) -> String {
+    format!("truma-ekit/relay/{}", relay.name())
+}
+
+fn relay_display_name(relay: EKitRelay) -> &'static str {
+    match relay {
+        EKitRelay::Fan => "Fan",
+        EKitRelay::Coil1 => "Heating coil #1",
+        EKitRelay::Coil2 => "Heating coil #2",
+    }
+}
+
+fn user_run_mode_name(run_mode: EKitUserRunMode) -> &'static str {
+    match run_mode {
+        EKitUserRunMode::Off => "Off",
+        EKitUserRunMode::FrostProtection => "Frost protection",
+        EKitUserRunMode::Cool => "Cool",
+        EKitUserRunMode::Half => "Half",
+        EKitUserRunMode::Full => "Full",
+    }
+}
+
+fn system_run_mode_name(run_mode: EKitSystemRunMode) -> &'static str {
+    match run_mode {
+        EKitSystemRunMode::Off => "Off",
+        EKitSystemRunMode::Cooldown => "Cooldown",
+        EKitSystemRunMode::Cool => "Cool",
+        EKitSystemRunMode::Half => "Half",
+        EKitSystemRunMode::Full => "Full",
+    }
+}
+
+fn fault_description(fault: EKitFault) -> String {
+    match fault {
+        EKitFault::OutputTemperatureUnavailable => "Output temperature unavailable".into(),
+        EKitFault::Overtemperature => "Overtemperature".into(),
+        EKitFault::OutputTemperatureSensorsDisagree => "Output temperature sensors disagree".into(),
+        EKitFault::AmbientTemperatureUnavailable => "Ambient temperature unavailable".into(),
+        EKitFault::RelayMaintenanceDue { relay } => {
+            format!("{} relay maintenance due", relay_display_name(relay))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use truma_ekit_core::util::celsius;
+
+    #[test]
+    fn discovery_messages_are_retained_and_reference_the_device() {
+        let messages = discovery_messages();
+        assert_eq!(messages.len(), 7);
+
+        for message in messages {
+            assert!(message.retain);
+            assert!(message.topic.starts_with("homeassistant/"));
+            assert!(message.topic.ends_with("/config"));
+
+            let config: serde_json::Value = serde_json::from_str(&message.payload).unwrap();
+            assert_eq!(config["device"]["identifiers"][0], DEVICE_ID);
+            assert_eq!(config["availability_topic"], AVAILABILITY_TOPIC);
+        }
+    }
+
+    #[test]
+    fn run_mode_select_discovery() {
+        let select = discovery_messages()
+            .into_iter()
+            .find(|message| message.topic == "homeassistant/select/truma_ekit/run_mode/config")
+            .unwrap();
+        let config: serde_json::Value = serde_json::from_str(&select.payload).unwrap();
+        assert_eq!(config["command_topic"], RUN_MODE_COMMAND_TOPIC);
+        assert_eq!(
+            config["options"],
+            json!(["Off", "Frost protection", "Cool", "Half", "Full"])
+        );
+    }
+
+    #[test]
+    fn state_messages_for_events() {
+        assert_eq!(
+            state_messages(&EKitEvent::RunModeChanged {
+                from: EKitSystemRunMode::Full,
+                to: EKitSystemRunMode::Cooldown,
+            }),
+            vec![Message::retained("truma-ekit/run-mode", "Cooldown")]
+        );
+        assert_eq!(
+            state_messages(&EKitEvent::RelayChanged {
+                relay: EKitRelay::Coil2,
+                closed: true,
+            }),
+            vec![Message::retained("truma-ekit/relay/coil2", "ON")]
+        );
+        assert_eq!(
+            state_messages(&EKitEvent::OutputTemperature {
+                temperature: Some(celsius(42.5)),
+            }),
+            vec![Message::retained("truma-ekit/output-temperature", "42.50")]
+        );
+        assert_eq!(
+            state_messages(&EKitEvent::OutputTemperature { temperature: None }),
+            vec![]
+        );
+        assert_eq!(
+            state_messages(&EKitEvent::Fault {
+                fault: EKitFault::Overtemperature,
+            }),
+            vec![Message::transient("truma-ekit/fault", "Overtemperature")]
+        );
+    }
+
+    #[test]
+    fn snapshot_messages_publish_the_current_state() {
+        assert_eq!(
+            snapshot_messages(EKitSystemRunMode::Half, Some(celsius(42.5))),
+            vec![
+                Message::retained("truma-ekit/run-mode", "Half"),
+                Message::retained("truma-ekit/relay/fan", "ON"),
+                Message::retained("truma-ekit/relay/coil1", "ON"),
+                Message::retained("truma-ekit/relay/coil2", "OFF"),
+                Message::retained("truma-ekit/output-temperature", "42.50"),
+            ]
+        );
+        assert_eq!(
+            snapshot_messages(EKitSystemRunMode::Off, None),
+            vec![
+                Message::retained("truma-ekit/run-mode", "Off"),
+                Message::retained("truma-ekit/relay/fan", "OFF"),
+                Message::retained("truma-ekit/relay/coil1", "OFF"),
+                Message::retained("truma-ekit/relay/coil2", "OFF"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_run_mode_commands() {
+        assert_eq!(parse_run_mode_command(b"Off"), Some(EKitUserRunMode::Off));
+        assert_eq!(parse_run_mode_command(b"cool"), Some(EKitUserRunMode::Cool));
+        assert_eq!(
+            parse_run_mode_command(b" Half\n"),
+            Some(EKitUserRunMode::Half)
+        );
+        assert_eq!(parse_run_mode_command(b"FULL"), Some(EKitUserRunMode::Full));
+        assert_eq!(
+            parse_run_mode_command(b"frost protection"),
+            Some(EKitUserRunMode::FrostProtection)
+        );
+        assert_eq!(parse_run_mode_command(b"Cooldown"), None);
+        assert_eq!(parse_run_mode_command(&[0xff, 0xfe]), None);
+    }
+}
@@ -1,30 +1,77 @@
 mod ekit;
+mod energy;
+mod frost_protection;
 mod heating;
+mod homeassistant;
+mod metrics;
+mod mqtt;
 mod overtemperature_protection;
 mod peripherals;
+mod power_budget;
 mod server;
+mod settings;
+mod thermostat;
+mod wear;
 mod wifi;
 
 use ekit::{EKit, EKitLocal};
+use energy::EnergyMeter;
 use esp_idf_hal::{
     adc::{AdcConfig, AdcDriver, Atten11dB},
+    delay::FreeRtos,
     gpio::PinDriver,
 };
 use esp_idf_svc::{eventloop::EspSystemEventLoop, nvs::EspDefaultNvsPartition};
 use esp_idf_sys as _;
 use heating::HeatingCoil;
+use metrics::Metrics;
+use mqtt::{EKitMqtt, MQTT_BROKER_URL};
 use peripherals::SystemPeripherals;
+use power_budget::{PowerBudget, CURRENT_SENSOR_AMPERES_PER_VOLT, SITE_CURRENT_LIMIT};
 use server::EKitHttpServer;
-use std::sync::{Arc, Mutex};
+use settings::Settings;
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 use truma_ekit_core::{
     adc::AdcInputPin,
-    peripherals::{fan::Fan, relay::Relay, tmp36::TMP36},
+    ekit::EKitRelay,
+    filter::{Filtered, Median},
+    onewire::OneWirePin,
+    peripherals::{
+        current_sensor::CurrentSensor,
+        ds18b20::DS18B20,
+        fan::Fan,
+        relay::Relay,
+        temperature_sensor::TemperatureSensor,
+        tmp36::{Tmp36Calibration, TMP36},
+    },
     powersaving::Powered,
+    schedule::WallClock,
+    storage::Storage,
     types::Temperature,
 };
+use wear::RelayWear;
 use wifi::WifiAp;
 
 const SLEEP_DURATION: std::time::Duration = std::time::Duration::from_secs(1);
+/// The sensor used to measure the output temperature.
+const OUTPUT_SENSOR: OutputSensor = OutputSensor::Tmp36;
+
+/// Reads the output temperature sensors, applying the calibration to a TMP36 only.
+type OutputTemperatures = Box<dyn FnMut(Tmp36Calibration) -> Vec<Option<Temperature>>>;
+
+/// The kinds of sensor that can measure the output temperature.
+#[allow(dead_code)] // only the configured sensor is constructed
+enum OutputSensor {
+    /// A TMP36 connected to the ADC.
+    Tmp36,
+    /// One or more DS18B20 on a 1-Wire bus, combined using the configured `SensorVoting`.
+    Ds18b20,
+}
 
 esp_idf_sys::esp_app_desc!();
 
@@ -36,35 +83,97 @@ fn main() -> anyhow::Result<()> {
     let sysloop = EspSystemEventLoop::take()?;
     let nvs_default_partition = EspDefaultNvsPartition::take()?;
 
+    let storage = Storage::new(nvs_default_partition.clone(), "ekit")?;
+    let energy_meter = EnergyMeter::new(
+        Storage::new(nvs_default_partition.clone(), "ekit")?,
+        Instant::now(),
+    );
+    let settings = Settings::new(Storage::new(nvs_default_partition.clone(), "ekit")?);
+
     let mut wifi_ap = WifiAp::new(peripherals.modem, sysloop, nvs_default_partition)?;
     wifi_ap.start()?;
 
+    let relay_wear = RelayWear::new(storage);
     let ekit = EKitLocal::new(
-        Fan::new(Relay::connected_to(PinDriver::output(
-            peripherals.fan.power,
-        )?)),
-        HeatingCoil::new(Relay::connected_to(PinDriver::output(
-            peripherals.coil1.power,
-        )?)),
-        HeatingCoil::new(Relay::connected_to(PinDriver::output(
-            peripherals.coil2.power,
-        )?)),
+        Fan::new(
+            Relay::connected_to(PinDriver::output(peripherals.fan.power)?)
+                .with_stats(relay_wear.restore(EKitRelay::Fan)),
+        ),
+        HeatingCoil::new(
+            Relay::connected_to(PinDriver::output(peripherals.coil1.power)?)
+                .with_stats(relay_wear.restore(EKitRelay::Coil1)),
+        ),
+        HeatingCoil::new(
+            Relay::connected_to(PinDriver::output(peripherals.coil2.power)?)
+                .with_stats(relay_wear.restore(EKitRelay::Coil2)),
+        ),
     );
 
-    let tmp36 = TMP36::connected_to(AdcInputPin::pin::<_, _, Atten11dB<_>>(
-        peripherals.thermometer.voltage,
-        AdcDriver::new(
-            peripherals.thermometer.adc,
-            &AdcConfig::new().calibration(true),
-        )?,
-    ));
-    let mut tmp36 = Powered::new(
-        tmp36,
-        PinDriver::output(peripherals.thermometer.vcc).unwrap(),
-    );
-    let mut tmp36 = tmp36.power_down();
+    let adc = Rc::new(RefCell::new(AdcDriver::new(
+        peripherals.thermometer.adc,
+        &AdcConfig::new().calibration(true),
+    )?));
+
+    let output_temperatures: OutputTemperatures = match OUTPUT_SENSOR {
+        OutputSensor::Tmp36 => {
+            let tmp36 = TMP36::connected_to(AdcInputPin::shared_pin::<_, _, Atten11dB<_>>(
+                peripherals.thermometer.voltage,
+                adc.clone(),
+            ));
+            // reject spikes without delaying overtemperature protection by more than a reading
+            let tmp36 = Filtered::new(tmp36)
+                .oversampled(8)
+                .with_filter(Median::new(3));
+            let mut tmp36 = Powered::new(tmp36, PinDriver::output(peripherals.thermometer.vcc)?);
+            tmp36.power_down();
+            Box::new(move |calibration| {
+                vec![tmp36
+                    .measure_temperature()
+                    .ok()
+                    .map(|temperature| calibration.apply(temperature))]
+            })
+        }
+        OutputSensor::Ds18b20 => {
+            let mut ds18b20 = DS18B20::connected_to(
+                OneWirePin::new(PinDriver::input_output_od(peripherals.onewire.data)?),
+                FreeRtos,
+            )?;
+            let sensors = ds18b20.sensors().len();
+            Box::new(move |_| match ds18b20.measure_temperatures() {
+                Ok(readings) => readings
+                    .into_iter()
+                    .map(|(_, reading)| reading.ok())
+                    .collect(),
+                // none of the sensors could be read
+                Err(_) => vec![None; sensors],
+            })
+        }
+    };
+
+    let power_budget = SITE_CURRENT_LIMIT.map(|limit| {
+        let power_budget = PowerBudget::new(limit);
+        match CURRENT_SENSOR_AMPERES_PER_VOLT {
+            Some(amperes_per_volt) => {
+                power_budget.with_current_sensor(CurrentSensor::connected_to(
+                    AdcInputPin::shared_pin::<_, _, Atten11dB<_>>(
+                        peripherals.current_sensor.voltage,
+                        adc.clone(),
+                    ),
+                    amperes_per_volt,
+                ))
+            }
+            None => power_budget,
+        }
+    });
 
-    let mut runner = EKitRunner::new(ekit, move || tmp36.power_up().measure_temperature().ok());
+    let mut runner = EKitRunner::new(
+        ekit,
+        relay_wear,
+        energy_meter,
+        settings,
+        power_budget,
+        output_temperatures,
+    );
     runner.start()?;
 
     loop {
@@ -73,36 +182,104 @@ fn main() -> anyhow::Result<()> {
     }
 }
 
-struct EKitRunner<E: EKit, F> {
+struct EKitRunner<'a, E: EKit, F> {
     ekit: Arc<Mutex<E>>,
+    metrics: Arc<Mutex<Metrics>>,
+    energy_meter: Arc<Mutex<EnergyMeter>>,
+    settings: Arc<Mutex<Settings>>,
+    clock: Arc<Mutex<WallClock>>,
     server: EKitHttpServer<E>,
-    output_temperature: F,
+    mqtt: Option<EKitMqtt<E>>,
+    relay_wear: RelayWear,
+    power_budget: Option<PowerBudget<'a>>,
+    output_temperatures: F,
 }
 
-impl<E, F> EKitRunner<E, F>
+impl<'a, E, F> EKitRunner<'a, E, F>
 where
     E: EKit + 'static,
-    F: FnMut() -> Option<Temperature>,
+    F: FnMut(Tmp36Calibration) -> Vec<Option<Temperature>>,
 {
-    pub fn new(ekit: E, output_temperature: F) -> Self {
+    pub fn new(
+        mut ekit: E,
+        relay_wear: RelayWear,
+        energy_meter: EnergyMeter,
+        settings: Settings,
+        power_budget: Option<PowerBudget<'a>>,
+        output_temperatures: F,
+    ) -> Self {
+        ekit.set_setpoint_bounds(settings.setpoint_bounds());
         let ekit = Arc::new(Mutex::new(ekit));
+        let metrics = Arc::new(Mutex::new(Metrics::new(Instant::now())));
+        let energy_meter = Arc::new(Mutex::new(energy_meter));
+        let settings = Arc::new(Mutex::new(settings));
+        let clock = Arc::new(Mutex::new(WallClock::new()));
         EKitRunner {
             ekit: ekit.clone(),
-            server: EKitHttpServer::new(ekit).unwrap(),
-            output_temperature,
+            metrics: metrics.clone(),
+            energy_meter: energy_meter.clone(),
+            settings: settings.clone(),
+            clock: clock.clone(),
+            server: EKitHttpServer::new(ekit.clone(), metrics, energy_meter, settings, clock)
+                .unwrap(),
+            mqtt: MQTT_BROKER_URL.map(|url| EKitMqtt::new(url, ekit)),
+            relay_wear,
+            power_budget,
+            output_temperatures,
         }
     }
 
     /// Start the e-kit runner.
     pub fn start(&mut self) -> anyhow::Result<()> {
+        // collect metrics from the e-kit events
+        let events = self.ekit.lock().unwrap().events().subscribe();
+        let metrics = self.metrics.clone();
+        std::thread::spawn(move || {
+            for event in events {
+                metrics.lock().unwrap().record(&event.event, Instant::now());
+            }
+        });
+
         self.server.start()?;
+        if let Some(mqtt) = &mut self.mqtt {
+            mqtt.start()?;
+        }
         Ok(())
     }
 
     /// Run the e-kit.
     pub fn run(&mut self) {
-        let output_temperature = (self.output_temperature)();
+        let (calibration, relay_lifetime_cycles, utc_offset_minutes) = {
+            let settings = self.settings.lock().unwrap();
+            (
+                settings.tmp36_calibration(),
+                settings.relay_lifetime_cycles(),
+                settings.utc_offset_minutes(),
+            )
+        };
+        let output_temperatures = (self.output_temperatures)(calibration);
         let mut ekit = self.ekit.lock().unwrap();
-        ekit.set_output_temperature(output_temperature);
+        ekit.set_output_temperatures(&output_temperatures);
+        ekit.run_boost(Instant::now());
+        ekit.run_thermostat(Instant::now());
+        ekit.run_frost_protection(Instant::now());
+
+        if let Some(power_budget) = &mut self.power_budget {
+            let limit = power_budget.update(ekit.run_mode(), Instant::now());
+            ekit.set_run_mode_limit(Some(limit));
+        }
+
+        let relay_stats = ekit.relay_stats();
+        self.relay_wear
+            .update(relay_stats, relay_lifetime_cycles, &ekit.events());
+        let local_day = self
+            .clock
+            .lock()
+            .unwrap()
+            .local_day(Instant::now(), utc_offset_minutes);
+        self.energy_meter
+            .lock()
+            .unwrap()
+            .update(relay_stats, Instant::now(), local_day);
     }
 }
@@ -0,0 +1,388 @@
+use std::{
+    fmt::Write,
+    time::{Duration, Instant},
+};
+use truma_ekit_core::{
+    ekit::{EKitEvent, EKitRelay, EKitSystemRunMode, EKitUserRunMode, PostEKitAmbient},
+    types::{Temperature, UnitTemperature},
+};
+
+/// The content type of the OpenMetrics text format.
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Metrics collected from the e-kit events.
+pub struct Metrics {
+    started: Instant,
+    relays: [RelayMetrics; 3],
+    cooldown_entries: u64,
+    run_mode_requests: [u64; 5],
+    rejected_run_mode_requests: u64,
+    power_limited: u64,
+    /// The failed readings of each output temperature sensor, by the index of its reading.
+    sensor_read_failures: Vec<u64>,
+    output_temperature: Option<Temperature>,
+    ambient: Option<PostEKitAmbient>,
+}
+
+#[derive(Default)]
+struct RelayMetrics {
+    switches: u64,
+    on_time: Duration,
+    closed_since: Option<Instant>,
+}
+
+impl Metrics {
+    pub fn new(started: Instant) -> Self {
+        Metrics {
+            started,
+            relays: Default::default(),
+            cooldown_entries: 0,
+            run_mode_requests: [0; 5],
+            rejected_run_mode_requests: 0,
+            power_limited: 0,
+            sensor_read_failures: Vec::new(),
+            output_temperature: None,
+            ambient: None,
+        }
+    }
+
+    /// Record an e-kit event that happened at `now`.
+    pub fn record(&mut self, event: &EKitEvent, now: Instant) {
+        match *event {
+            EKitEvent::RunModeChanged { to, .. } => {
+                if to == EKitSystemRunMode::Cooldown {
+                    self.cooldown_entries += 1;
+                }
+            }
+            EKitEvent::RunModeRequested { run_mode, accepted } => {
+                self.run_mode_requests[user_run_mode_index(run_mode)] += 1;
+                if !accepted {
+                    self.rejected_run_mode_requests += 1;
+                }
+            }
+            EKitEvent::RelayChanged { relay, closed } => {
+                let relay = &mut self.relays[relay_index(relay)];
+                relay.switches += 1;
+                if closed {
+                    relay.closed_since.get_or_insert(now);
+                } else if let Some(closed_since) = relay.closed_since.take() {
+                    relay.on_time += now.saturating_duration_since(closed_since);
+                }
+            }
+            EKitEvent::OutputTemperature { temperature } => self.output_temperature = temperature,
+            EKitEvent::OutputTemperatureSensorFailed { sensor } => {
+                if self.sensor_read_failures.len() <= sensor {
+                    self.sensor_read_failures.resize(sensor + 1, 0);
+                }
+                self.sensor_read_failures[sensor] += 1;
+            }
+            EKitEvent::RunModeLimited { .. } => self.power_limited += 1,
+            EKitEvent::Fault { .. } => {}
+        }
+    }
+
+    /// Record the ambient conditions reported by the thermostat.
+    pub fn record_ambient(&mut self, ambient: PostEKitAmbient) {
+        self.ambient = Some(ambient);
+    }
+
+    /// Render the metrics at `now` in the OpenMetrics text format.
+    pub fn render(&self, now: Instant) -> String {
+        let mut out = String::new();
+
+        metric_header(
+            &mut out,
+            "ekit_relay_switches",
+            "counter",
+            "Number of times a relay has been switched.",
+        );
+        for relay in EKitRelay::all() {
+            let metrics = &self.relays[relay_index(relay)];
+            writeln!(
+                out,
+                "ekit_relay_switches_total{{relay=\"{}\"}} {}",
+                relay.name(),
+                metrics.switches
+            )
+            .unwrap();
+        }
+
+        metric_header(
+            &mut out,
+            "ekit_relay_on_seconds",
+            "counter",
+            "Cumulative time a relay has been closed.",
+        );
+        for relay in EKitRelay::all() {
+            let metrics = &self.relays[relay_index(relay)];
+            let on_time = metrics.on_time
+                + metrics
+                    .closed_since
+                    .map(|closed_since| now.saturating_duration_since(closed_since))
+                    .unwrap_or_default();
+            writeln!(
+                out,
+                "ekit_relay_on_seconds_total{{relay=\"{}\"}} {:.3}",
+                relay.name(),
+                on_time.as_secs_f64()
+            )
+            .unwrap();
+        }
+
+        metric_header(
+            &mut out,
+            "ekit_cooldown_entries",
+            "counter",
+            "Number of times cooldown has been entered.",
+        );
+        writeln!(out, "ekit_cooldown_entries_total {}", self.cooldown_entries).unwrap();
+
+        metric_header(
+            &mut out,
+            "ekit_run_mode_requests",
+            "counter",
+            "Number of requested user run modes.",
+        );
+        for run_mode in EKitUserRunMode::all() {
+            writeln!(
+                out,
+                "ekit_run_mode_requests_total{{run_mode=\"{:?}\"}} {}",
+                run_mode,
+                self.run_mode_requests[user_run_mode_index(run_mode)]
+            )
+            .unwrap();
+        }
+
+        metric_header(
+            &mut out,
+            "ekit_rejected_run_mode_requests",
+            "counter",
+            "Number of denied user run mode requests.",
+        );
+        writeln!(
+            out,
+            "ekit_rejected_run_mode_requests_total {}",
+            self.rejected_run_mode_requests
+        )
+        .unwrap();
+
+        metric_header(
+            &mut out,
+            "ekit_power_limited_run_modes",
+            "counter",
+            "Number of run modes downgraded to stay within the power budget.",
+        );
+        writeln!(
+            out,
+            "ekit_power_limited_run_modes_total {}",
+            self.power_limited
+        )
+        .unwrap();
+
+        metric_header(
+            &mut out,
+            "ekit_sensor_read_failures",
+            "counter",
+            "Number of failed readings of each output temperature sensor.",
+        );
+        for (sensor, failures) in self.sensor_read_failures.iter().enumerate() {
+            writeln!(
+                out,
+                "ekit_sensor_read_failures_total{{sensor=\"{}\"}} {}",
+                sensor, failures
+            )
+            .unwrap();
+        }
+
+        metric_header(
+            &mut out,
+            "ekit_output_temperature_celsius",
+            "gauge",
+            "The most recently measured output temperature.",
+        );
+        let output_temperature = self
+            .output_temperature
+            .map(|temperature| temperature.converted_to(UnitTemperature::celsius()).value)
+            .unwrap_or(f32::NAN);
+        writeln!(
+            out,
+            "ekit_output_temperature_celsius {:.2}",
+            output_temperature
+        )
+        .unwrap();
+
+        let ambient_temperature = self.ambient.map(|ambient| ambient.temperature_celsius);
+        let ambient_humidity = self.ambient.and_then(|ambient| ambient.humidity_percent);
+        let ambient_pressure = self.ambient.and_then(|ambient| ambient.pressure_hpa);
+        for (name, help, value) in [
+            (
+                "ekit_ambient_temperature_celsius",
+                "The most recently reported ambient temperature.",
+                ambient_temperature,
+            ),
+            (
+                "ekit_ambient_humidity_percent",
+                "The most recently reported relative humidity.",
+                ambient_humidity,
+            ),
+            (
+                "ekit_ambient_pressure_hectopascals",
+                "The most recently reported air pressure.",
+                ambient_pressure,
+            ),
+        ] {
+            metric_header(&mut out, name, "gauge", help);
+            writeln!(out, "{} {:.2}", name, value.unwrap_or(f32::NAN)).unwrap();
+        }
+
+        metric_header(
+            &mut out,
+            "ekit_uptime_seconds",
+            "gauge",
+            "Time since the controller started.",
+        );
+        writeln!(
+            out,
+            "ekit_uptime_seconds {:.3}",
+            now.saturating_duration_since(self.started).as_secs_f64()
+        )
+        .unwrap();
+
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+fn metric_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+}
+
+fn relay_index(relay: EKitRelay) -> usize {
+    match relay {
+        EKitRelay::Fan => 0,
+        EKitRelay::Coil1 => 1,
+        EKitRelay::Coil2 => 2,
+    }
+}
+
+fn user_run_mode_index(run_mode: EKitUserRunMode) -> usize {
+    match run_mode {
+        EKitUserRunMode::Off => 0,
+        EKitUserRunMode::FrostProtection => 1,
+        EKitUserRunMode::Cool => 2,
+        EKitUserRunMode::Half => 3,
+        EKitUserRunMode::Full => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use truma_ekit_core::util::celsius;
+
+    #[test]
+    fn initial_metrics() {
+        let started = Instant::now();
+        let rendered = Metrics::new(started).render(started + Duration::from_secs(2));
+
+        assert!(rendered.contains("ekit_relay_switches_total{relay=\"fan\"} 0\n"));
+        assert!(rendered.contains("ekit_relay_on_seconds_total{relay=\"coil2\"} 0.000\n"));
+        assert!(rendered.contains("ekit_output_temperature_celsius NaN\n"));
+        assert!(rendered.contains("ekit_ambient_temperature_celsius NaN\n"));
+        assert!(rendered.contains("ekit_uptime_seconds 2.000\n"));
+        assert!(rendered.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn relay_switches_and_on_time() {
+        let started = Instant::now();
+        let mut metrics = Metrics::new(started);
+        let at = |secs| started + Duration::from_secs(secs);
+
+        let closed = EKitEvent::RelayChanged {
+            relay: EKitRelay::Coil1,
+            closed: true,
+        };
+        let opened = EKitEvent::RelayChanged {
+            relay: EKitRelay::Coil1,
+            closed: false,
+        };
+        metrics.record(&closed, at(10));
+        metrics.record(&opened, at(15));
+        metrics.record(&closed, at(20));
+
+        let rendered = metrics.render(at(22));
+        assert!(rendered.contains("ekit_relay_switches_total{relay=\"coil1\"} 3\n"));
+        assert!(rendered.contains("ekit_relay_on_seconds_total{relay=\"coil1\"} 7.000\n"));
+        assert!(rendered.contains("ekit_relay_switches_total{relay=\"coil2\"} 0\n"));
+    }
+
+    #[test]
+    fn requests_cooldowns_and_failures() {
+        let started = Instant::now();
+        let mut metrics = Metrics::new(started);
+
+        metrics.record(
+            &EKitEvent::RunModeRequested {
+                run_mode: EKitUserRunMode::Half,
+                accepted: true,
+            },
+            started,
+        );
+        metrics.record(
+            &EKitEvent::RunModeRequested {
+                run_mode: EKitUserRunMode::Half,
+                accepted: false,
+            },
+            started,
+        );
+        metrics.record(
+            &EKitEvent::RunModeChanged {
+                from: EKitSystemRunMode::Half,
+                to: EKitSystemRunMode::Cooldown,
+            },
+            started,
+        );
+        metrics.record(
+            &EKitEvent::OutputTemperatureSensorFailed { sensor: 1 },
+            started,
+        );
+        metrics.record(
+            &EKitEvent::OutputTemperatureSensorFailed { sensor: 1 },
+            started,
+        );
+        metrics.record(
+            &EKitEvent::OutputTemperature {
+                temperature: Some(celsius(55.5)),
+            },
+            started,
+        );
+
+        let rendered = metrics.render(started);
+        assert!(rendered.contains("ekit_run_mode_requests_total{run_mode=\"Half\"} 2\n"));
+        assert!(rendered.contains("ekit_run_mode_requests_total{run_mode=\"Full\"} 0\n"));
+        assert!(rendered.contains("ekit_rejected_run_mode_requests_total 1\n"));
+        assert!(rendered.contains("ekit_cooldown_entries_total 1\n"));
+        assert!(rendered.contains("ekit_sensor_read_failures_total{sensor=\"0\"} 0\n"));
+        assert!(rendered.contains("ekit_sensor_read_failures_total{sensor=\"1\"} 2\n"));
+        assert!(rendered.contains("ekit_output_temperature_celsius 55.50\n"));
+    }
+
+    #[test]
+    fn ambient() {
+        let started = Instant::now();
+        let mut metrics = Metrics::new(started);
+        metrics.record_ambient(PostEKitAmbient {
+            temperature_celsius: 19.5,
+            humidity_percent: Some(52.25),
+            pressure_hpa: None,
+            age_secs: None,
+        });
+
+        let rendered = metrics.render(started);
+        assert!(rendered.contains("ekit_ambient_temperature_celsius 19.50\n"));
+        assert!(rendered.contains("ekit_ambient_humidity_percent 52.25\n"));
+        assert!(rendered.contains("ekit_ambient_pressure_hectopascals NaN\n"));
+    }
+}
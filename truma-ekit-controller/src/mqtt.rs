@@ -0,0 +1,151 @@
+use crate::{
+    ekit::EKit,
+    homeassistant::{self, AVAILABILITY_TOPIC, PAYLOAD_OFFLINE},
+};
+use embedded_svc::mqtt::client::{Event, Message as _, QoS};
+use esp_idf_svc::mqtt::client::{EspMqttClient, LwtConfiguration, MqttClientConfiguration};
+use esp_idf_sys::EspError;
+use std::sync::{mpsc::channel, Arc, Mutex};
+use truma_ekit_core::{
+    ekit::{EKitEvent, EKitSystemRunMode},
+    types::Temperature,
+};
+
+/// The URL of the MQTT broker (e.g. `mqtt://192.168.71.2:1883`), or `None` to disable MQTT.
+pub const MQTT_BROKER_URL: Option<&str> = None;
+/// The client id used to connect to the MQTT broker.
+const MQTT_CLIENT_ID: &str = "truma-ekit";
+/// The stack size of the thread publishing to the MQTT broker.
+const PUBLISHER_STACK_SIZE: usize = 8 * 1024;
+
+#[derive(thiserror::Error, Debug)]
+pub enum EKitMqttError {
+    #[error("ESP error: {0}")]
+    Esp(#[from] EspError),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A message to be handled by the publisher thread.
+enum Outgoing {
+    /// The client has (re)connected to the broker, while the e-kit was in the given state.
+    Connected {
+        run_mode: EKitSystemRunMode,
+        output_temperature: Option<Temperature>,
+    },
+    /// The e-kit has published an event.
+    Event(EKitEvent),
+}
+
+/// Publishes the e-kit state to an MQTT broker, and accepts run mode requests from it.
+pub struct EKitMqtt<E: EKit> {
+    url: &'static str,
+    ekit: Arc<Mutex<E>>,
+}
+
+impl<E> EKitMqtt<E>
+where
+    E: EKit + 'static,
+{
+    pub fn new(url: &'static str, ekit: Arc<Mutex<E>>) -> Self {
+        EKitMqtt { url, ekit }
+    }
+
+    /// Connect to the MQTT broker.
+    ///
+    /// The connection is maintained (and reestablished if needed) in the background.
+    pub fn start(&mut self) -> Result<(), EKitMqttError> {
+        let (outgoing_tx, outgoing_rx) = channel();
+
+        let conf = MqttClientConfiguration {
+            client_id: Some(MQTT_CLIENT_ID),
+            lwt: Some(LwtConfiguration {
+                topic: AVAILABILITY_TOPIC,
+                payload: PAYLOAD_OFFLINE.as_bytes(),
+                qos: QoS::AtLeastOnce,
+                retain: true,
+            }),
+            ..Default::default()
+        };
+
+        let ekit = self.ekit.clone();
+        let connected_tx = outgoing_tx.clone();
+        let mut client = EspMqttClient::new(self.url, &conf, move |event| match event {
+            Ok(Event::Connected(_)) => {
+                log::info!("connected to MQTT broker");
+                let (run_mode, output_temperature) = {
+                    let ekit = ekit.lock().unwrap();
+                    (ekit.run_mode(), ekit.output_temperature())
+                };
+                let _ = connected_tx.send(Outgoing::Connected {
+                    run_mode,
+                    output_temperature,
+                });
+            }
+            Ok(Event::Received(message))
+                if message.topic() == Some(homeassistant::RUN_MODE_COMMAND_TOPIC) =>
+            {
+                match homeassistant::parse_run_mode_command(message.data()) {
+                    Some(run_mode) => {
+                        log::info!("e-kit run mode {:?} requested over MQTT", run_mode);
+                        ekit.lock().unwrap().request_user_run_mode(run_mode);
+                    }
+                    None => log::warn!("ignoring invalid MQTT run mode command"),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => log::error!("MQTT error ({})", e),
+        })?;
+
+        // forward the e-kit events to the publisher thread
+        let events = self.ekit.lock().unwrap().events().subscribe();
+        std::thread::spawn(move || {
+            for event in events {
+                if outgoing_tx.send(Outgoing::Event(event.event)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        std::thread::Builder::new()
+            .stack_size(PUBLISHER_STACK_SIZE)
+            .spawn(move || {
+                for outgoing in outgoing_rx {
+                    let messages = match outgoing {
+                        Outgoing::Connected {
+                            run_mode,
+                            output_temperature,
+                        } => {
+                            if let Err(e) = client
+                                .subscribe(homeassistant::RUN_MODE_COMMAND_TOPIC, QoS::AtLeastOnce)
+                            {
+                                log::error!("failed to subscribe to run mode commands ({})", e);
+                            }
+                            let mut messages = homeassistant::discovery_messages();
+                            messages.push(homeassistant::availability_message());
+                            // the state is otherwise only published on change
+                            messages.extend(homeassistant::snapshot_messages(
+                                run_mode,
+                                output_temperature,
+                            ));
+                            messages
+                        }
+                        Outgoing::Event(event) => homeassistant::state_messages(&event),
+                    };
+
+                    for message in messages {
+                        if let Err(e) = client.publish(
+                            &message.topic,
+                            QoS::AtLeastOnce,
+                            message.retain,
+                            message.payload.as_bytes(),
+                        ) {
+                            log::error!("failed to publish to {} ({})", message.topic, e);
+                        }
+                    }
+                }
+            })?;
+
+        Ok(())
+    }
+}
@@ -1,14 +1,100 @@
-use truma_ekit_core::{ekit::EKitSystemRunMode, types::Temperature, util::celsius};
+use truma_ekit_core::{
+    ekit::{EKitFault, EKitSystemRunMode},
+    types::{Temperature, TemperatureDelta},
+    util::celsius,
+};
 
 /// Cooldown will be entered if the output temperature is greater than or equal to this limit.
 const COOLDOWN_ENTER: Temperature = celsius(90.0);
 /// Cooldown will be exited if the output temperature is less than than or equal to this limit.
 const COOLDOWN_EXIT: Temperature = celsius(50.0);
+/// How the readings of multiple output temperature sensors are combined.
+const SENSOR_VOTING: SensorVoting = SensorVoting::MaxOf;
+/// The sensors are considered to disagree if their readings differ by more than this tolerance, if any.
+///
+/// Only useful for sensors measuring the same spot: the outlet and the coil housing differ by tens of °C while warming
+/// up, and with `MaxOf` the hottest sensor triggers cooldown anyway.
+const SENSOR_DISAGREEMENT_TOLERANCE: Option<TemperatureDelta> = None;
+
+/// How the readings of multiple output temperature sensors are combined into a single output temperature.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[allow(dead_code)] // only the configured voting is used
+pub enum SensorVoting {
+    /// The highest available reading is used, so any sensor that can be read can trigger cooldown.
+    ///
+    /// The output temperature is unavailable if none of the sensors could be read, and cooldown is forced if the
+    /// sensors disagree.
+    MaxOf,
+    /// The second highest reading is used, so 2 out of 3 sensors have to agree to trigger cooldown.
+    ///
+    /// If one sensor could not be read, the highest of the remaining 2 readings is used.
+    /// The output temperature is unavailable if fewer than 2 sensors could be read, and cooldown is forced if no 2
+    /// sensors agree.
+    TwoOutOfThree,
+}
+
+impl SensorVoting {
+    /// Returns the output temperature voted on by the given sensor readings.
+    pub fn vote(&self, readings: &[Option<Temperature>]) -> Option<Temperature> {
+        let mut available: Vec<Temperature> = readings.iter().flatten().copied().collect();
+        // highest first
+        available.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        match self {
+            SensorVoting::MaxOf => available.first().copied(),
+            SensorVoting::TwoOutOfThree => match available.len() {
+                0 | 1 => None,
+                2 => Some(available[0]),
+                _ => Some(available[1]),
+            },
+        }
+    }
+
+    /// Returns the fault of the sensors that keeps the voting from protecting the e-kit, if any.
+    fn fault(
+        &self,
+        readings: &[Option<Temperature>],
+        disagreement_tolerance: Option<TemperatureDelta>,
+    ) -> Option<EKitFault> {
+        let disagree = |readings: &[Option<Temperature>]| {
+            disagreement_tolerance.is_some_and(|tolerance| spread(readings) > Some(tolerance))
+        };
+        match self {
+            // a single sensor failing is covered by the remaining ones
+            SensorVoting::MaxOf => {
+                if readings.iter().all(Option::is_none) {
+                    Some(EKitFault::OutputTemperatureUnavailable)
+                } else if disagree(readings) {
+                    Some(EKitFault::OutputTemperatureSensorsDisagree)
+                } else {
+                    None
+                }
+            }
+            // a single sensor failing or disagreeing is outvoted, as long as 2 sensors agree
+            SensorVoting::TwoOutOfThree => {
+                let available: Vec<_> = readings.iter().copied().filter(Option::is_some).collect();
+                if available.len() < 2 {
+                    Some(EKitFault::OutputTemperatureUnavailable)
+                } else if disagree(&available)
+                    && !(0..available.len()).any(|i| {
+                        (i + 1..available.len()).any(|j| !disagree(&[available[i], available[j]]))
+                    })
+                {
+                    Some(EKitFault::OutputTemperatureSensorsDisagree)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct OvertemperatureProtection {
     is_active: bool,
     was_active: bool,
+    voting: SensorVoting,
+    disagreement_tolerance: Option<TemperatureDelta>,
+    sensor_fault: Option<EKitFault>,
 }
 
 impl OvertemperatureProtection {
@@ -17,9 +103,36 @@ impl OvertemperatureProtection {
         OvertemperatureProtection {
             is_active: false,
             was_active: false,
+            voting: SENSOR_VOTING,
+            disagreement_tolerance: SENSOR_DISAGREEMENT_TOLERANCE,
+            sensor_fault: None,
         }
     }
 
+    /// Combine the readings of multiple sensors using the given voting.
+    #[cfg(test)]
+    pub fn with_voting(mut self, voting: SensorVoting) -> Self {
+        self.voting = voting;
+        self
+    }
+
+    /// Consider the sensors to disagree if their readings differ by more than the given tolerance.
+    #[cfg(test)]
+    pub fn with_disagreement_tolerance(mut self, tolerance: TemperatureDelta) -> Self {
+        self.disagreement_tolerance = Some(tolerance);
+        self
+    }
+
+    /// Returns the fault of the sensors that forced cooldown the last time they were read, if any.
+    pub fn sensor_fault(&self) -> Option<EKitFault> {
+        self.sensor_fault
+    }
+
+    /// Returns `true` if overtemperature protection is currently active.
+    pub fn is_active(&self) -> bool {
+        self.is_active
+    }
+
     /// Enter overtemperature protection.
     pub fn enter(&mut self) {
         self.is_active = true;
@@ -32,10 +145,21 @@ impl OvertemperatureProtection {
         self.is_active = false;
     }
 
-    /// Signals that the e-kit output temperature has changed.
-    pub fn output_temperature_changed(&mut self, output_temperature: Option<Temperature>) {
+    /// Signals that the e-kit output temperature, as read by each of the sensors, has changed.
+    ///
+    /// Returns the output temperature voted on by the sensors, if available. Cooldown is forced if the sensors can't be
+    /// relied on to protect the e-kit (e.g. one of them failed or they disagree, depending on the voting).
+    pub fn output_temperatures_changed(
+        &mut self,
+        readings: &[Option<Temperature>],
+    ) -> Option<Temperature> {
+        let output_temperature = self.voting.vote(readings);
+        self.sensor_fault = self.voting.fault(readings, self.disagreement_tolerance);
+
         self.was_active = self.is_active;
         self.is_active = match output_temperature {
+            // a safe state, until the sensors can be relied on again
+            Some(_) if self.sensor_fault.is_some() => true,
             Some(temperature) => {
                 if self.is_active {
                     // exit overtemperature protection once the output temperature is less than or equal to `COOLDOWN_EXIT`
@@ -47,7 +171,8 @@ impl OvertemperatureProtection {
             }
             // if we failed to get the temperature, we force overtemperature protection
             None => true,
-        }
+        };
+        output_temperature
     }
 
     /// Returns the forced e-kit system run mode.
@@ -64,9 +189,23 @@ impl OvertemperatureProtection {
     }
 }
 
+/// Returns the difference between the highest and lowest available reading, if any.
+fn spread(readings: &[Option<Temperature>]) -> Option<TemperatureDelta> {
+    let available = readings.iter().flatten();
+    let highest = available
+        .clone()
+        .copied()
+        .reduce(|a, b| if b > a { b } else { a })?;
+    let lowest = available
+        .copied()
+        .reduce(|a, b| if b < a { b } else { a })?;
+    Some(highest - lowest)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use truma_ekit_core::util::celsius_delta;
 
     #[test]
     fn initially_inactive() {
@@ -100,7 +239,7 @@ mod tests {
     #[test]
     fn activates_on_overtemperature() {
         let mut sub = OvertemperatureProtection::inactive();
-        sub.output_temperature_changed(Some(COOLDOWN_ENTER));
+        sub.output_temperatures_changed(&[Some(COOLDOWN_ENTER)]);
         assert!(sub.is_active);
     }
 
@@ -108,7 +247,7 @@ mod tests {
     fn deactivates_after_cooldown() {
         let mut sub = OvertemperatureProtection::inactive();
         sub.enter();
-        sub.output_temperature_changed(Some(COOLDOWN_EXIT));
+        sub.output_temperatures_changed(&[Some(COOLDOWN_EXIT)]);
         assert!(!sub.is_active);
         assert!(sub.was_active);
     }
@@ -123,8 +262,118 @@ mod tests {
     #[test]
     fn turns_off_after_cooldown() {
         let mut sub = OvertemperatureProtection::inactive();
-        sub.output_temperature_changed(Some(COOLDOWN_ENTER));
-        sub.output_temperature_changed(Some(COOLDOWN_EXIT));
+        sub.output_temperatures_changed(&[Some(COOLDOWN_ENTER)]);
+        sub.output_temperatures_changed(&[Some(COOLDOWN_EXIT)]);
         assert_eq!(sub.forced_run_mode(), Some(EKitSystemRunMode::Off));
     }
+
+    #[test]
+    fn max_of_voting() {
+        let voting = SensorVoting::MaxOf;
+        assert_eq!(
+            voting.vote(&[
+                Some(celsius(40.0)),
+                Some(COOLDOWN_ENTER),
+                Some(celsius(45.0))
+            ]),
+            Some(COOLDOWN_ENTER)
+        );
+        // a failed sensor neither disables protection...
+        assert_eq!(
+            voting.vote(&[None, Some(COOLDOWN_ENTER)]),
+            Some(COOLDOWN_ENTER)
+        );
+        let mut sub = OvertemperatureProtection::inactive().with_voting(voting);
+        sub.output_temperatures_changed(&[None, Some(COOLDOWN_ENTER)]);
+        assert!(sub.is_active());
+        sub.output_temperatures_changed(&[None, Some(COOLDOWN_EXIT)]);
+        assert!(!sub.is_active());
+
+        // ...nor causes a cooldown
+        let mut sub = OvertemperatureProtection::inactive().with_voting(voting);
+        sub.output_temperatures_changed(&[None, Some(celsius(40.0))]);
+        assert!(!sub.is_active());
+        assert_eq!(sub.sensor_fault(), None);
+        assert_eq!(sub.forced_run_mode(), None);
+
+        // cooldown is forced once none of the sensors can be read
+        sub.output_temperatures_changed(&[None, None]);
+        assert!(sub.is_active());
+        assert_eq!(
+            sub.sensor_fault(),
+            Some(EKitFault::OutputTemperatureUnavailable)
+        );
+    }
+
+    #[test]
+    fn two_out_of_three_voting() {
+        let voting = SensorVoting::TwoOutOfThree;
+        // a single sensor reading too high doesn't trigger cooldown
+        assert_eq!(
+            voting.vote(&[
+                Some(celsius(40.0)),
+                Some(celsius(120.0)),
+                Some(celsius(42.0))
+            ]),
+            Some(celsius(42.0))
+        );
+        assert_eq!(
+            voting.vote(&[
+                Some(COOLDOWN_ENTER),
+                Some(celsius(40.0)),
+                Some(COOLDOWN_ENTER)
+            ]),
+            Some(COOLDOWN_ENTER)
+        );
+        // with a failed sensor, either remaining sensor triggers cooldown
+        assert_eq!(
+            voting.vote(&[Some(celsius(40.0)), None, Some(COOLDOWN_ENTER)]),
+            Some(COOLDOWN_ENTER)
+        );
+        assert_eq!(voting.vote(&[Some(celsius(40.0)), None, None]), None);
+
+        let mut sub = OvertemperatureProtection::inactive()
+            .with_voting(voting)
+            .with_disagreement_tolerance(celsius_delta(15.0));
+        sub.output_temperatures_changed(&[
+            Some(celsius(40.0)),
+            Some(celsius(120.0)),
+            Some(celsius(42.0)),
+        ]);
+        assert!(!sub.is_active());
+        assert_eq!(sub.sensor_fault(), None);
+        sub.output_temperatures_changed(&[Some(celsius(40.0)), None, Some(celsius(42.0))]);
+        assert!(!sub.is_active());
+
+        // no 2 sensors agree
+        sub.output_temperatures_changed(&[Some(celsius(40.0)), None, Some(celsius(70.0))]);
+        assert!(sub.is_active());
+        assert_eq!(
+            sub.sensor_fault(),
+            Some(EKitFault::OutputTemperatureSensorsDisagree)
+        );
+    }
+
+    #[test]
+    fn sensors_disagree() {
+        let mut sub = OvertemperatureProtection::inactive();
+        sub.output_temperatures_changed(&[Some(celsius(40.0)), Some(celsius(80.0))]);
+        assert_eq!(sub.sensor_fault(), None);
+
+        let mut sub =
+            OvertemperatureProtection::inactive().with_disagreement_tolerance(celsius_delta(15.0));
+        sub.output_temperatures_changed(&[Some(celsius(40.0)), Some(celsius(50.0))]);
+        assert_eq!(sub.sensor_fault(), None);
+
+        sub.output_temperatures_changed(&[Some(celsius(40.0)), Some(celsius(80.0))]);
+        assert_eq!(
+            sub.sensor_fault(),
+            Some(EKitFault::OutputTemperatureSensorsDisagree)
+        );
+        assert!(sub.is_active());
+
+        sub.output_temperatures_changed(&[Some(celsius(40.0)), Some(celsius(42.0))]);
+        assert_eq!(sub.sensor_fault(), None);
+        assert!(!sub.is_active());
+    }
 }
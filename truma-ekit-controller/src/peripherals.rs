@@ -1,19 +1,21 @@
 use esp_idf_hal::{
     adc::ADC1,
-    gpio::{AnyOutputPin, Gpio2},
+    gpio::{AnyIOPin, AnyOutputPin, Gpio2, Gpio3},
     modem::Modem,
     prelude::Peripherals,
 };
 
-pub struct SystemPeripherals<ADC, GP> {
+pub struct SystemPeripherals<ADC, GP, CS> {
     pub fan: FanPeripherals,
     pub coil1: CoilPeripherals,
     pub coil2: CoilPeripherals,
     pub thermometer: ThermometerPeripherals<ADC, GP>,
+    pub current_sensor: CurrentSensorPeripherals<CS>,
+    pub onewire: OneWirePeripherals,
     pub modem: Modem,
 }
 
-impl SystemPeripherals<ADC1, Gpio2> {
+impl SystemPeripherals<ADC1, Gpio2, Gpio3> {
     pub fn take() -> Self {
         let peripherals = Peripherals::take().unwrap();
         SystemPeripherals {
@@ -31,6 +33,12 @@ impl SystemPeripherals<ADC1, Gpio2> {
                 voltage: peripherals.pins.gpio2,
                 vcc: peripherals.pins.gpio10.into(),
             },
+            current_sensor: CurrentSensorPeripherals {
+                voltage: peripherals.pins.gpio3,
+            },
+            onewire: OneWirePeripherals {
+                data: peripherals.pins.gpio4.into(),
+            },
             modem: peripherals.modem,
         }
     }
@@ -49,3 +57,15 @@ pub struct ThermometerPeripherals<ADC, GP> {
     pub voltage: GP,
     pub vcc: AnyOutputPin,
 }
+
+/// The (optional) sensor measuring the total current drawn at the campsite.
+///
+/// It shares the ADC with the thermometer.
+pub struct CurrentSensorPeripherals<GP> {
+    pub voltage: GP,
+}
+
+/// The (optional) 1-Wire bus the DS18B20 sensors are connected to.
+pub struct OneWirePeripherals {
+    pub data: AnyIOPin,
+}
@@ -0,0 +1,216 @@
+use crate::energy::run_mode_power;
+use std::time::{Duration, Instant};
+use truma_ekit_core::{
+    ekit::EKitSystemRunMode,
+    peripherals::current_sensor::CurrentSensor,
+    types::{Current, Power, Voltage},
+    util::{amperes, volts, watts},
+};
+
+/// The current limit of the campsite hookup (e.g. `Some(amperes(6.0))`), or `None` to disable power budget limiting.
+pub const SITE_CURRENT_LIMIT: Option<Current> = None;
+/// The transfer ratio (in A/V) of the current sensor measuring the total site current, or `None` if there is no such
+/// sensor.
+pub const CURRENT_SENSOR_AMPERES_PER_VOLT: Option<f32> = None;
+/// The voltage of the campsite hookup.
+const MAINS_VOLTAGE: Voltage = volts(230.0);
+/// The current kept in reserve below the site current limit.
+const SAFETY_MARGIN: Current = amperes(0.5);
+/// The minimum time a lowered run mode limit is kept before it is raised again.
+const RESTORE_DELAY: Duration = Duration::from_secs(30);
+
+/// The run modes the e-kit can be limited to, from highest to lowest power.
+///
+/// The fan is never shed, so the e-kit can always cool down.
+const LIMITS: [EKitSystemRunMode; 3] = [
+    EKitSystemRunMode::Full,
+    EKitSystemRunMode::Half,
+    EKitSystemRunMode::Cool,
+];
+
+/// Limits the run mode of the e-kit to stay within the current limit of the campsite hookup.
+pub struct PowerBudget<'a> {
+    budget: Power,
+    current_sensor: Option<CurrentSensor<'a>>,
+    limit: EKitSystemRunMode,
+    lowered_at: Option<Instant>,
+}
+
+impl<'a> PowerBudget<'a> {
+    /// Returns a power budget for the given site current limit.
+    pub fn new(site_current_limit: Current) -> Self {
+        PowerBudget {
+            budget: (site_current_limit - SAFETY_MARGIN) * MAINS_VOLTAGE,
+            current_sensor: None,
+            limit: EKitSystemRunMode::Full,
+            lowered_at: None,
+        }
+    }
+
+    /// Take the other loads at the site into account, using a sensor measuring the total site current.
+    pub fn with_current_sensor(mut self, current_sensor: CurrentSensor<'a>) -> Self {
+        self.current_sensor = Some(current_sensor);
+        self
+    }
+
+    /// Returns the highest run mode that stays within the power budget, given the current e-kit run mode.
+    pub fn update(&mut self, run_mode: EKitSystemRunMode, now: Instant) -> EKitSystemRunMode {
+        let site_current =
+            self.current_sensor
+                .as_mut()
+                .and_then(|sensor| match sensor.measure_current() {
+                    Ok(current) => Some(current),
+                    Err(e) => {
+                        log::error!("failed to measure site current ({})", e);
+                        None
+                    }
+                });
+        self.run_mode_limit(run_mode, site_current, now)
+    }
+
+    fn run_mode_limit(
+        &mut self,
+        run_mode: EKitSystemRunMode,
+        site_current: Option<Current>,
+        now: Instant,
+    ) -> EKitSystemRunMode {
+        // the power drawn by everything but the e-kit
+        let other_loads = site_current
+            .map(|current| current * MAINS_VOLTAGE - run_mode_power(run_mode))
+            .filter(|other_loads| *other_loads > watts(0.0))
+            .unwrap_or(watts(0.0));
+        let available = self.budget - other_loads;
+
+        let limit = LIMITS
+            .into_iter()
+            .find(|limit| run_mode_power(*limit) <= available)
+            .unwrap_or(EKitSystemRunMode::Cool);
+
+        if run_mode_power(limit) < run_mode_power(self.limit) {
+            log::info!("lowering run mode limit to {:?}", limit);
+            self.limit = limit;
+            self.lowered_at = Some(now);
+        } else if limit != self.limit
+            && self
+                .lowered_at
+                .map(|lowered_at| now.saturating_duration_since(lowered_at) >= RESTORE_DELAY)
+                .unwrap_or(true)
+        {
+            log::info!("raising run mode limit to {:?}", limit);
+            self.limit = limit;
+        }
+
+        self.limit
+    }
+}
+
+/// Returns `run_mode`, downgraded to `limit` if it would draw more power.
+pub fn limited_run_mode(
+    run_mode: EKitSystemRunMode,
+    limit: EKitSystemRunMode,
+) -> EKitSystemRunMode {
+    if run_mode_power(run_mode) > run_mode_power(limit) {
+        limit
+    } else {
+        run_mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn static_limit() {
+        let now = Instant::now();
+
+        let mut budget = PowerBudget::new(amperes(6.0));
+        assert_eq!(
+            budget.run_mode_limit(EKitSystemRunMode::Off, None, now),
+            EKitSystemRunMode::Half
+        );
+
+        let mut budget = PowerBudget::new(amperes(16.0));
+        assert_eq!(
+            budget.run_mode_limit(EKitSystemRunMode::Off, None, now),
+            EKitSystemRunMode::Full
+        );
+
+        let mut budget = PowerBudget::new(amperes(2.0));
+        assert_eq!(
+            budget.run_mode_limit(EKitSystemRunMode::Off, None, now),
+            EKitSystemRunMode::Cool
+        );
+    }
+
+    #[test]
+    fn sheds_coils_when_other_loads_are_switched_on() {
+        let now = Instant::now();
+        let mut budget = PowerBudget::new(amperes(16.0));
+
+        // e-kit at full capacity (~7.9 A), nothing else
+        assert_eq!(
+            budget.run_mode_limit(EKitSystemRunMode::Full, Some(amperes(8.0)), now),
+            EKitSystemRunMode::Full
+        );
+        // a kettle (~8.7 A) is switched on
+        assert_eq!(
+            budget.run_mode_limit(EKitSystemRunMode::Full, Some(amperes(16.6)), now),
+            EKitSystemRunMode::Half
+        );
+    }
+
+    #[test]
+    fn restores_limit_after_delay() {
+        let now = Instant::now();
+        let mut budget = PowerBudget::new(amperes(6.0));
+
+        // a kettle (~8.7 A) is switched on
+        assert_eq!(
+            budget.run_mode_limit(EKitSystemRunMode::Half, Some(amperes(12.8)), now),
+            EKitSystemRunMode::Cool
+        );
+
+        // the kettle is switched off again
+        assert_eq!(
+            budget.run_mode_limit(
+                EKitSystemRunMode::Cool,
+                Some(amperes(0.1)),
+                now + Duration::from_secs(10)
+            ),
+            EKitSystemRunMode::Cool
+        );
+        assert_eq!(
+            budget.run_mode_limit(
+                EKitSystemRunMode::Cool,
+                Some(amperes(0.1)),
+                now + RESTORE_DELAY
+            ),
+            EKitSystemRunMode::Half
+        );
+    }
+
+    #[test]
+    fn limits_run_modes() {
+        assert_eq!(
+            limited_run_mode(EKitSystemRunMode::Full, EKitSystemRunMode::Half),
+            EKitSystemRunMode::Half
+        );
+        assert_eq!(
+            limited_run_mode(EKitSystemRunMode::Half, EKitSystemRunMode::Cool),
+            EKitSystemRunMode::Cool
+        );
+        assert_eq!(
+            limited_run_mode(EKitSystemRunMode::Cool, EKitSystemRunMode::Half),
+            EKitSystemRunMode::Cool
+        );
+        assert_eq!(
+            limited_run_mode(EKitSystemRunMode::Off, EKitSystemRunMode::Cool),
+            EKitSystemRunMode::Off
+        );
+        assert_eq!(
+            limited_run_mode(EKitSystemRunMode::Cooldown, EKitSystemRunMode::Cool),
+            EKitSystemRunMode::Cooldown
+        );
+    }
+}
@@ -1,10 +1,39 @@
-use embedded_svc::http::Method;
+use crate::{
+    ekit::{EKit, MAX_BOOST_DURATION},
+    energy::{self, EnergyMeter},
+    metrics::{self, Metrics},
+    settings::Settings,
+};
+use embedded_svc::{
+    http::{Method, Query},
+    io::Write,
+};
 use esp_idf_svc::{
     errors::EspIOError,
     http::server::{Configuration, EspHttpServer},
 };
-use std::sync::{Arc, Mutex};
-use truma_ekit_core::ekit::{EKit, PostEKitRunMode};
+use serde::Deserialize;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use truma_ekit_core::{
+    control::is_plausible_ambient,
+    ekit::{
+        EKitStatus, PostEKitAmbient, PostEKitBoost, PostEKitConfig, PostEKitRelayLifetime,
+        PostEKitRunMode, PostEKitScheduleDay, PostEKitSetpoint, PostEKitSetpointBounds,
+        PostEKitTime,
+    },
+    peripherals::tmp36::Tmp36Calibration,
+    schedule::{Presets, WallClock, Weekday},
+    types::{UnitEnergy, UnitPower},
+    util::celsius,
+};
+
+/// The maximum time a long-poll request for events will wait for new events.
+///
+/// The HTTP server handles one request at a time, so this should be kept short.
+const EVENTS_LONG_POLL_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(thiserror::Error, Debug)]
 pub enum EKitServerError {
@@ -12,18 +41,42 @@ pub enum EKitServerError {
     IoError(#[from] EspIOError),
 }
 
+#[derive(Deserialize, Debug)]
+struct GetEvents {
+    /// Only return events with a sequence number greater than this one.
+    #[serde(default)]
+    after: u64,
+}
+
 pub struct EKitHttpServer<E: EKit> {
     server: EspHttpServer,
     ekit: Arc<Mutex<E>>,
+    metrics: Arc<Mutex<Metrics>>,
+    energy: Arc<Mutex<EnergyMeter>>,
+    settings: Arc<Mutex<Settings>>,
+    clock: Arc<Mutex<WallClock>>,
 }
 
 impl<E> EKitHttpServer<E>
 where
     E: EKit + Send + 'static,
 {
-    pub fn new(ekit: Arc<Mutex<E>>) -> Result<Self, EKitServerError> {
+    pub fn new(
+        ekit: Arc<Mutex<E>>,
+        metrics: Arc<Mutex<Metrics>>,
+        energy: Arc<Mutex<EnergyMeter>>,
+        settings: Arc<Mutex<Settings>>,
+        clock: Arc<Mutex<WallClock>>,
+    ) -> Result<Self, EKitServerError> {
         let server = EspHttpServer::new(&Configuration::default())?;
-        Ok(EKitHttpServer { server, ekit })
+        Ok(EKitHttpServer {
+            server,
+            ekit,
+            metrics,
+            energy,
+            settings,
+            clock,
+        })
     }
 
     pub fn start(&mut self) -> Result<(), EKitServerError> {
@@ -35,16 +88,468 @@ where
                 let count = body.read(&mut buf)?;
                 let post: PostEKitRunMode = serde_urlencoded::from_bytes(&buf[..count])?;
 
-                log::info!("e-kit run mode {:?} requested", post.run_mode);
+                let mut ekit = ekit.lock()?;
+                if post.periodic {
+                    ekit.request_periodic_run_mode(post.run_mode);
+                } else {
+                    log::info!("e-kit run mode {:?} requested", post.run_mode);
+                    ekit.request_user_run_mode(post.run_mode);
+                }
+
+                req.into_ok_response()?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let events = self.ekit.lock().unwrap().events();
+        self.server
+            .fn_handler("/events", Method::Get, move |req| {
+                let query = req.uri().split_once('?').map(|(_, q)| q).unwrap_or("");
+                let get: GetEvents = serde_urlencoded::from_str(query)?;
+
+                // long-poll: wait for events newer than the ones the client has already seen
+                let events = events.wait_for_events_after(get.after, EVENTS_LONG_POLL_TIMEOUT);
+                let payload = serde_json::to_vec(&events)?;
+
+                let mut res =
+                    req.into_response(200, None, &[("content-type", "application/json")])?;
+                res.write_all(&payload)?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let metrics = self.metrics.clone();
+        self.server
+            .fn_handler("/metrics", Method::Get, move |req| {
+                let payload = metrics.lock()?.render(Instant::now());
+
+                let mut res =
+                    req.into_response(200, None, &[("content-type", metrics::CONTENT_TYPE)])?;
+                res.write_all(payload.as_bytes())?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let ekit = self.ekit.clone();
+        self.server
+            .fn_handler("/setpoint", Method::Post, move |mut req| {
+                let (_, body) = req.split();
+                let mut buf = [0_u8; 1024];
+                let count = body.read(&mut buf)?;
+                let post: PostEKitSetpoint = serde_urlencoded::from_bytes(&buf[..count])?;
+
+                log::info!("e-kit setpoint {} °C requested", post.setpoint_celsius);
+
+                let result = ekit.lock()?.set_setpoint(celsius(post.setpoint_celsius));
+                match result {
+                    Ok(()) => {
+                        req.into_ok_response()?;
+                    }
+                    Err(err) => {
+                        log::warn!("rejected setpoint: {}", err);
+                        let mut res = req.into_response(400, Some("Bad Request"), &[])?;
+                        res.write_all(err.to_string().as_bytes())?;
+                    }
+                }
+
+                Ok(())
+            })
+            .unwrap();
+
+        let ekit = self.ekit.clone();
+        let settings = self.settings.clone();
+        self.server
+            .fn_handler("/setpoint/bounds", Method::Post, move |mut req| {
+                let (_, body) = req.split();
+                let mut buf = [0_u8; 1024];
+                let count = body.read(&mut buf)?;
+                let post: PostEKitSetpointBounds = serde_urlencoded::from_bytes(&buf[..count])?;
+
+                let setpoint_bounds = match post.bounds() {
+                    Ok(setpoint_bounds) => setpoint_bounds,
+                    Err(e) => {
+                        let reason = format!("invalid setpoint bounds: {}", e);
+                        log::warn!("rejected setpoint bounds: {}", reason);
+                        let mut res = req.into_response(400, Some("Bad Request"), &[])?;
+                        res.write_all(reason.as_bytes())?;
+                        return Ok(());
+                    }
+                };
+
+                log::info!(
+                    "setpoint bounds {} °C to {} °C configured",
+                    post.min_celsius,
+                    post.max_celsius
+                );
+
+                settings.lock()?.set_setpoint_bounds(setpoint_bounds)?;
+                ekit.lock()?.set_setpoint_bounds(setpoint_bounds);
+
+                req.into_ok_response()?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let ekit = self.ekit.clone();
+        self.server
+            .fn_handler("/boost", Method::Post, move |mut req| {
+                let (_, body) = req.split();
+                let mut buf = [0_u8; 1024];
+                let count = body.read(&mut buf)?;
+                let post: PostEKitBoost = serde_urlencoded::from_bytes(&buf[..count])?;
+
+                log::info!("e-kit boost for {} s requested", post.duration_secs);
+
+                let duration = Duration::from_secs(post.duration_secs);
+                if duration > MAX_BOOST_DURATION {
+                    let reason = format!(
+                        "boost duration {} s exceeds the maximum of {} s",
+                        post.duration_secs,
+                        MAX_BOOST_DURATION.as_secs()
+                    );
+                    log::warn!("rejected boost: {}", reason);
+                    let mut res = req.into_response(400, Some("Bad Request"), &[])?;
+                    res.write_all(reason.as_bytes())?;
+                    return Ok(());
+                }
 
                 let mut ekit = ekit.lock()?;
-                ekit.request_user_run_mode(post.run_mode);
+                ekit.request_boost(duration);
+
+                req.into_ok_response()?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let ekit = self.ekit.clone();
+        let metrics = self.metrics.clone();
+        self.server
+            .fn_handler("/ambient", Method::Post, move |mut req| {
+                let (_, body) = req.split();
+                let mut buf = [0_u8; 1024];
+                let count = body.read(&mut buf)?;
+                let post: PostEKitAmbient = serde_urlencoded::from_bytes(&buf[..count])?;
+
+                let temperature = celsius(post.temperature_celsius);
+                if !is_plausible_ambient(temperature) {
+                    let reason = format!(
+                        "ambient temperature {} °C is implausible",
+                        post.temperature_celsius
+                    );
+                    log::warn!("rejected ambient conditions: {}", reason);
+                    let mut res = req.into_response(400, Some("Bad Request"), &[])?;
+                    res.write_all(reason.as_bytes())?;
+                    return Ok(());
+                }
+
+                metrics.lock()?.record_ambient(post);
+                let now = Instant::now();
+                let measured_at = post
+                    .age_secs
+                    .and_then(|age| now.checked_sub(Duration::from_secs(age)))
+                    .unwrap_or(now);
+                ekit.lock()?
+                    .report_ambient_temperature(temperature, measured_at);
+
+                req.into_ok_response()?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let ekit = self.ekit.clone();
+        let energy = self.energy.clone();
+        let settings = self.settings.clone();
+        let clock = self.clock.clone();
+        self.server
+            .fn_handler("/status", Method::Get, move |req| {
+                let (
+                    run_mode,
+                    output_temperature,
+                    setpoint,
+                    setpoint_bounds,
+                    power_limited,
+                    boost_remaining,
+                ) = {
+                    let ekit = ekit.lock()?;
+                    (
+                        ekit.run_mode(),
+                        ekit.output_temperature(),
+                        ekit.setpoint(),
+                        ekit.setpoint_bounds(),
+                        ekit.is_power_limited(),
+                        ekit.boost_remaining(Instant::now()),
+                    )
+                };
+                let (temperature_scale, utc_offset_minutes) = {
+                    let settings = settings.lock()?;
+                    (settings.temperature_scale(), settings.utc_offset_minutes())
+                };
+                let unix_secs = clock.lock()?.unix_secs(Instant::now());
+                let status = {
+                    let energy = energy.lock()?;
+                    EKitStatus {
+                        run_mode,
+                        temperature_scale,
+                        output_temperature: output_temperature.map(|temperature| {
+                            temperature.converted_to(temperature_scale.unit()).value
+                        }),
+                        setpoint: setpoint.map(|temperature| {
+                            temperature.converted_to(temperature_scale.unit()).value
+                        }),
+                        setpoint_min: setpoint_bounds
+                            .min()
+                            .converted_to(temperature_scale.unit())
+                            .value,
+                        setpoint_max: setpoint_bounds
+                            .max()
+                            .converted_to(temperature_scale.unit())
+                            .value,
+                        power_limited,
+                        power_w: energy::run_mode_power(run_mode)
+                            .converted_to(UnitPower::watts())
+                            .value,
+                        energy_today_wh: energy
+                            .today()
+                            .converted_to(UnitEnergy::watt_hours())
+                            .value,
+                        energy_total_wh: energy
+                            .total()
+                            .converted_to(UnitEnergy::watt_hours())
+                            .value,
+                        boost_remaining_secs: boost_remaining.map(|remaining| remaining.as_secs()),
+                        unix_secs,
+                        utc_offset_minutes,
+                    }
+                };
+                let payload = serde_json::to_vec(&status)?;
+
+                let mut res =
+                    req.into_response(200, None, &[("content-type", "application/json")])?;
+                res.write_all(&payload)?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let energy = self.energy.clone();
+        self.server
+            .fn_handler("/energy/reset", Method::Post, move |req| {
+                energy.lock()?.reset();
+
+                req.into_ok_response()?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let settings = self.settings.clone();
+        self.server
+            .fn_handler("/config", Method::Post, move |mut req| {
+                let (_, body) = req.split();
+                let mut buf = [0_u8; 1024];
+                let count = body.read(&mut buf)?;
+                let post: PostEKitConfig = serde_urlencoded::from_bytes(&buf[..count])?;
+
+                log::info!("temperature scale {:?} configured", post.temperature_scale);
+
+                settings
+                    .lock()?
+                    .set_temperature_scale(post.temperature_scale)?;
+
+                req.into_ok_response()?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let settings = self.settings.clone();
+        self.server
+            .fn_handler("/calibration", Method::Get, move |req| {
+                let calibration = settings.lock()?.tmp36_calibration();
+                let payload = serde_json::to_vec(&calibration)?;
+
+                let mut res =
+                    req.into_response(200, None, &[("content-type", "application/json")])?;
+                res.write_all(&payload)?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let settings = self.settings.clone();
+        self.server
+            .fn_handler("/calibration", Method::Post, move |mut req| {
+                let (_, body) = req.split();
+                let mut buf = [0_u8; 1024];
+                let count = body.read(&mut buf)?;
+                let post: Tmp36Calibration = serde_urlencoded::from_bytes(&buf[..count])?;
+
+                if let Err(e) = post.validated() {
+                    let reason = format!("invalid calibration: {}", e);
+                    log::warn!("rejected output temperature sensor calibration: {}", reason);
+                    let mut res = req.into_response(400, Some("Bad Request"), &[])?;
+                    res.write_all(reason.as_bytes())?;
+                    return Ok(());
+                }
+
+                log::info!(
+                    "output temperature sensor calibration {:?} configured",
+                    post
+                );
+
+                settings.lock()?.set_tmp36_calibration(post)?;
+
+                req.into_ok_response()?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let settings = self.settings.clone();
+        self.server
+            .fn_handler("/relay/lifetime", Method::Get, move |req| {
+                let cycles = settings.lock()?.relay_lifetime_cycles();
+                let payload = serde_json::to_vec(&PostEKitRelayLifetime { cycles })?;
+
+                let mut res =
+                    req.into_response(200, None, &[("content-type", "application/json")])?;
+                res.write_all(&payload)?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let settings = self.settings.clone();
+        self.server
+            .fn_handler("/relay/lifetime", Method::Post, move |mut req| {
+                let (_, body) = req.split();
+                let mut buf = [0_u8; 1024];
+                let count = body.read(&mut buf)?;
+                let post: PostEKitRelayLifetime = serde_urlencoded::from_bytes(&buf[..count])?;
+
+                if let Err(e) = settings.lock()?.set_relay_lifetime_cycles(post.cycles) {
+                    let reason = format!("invalid relay lifetime: {}", e);
+                    log::warn!("rejected relay lifetime: {}", reason);
+                    let mut res = req.into_response(400, Some("Bad Request"), &[])?;
+                    res.write_all(reason.as_bytes())?;
+                    return Ok(());
+                }
+
+                log::info!("relay lifetime of {} cycles configured", post.cycles);
 
                 req.into_ok_response()?;
 
                 Ok(())
             })
             .unwrap();
+
+        let settings = self.settings.clone();
+        let clock = self.clock.clone();
+        self.server
+            .fn_handler("/time", Method::Post, move |mut req| {
+                let (_, body) = req.split();
+                let mut buf = [0_u8; 1024];
+                let count = body.read(&mut buf)?;
+                let post: PostEKitTime = serde_urlencoded::from_bytes(&buf[..count])?;
+
+                if let Err(e) = settings
+                    .lock()?
+                    .set_utc_offset_minutes(post.utc_offset_minutes)
+                {
+                    let reason = format!("invalid UTC offset: {}", e);
+                    log::warn!("rejected time: {}", reason);
+                    let mut res = req.into_response(400, Some("Bad Request"), &[])?;
+                    res.write_all(reason.as_bytes())?;
+                    return Ok(());
+                }
+
+                log::info!(
+                    "time {} s (UTC{:+} min) set",
+                    post.unix_secs,
+                    post.utc_offset_minutes
+                );
+
+                clock.lock()?.set(post.unix_secs, Instant::now());
+
+                req.into_ok_response()?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let settings = self.settings.clone();
+        self.server
+            .fn_handler("/schedule", Method::Get, move |req| {
+                let schedule = settings.lock()?.schedule();
+                let payload = serde_json::to_vec(&schedule)?;
+
+                let mut res =
+                    req.into_response(200, None, &[("content-type", "application/json")])?;
+                res.write_all(&payload)?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let settings = self.settings.clone();
+        self.server
+            .fn_handler("/schedule/presets", Method::Post, move |mut req| {
+                let (_, body) = req.split();
+                let mut buf = [0_u8; 1024];
+                let count = body.read(&mut buf)?;
+                let post: Presets = serde_urlencoded::from_bytes(&buf[..count])?;
+
+                if let Err(e) = settings.lock()?.set_presets(post) {
+                    let reason = format!("invalid presets: {}", e);
+                    log::warn!("rejected schedule presets: {}", reason);
+                    let mut res = req.into_response(400, Some("Bad Request"), &[])?;
+                    res.write_all(reason.as_bytes())?;
+                    return Ok(());
+                }
+
+                log::info!("schedule presets {:?} configured", post);
+
+                req.into_ok_response()?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let settings = self.settings.clone();
+        self.server
+            .fn_handler("/schedule/day", Method::Post, move |mut req| {
+                let (_, body) = req.split();
+                let mut buf = [0_u8; 1024];
+                let count = body.read(&mut buf)?;
+                // JSON, as the blocks don't fit into a form
+                let post: PostEKitScheduleDay = serde_json::from_slice(&buf[..count])?;
+
+                let result = match Weekday::from_index(post.day) {
+                    Some(day) => settings.lock()?.set_day(day, post.blocks.clone()),
+                    None => Err(anyhow::anyhow!("day {} is not a day of the week", post.day)),
+                };
+                if let Err(e) = result {
+                    let reason = format!("invalid schedule: {}", e);
+                    log::warn!("rejected schedule of day {}: {}", post.day, reason);
+                    let mut res = req.into_response(400, Some("Bad Request"), &[])?;
+                    res.write_all(reason.as_bytes())?;
+                    return Ok(());
+                }
+
+                log::info!("schedule of day {} configured: {:?}", post.day, post.blocks);
+
+                req.into_ok_response()?;
+
+                Ok(())
+            })
+            .unwrap();
+
         Ok(())
     }
 }
@@ -0,0 +1,245 @@
+use crate::wear::{validated_lifetime, RELAY_LIFETIME_CYCLES};
+use truma_ekit_core::{
+    control::{SetpointBounds, SETPOINT_BOUNDS},
+    ekit::{EKitSchedule, PostEKitSetpointBounds},
+    peripherals::tmp36::Tmp36Calibration,
+    schedule::{validated_day, validated_utc_offset, DaySchedule, Presets, Schedule, Weekday},
+    storage::Storage,
+    types::TemperatureScale,
+};
+
+/// The storage key of the preferred temperature scale.
+const TEMPERATURE_SCALE_KEY: &str = "temp.scale";
+/// The storage key of the calibration of the output temperature sensor.
+const TMP36_CALIBRATION_KEY: &str = "tmp36.cal";
+/// The storage key of the lowest and highest temperatures that can be requested.
+const SETPOINT_BOUNDS_KEY: &str = "setpoint.bnd";
+/// The storage key of the offset of the local time zone from UTC.
+const UTC_OFFSET_KEY: &str = "utc.offset";
+/// The storage key of the setpoints of the schedule's presets.
+const PRESETS_KEY: &str = "sched.presets";
+/// The prefix of the storage keys of the schedule's days, followed by the number of days since Monday.
+const SCHEDULE_KEY_PREFIX: &str = "sched.";
+/// The storage key of the number of switching cycles the relays are rated for.
+const RELAY_LIFETIME_KEY: &str = "relay.life";
+
+/// The user configuration, persisted across reboots.
+pub struct Settings {
+    storage: Storage,
+    temperature_scale: TemperatureScale,
+    tmp36_calibration: Tmp36Calibration,
+    setpoint_bounds: SetpointBounds,
+    utc_offset_minutes: i32,
+    presets: Presets,
+    schedule: Schedule,
+    relay_lifetime_cycles: u64,
+}
+
+impl Settings {
+    pub fn new(storage: Storage) -> Self {
+        let temperature_scale = match storage.get(TEMPERATURE_SCALE_KEY) {
+            Ok(temperature_scale) => temperature_scale.unwrap_or_default(),
+            Err(e) => {
+                log::error!("failed to restore temperature scale ({})", e);
+                TemperatureScale::default()
+            }
+        };
+
+        let tmp36_calibration = match storage
+            .get::<Tmp36Calibration>(TMP36_CALIBRATION_KEY)
+            .and_then(|tmp36_calibration| {
+                tmp36_calibration
+                    .map(Tmp36Calibration::validated)
+                    .transpose()
+            }) {
+            Ok(tmp36_calibration) => tmp36_calibration.unwrap_or_default(),
+            Err(e) => {
+                log::error!("failed to restore TMP36 calibration ({})", e);
+                Tmp36Calibration::default()
+            }
+        };
+
+        let setpoint_bounds = match storage
+            .get::<PostEKitSetpointBounds>(SETPOINT_BOUNDS_KEY)
+            .and_then(|setpoint_bounds| {
+                setpoint_bounds
+                    .map(|setpoint_bounds| setpoint_bounds.bounds())
+                    .transpose()
+            }) {
+            Ok(setpoint_bounds) => setpoint_bounds.unwrap_or(SETPOINT_BOUNDS),
+            Err(e) => {
+                log::error!("failed to restore setpoint bounds ({})", e);
+                SETPOINT_BOUNDS
+            }
+        };
+
+        let utc_offset_minutes = match storage
+            .get::<i32>(UTC_OFFSET_KEY)
+            .and_then(|utc_offset_minutes| utc_offset_minutes.map(validated_utc_offset).transpose())
+        {
+            Ok(utc_offset_minutes) => utc_offset_minutes.unwrap_or_default(),
+            Err(e) => {
+                log::error!("failed to restore UTC offset ({})", e);
+                0
+            }
+        };
+
+        let presets = match storage
+            .get::<Presets>(PRESETS_KEY)
+            .and_then(|presets| presets.map(Presets::validated).transpose())
+        {
+            Ok(presets) => presets.unwrap_or_default(),
+            Err(e) => {
+                log::error!("failed to restore schedule presets ({})", e);
+                Presets::default()
+            }
+        };
+
+        // each day is stored separately, to stay within the maximum size of a stored value
+        let mut schedule = Schedule::default_week();
+        for day in Weekday::all() {
+            match storage
+                .get::<DaySchedule>(&schedule_key(day))
+                .and_then(|blocks| blocks.map(validated_day).transpose())
+            {
+                Ok(Some(blocks)) => schedule.set_day(day, blocks),
+                Ok(None) => {}
+                Err(e) => log::error!("failed to restore schedule of {:?} ({})", day, e),
+            }
+        }
+
+        let relay_lifetime_cycles = match storage
+            .get::<u64>(RELAY_LIFETIME_KEY)
+            .and_then(|cycles| cycles.map(validated_lifetime).transpose())
+        {
+            Ok(cycles) => cycles.unwrap_or(RELAY_LIFETIME_CYCLES),
+            Err(e) => {
+                log::error!("failed to restore relay lifetime ({})", e);
+                RELAY_LIFETIME_CYCLES
+            }
+        };
+
+        Settings {
+            storage,
+            temperature_scale,
+            tmp36_calibration,
+            setpoint_bounds,
+            utc_offset_minutes,
+            presets,
+            schedule,
+            relay_lifetime_cycles,
+        }
+    }
+
+    /// Returns the temperature scale preferred by the user.
+    pub fn temperature_scale(&self) -> TemperatureScale {
+        self.temperature_scale
+    }
+
+    /// Set the temperature scale preferred by the user.
+    pub fn set_temperature_scale(
+        &mut self,
+        temperature_scale: TemperatureScale,
+    ) -> anyhow::Result<()> {
+        self.storage
+            .set(TEMPERATURE_SCALE_KEY, &temperature_scale)?;
+        self.temperature_scale = temperature_scale;
+        Ok(())
+    }
+
+    /// Returns the calibration of the output temperature sensor.
+    pub fn tmp36_calibration(&self) -> Tmp36Calibration {
+        self.tmp36_calibration
+    }
+
+    /// Set the calibration of the output temperature sensor.
+    ///
+    /// Returns an error if the calibration is out of range.
+    pub fn set_tmp36_calibration(
+        &mut self,
+        tmp36_calibration: Tmp36Calibration,
+    ) -> anyhow::Result<()> {
+        let tmp36_calibration = tmp36_calibration.validated()?;
+        self.storage
+            .set(TMP36_CALIBRATION_KEY, &tmp36_calibration)?;
+        self.tmp36_calibration = tmp36_calibration;
+        Ok(())
+    }
+
+    /// Returns the lowest and highest temperatures that can be requested.
+    pub fn setpoint_bounds(&self) -> SetpointBounds {
+        self.setpoint_bounds
+    }
+
+    /// Set the lowest and highest temperatures that can be requested.
+    pub fn set_setpoint_bounds(&mut self, setpoint_bounds: SetpointBounds) -> anyhow::Result<()> {
+        self.storage.set(
+            SETPOINT_BOUNDS_KEY,
+            &PostEKitSetpointBounds::from(setpoint_bounds),
+        )?;
+        self.setpoint_bounds = setpoint_bounds;
+        Ok(())
+    }
+
+    /// Returns the offset of the local time zone from UTC, in minutes.
+    pub fn utc_offset_minutes(&self) -> i32 {
+        self.utc_offset_minutes
+    }
+
+    /// Set the offset of the local time zone from UTC, in minutes.
+    ///
+    /// Returns an error if the offset is out of range.
+    pub fn set_utc_offset_minutes(&mut self, utc_offset_minutes: i32) -> anyhow::Result<()> {
+        let utc_offset_minutes = validated_utc_offset(utc_offset_minutes)?;
+        if utc_offset_minutes != self.utc_offset_minutes {
+            self.storage.set(UTC_OFFSET_KEY, &utc_offset_minutes)?;
+            self.utc_offset_minutes = utc_offset_minutes;
+        }
+        Ok(())
+    }
+
+    /// Returns the heating schedule and the setpoints of its presets.
+    pub fn schedule(&self) -> EKitSchedule {
+        EKitSchedule::new(&self.schedule, self.presets)
+    }
+
+    /// Set the setpoints of the schedule's presets.
+    ///
+    /// Returns an error if a setpoint isn't a plausible ambient temperature.
+    pub fn set_presets(&mut self, presets: Presets) -> anyhow::Result<()> {
+        let presets = presets.validated()?;
+        self.storage.set(PRESETS_KEY, &presets)?;
+        self.presets = presets;
+        Ok(())
+    }
+
+    /// Replace the blocks of the given day of the schedule.
+    ///
+    /// Returns an error if there are too many blocks, or one doesn't start within the day.
+    pub fn set_day(&mut self, day: Weekday, blocks: DaySchedule) -> anyhow::Result<()> {
+        let blocks = validated_day(blocks)?;
+        self.storage.set(&schedule_key(day), &blocks)?;
+        self.schedule.set_day(day, blocks);
+        Ok(())
+    }
+
+    /// Returns the number of switching cycles the relays are rated for.
+    pub fn relay_lifetime_cycles(&self) -> u64 {
+        self.relay_lifetime_cycles
+    }
+
+    /// Set the number of switching cycles the relays are rated for.
+    ///
+    /// Returns an error if the number of cycles is out of range.
+    pub fn set_relay_lifetime_cycles(&mut self, cycles: u64) -> anyhow::Result<()> {
+        let cycles = validated_lifetime(cycles)?;
+        self.storage.set(RELAY_LIFETIME_KEY, &cycles)?;
+        self.relay_lifetime_cycles = cycles;
+        Ok(())
+    }
+}
+
+/// Returns the storage key of the given day of the schedule.
+fn schedule_key(day: Weekday) -> String {
+    format!("{}{}", SCHEDULE_KEY_PREFIX, day.index())
+}
@@ -0,0 +1,219 @@
+use std::time::{Duration, Instant};
+use truma_ekit_core::{
+    control::{
+        ControlInputs, ControlReason, ControlStrategy, SetpointBounds, SetpointError,
+        CONTROL_STRATEGY,
+    },
+    ekit::EKitUserRunMode,
+    types::{Temperature, TemperatureDelta},
+};
+
+/// The maximum age of the ambient temperature used to run the e-kit.
+/// If no recent ambient temperature has been reported, the e-kit is turned off until reports return.
+const MAX_AMBIENT_AGE: Duration = Duration::from_secs(2 * 60);
+
+/// Runs the e-kit on the controller, based on the requested temperature and the reported ambient temperature.
+pub struct Thermostat {
+    setpoint: Temperature,
+    setpoint_bounds: SetpointBounds,
+    strategy: Box<dyn ControlStrategy + Send>,
+    ambient_unavailable: bool,
+    reason: Option<ControlReason>,
+}
+
+impl Thermostat {
+    /// Returns an error if the requested temperature is out of the setpoint bounds.
+    pub fn new(
+        setpoint: Temperature,
+        setpoint_bounds: SetpointBounds,
+    ) -> Result<Self, SetpointError> {
+        Ok(Thermostat {
+            setpoint: setpoint_bounds.validated(setpoint)?,
+            setpoint_bounds,
+            strategy: Box::new(CONTROL_STRATEGY),
+            ambient_unavailable: false,
+            reason: None,
+        })
+    }
+
+    /// Get the requested temperature.
+    pub fn setpoint(&self) -> Temperature {
+        self.setpoint
+    }
+
+    /// Set the requested temperature.
+    ///
+    /// Returns an error (keeping the current requested temperature) if it's out of the setpoint bounds.
+    pub fn set_setpoint(&mut self, setpoint: Temperature) -> Result<(), SetpointError> {
+        self.setpoint = self.setpoint_bounds.validated(setpoint)?;
+        Ok(())
+    }
+
+    /// Set the lowest and highest temperatures that can be requested.
+    ///
+    /// The requested temperature is kept within the new bounds.
+    pub fn set_setpoint_bounds(&mut self, setpoint_bounds: SetpointBounds) {
+        self.setpoint_bounds = setpoint_bounds;
+        self.setpoint = setpoint_bounds.clamped(self.setpoint);
+    }
+
+    /// Returns `true` if the e-kit has been turned off because no recent ambient temperature has been reported.
+    pub fn is_ambient_unavailable(&self) -> bool {
+        self.ambient_unavailable
+    }
+
+    /// Get the run mode to request at `now`, based on the ambient temperature reported (and when it was measured), its
+    /// rate of change (if known) and the run mode currently in effect.
+    ///
+    /// Requests `Off` while the ambient temperature is outdated, rather than running on an outdated reading.
+    pub fn control(
+        &mut self,
+        reported_ambient: Option<(Instant, Temperature)>,
+        rate_of_change: Option<TemperatureDelta>,
+        current_mode: EKitUserRunMode,
+        now: Instant,
+    ) -> EKitUserRunMode {
+        let ambient = reported_ambient
+            .filter(|(measured_at, _)| {
+                now.saturating_duration_since(*measured_at) <= MAX_AMBIENT_AGE
+            })
+            .map(|(_, temperature)| temperature);
+        match ambient {
+            Some(ambient) => {
+                if self.ambient_unavailable {
+                    log::info!("ambient temperature reports returned, resuming");
+                    self.ambient_unavailable = false;
+                }
+                let decision = self.strategy.decide(&ControlInputs {
+                    setpoint: self.setpoint,
+                    ambient,
+                    rate_of_change,
+                    current_mode,
+                    now,
+                });
+                if self.reason != Some(decision.reason) {
+                    log::info!(
+                        "{} strategy requests {:?}: {:?}",
+                        self.strategy.name(),
+                        decision.run_mode,
+                        decision.reason
+                    );
+                    self.reason = Some(decision.reason);
+                }
+                decision.run_mode
+            }
+            None => {
+                if !self.ambient_unavailable {
+                    log::warn!("no recent ambient temperature reported, turning off the e-kit");
+                    self.ambient_unavailable = true;
+                }
+                self.reason = None;
+                EKitUserRunMode::Off
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use truma_ekit_core::{control::SETPOINT_BOUNDS, util::celsius};
+
+    #[test]
+    fn runs_on_recent_ambient_temperature() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut sub = Thermostat::new(celsius(21.0), SETPOINT_BOUNDS).unwrap();
+
+        assert_eq!(
+            sub.control(
+                Some((at(0), celsius(18.0))),
+                None,
+                EKitUserRunMode::Off,
+                at(0)
+            ),
+            EKitUserRunMode::Full
+        );
+        assert_eq!(
+            sub.control(
+                Some((at(0), celsius(20.0))),
+                None,
+                EKitUserRunMode::Off,
+                at(120)
+            ),
+            EKitUserRunMode::Half
+        );
+        assert!(!sub.is_ambient_unavailable());
+
+        sub.set_setpoint(celsius(19.0)).unwrap();
+        assert_eq!(
+            sub.control(
+                Some((at(100), celsius(20.0))),
+                None,
+                EKitUserRunMode::Off,
+                at(120)
+            ),
+            EKitUserRunMode::Off
+        );
+    }
+
+    #[test]
+    fn rejects_setpoint_out_of_bounds() {
+        assert!(matches!(
+            Thermostat::new(celsius(-40.0), SETPOINT_BOUNDS),
+            Err(SetpointError::BelowMinimum { .. })
+        ));
+
+        let mut sub = Thermostat::new(celsius(21.0), SETPOINT_BOUNDS).unwrap();
+        assert!(matches!(
+            sub.set_setpoint(celsius(80.0)),
+            Err(SetpointError::AboveMaximum { .. })
+        ));
+        assert_eq!(sub.setpoint(), celsius(21.0));
+        assert!(matches!(
+            sub.set_setpoint(celsius(f32::NAN)),
+            Err(SetpointError::Invalid { .. })
+        ));
+        assert_eq!(sub.setpoint(), celsius(21.0));
+        assert!(sub.set_setpoint(celsius(30.0)).is_ok());
+        assert_eq!(sub.setpoint(), celsius(30.0));
+
+        sub.set_setpoint_bounds(SetpointBounds::new(celsius(10.0), celsius(25.0)));
+        assert_eq!(sub.setpoint(), celsius(25.0));
+        assert!(sub.set_setpoint(celsius(26.0)).is_err());
+    }
+
+    #[test]
+    fn turns_off_on_outdated_ambient_temperature() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut sub = Thermostat::new(celsius(21.0), SETPOINT_BOUNDS).unwrap();
+
+        assert_eq!(
+            sub.control(None, None, EKitUserRunMode::Off, at(0)),
+            EKitUserRunMode::Off
+        );
+        assert!(sub.is_ambient_unavailable());
+
+        assert_eq!(
+            sub.control(
+                Some((at(0), celsius(18.0))),
+                None,
+                EKitUserRunMode::Off,
+                at(10)
+            ),
+            EKitUserRunMode::Full
+        );
+        assert!(!sub.is_ambient_unavailable());
+        assert_eq!(
+            sub.control(
+                Some((at(0), celsius(18.0))),
+                None,
+                EKitUserRunMode::Off,
+                at(121)
+            ),
+            EKitUserRunMode::Off
+        );
+        assert!(sub.is_ambient_unavailable());
+    }
+}
@@ -0,0 +1,197 @@
+use anyhow::anyhow;
+use std::time::Duration;
+use truma_ekit_core::{
+    ekit::{EKitEvent, EKitFault, EKitRelay},
+    events::EventBus,
+    peripherals::relay::RelayStats,
+    storage::Storage,
+    throttle::Throttle,
+};
+
+/// The number of switching cycles the relays are rated for, unless configured otherwise.
+pub const RELAY_LIFETIME_CYCLES: u64 = 100_000;
+/// The lowest and highest number of switching cycles the relays can be configured to be rated for.
+const RELAY_LIFETIME_CYCLES_RANGE: (u64, u64) = (1_000, 10_000_000);
+/// A maintenance warning is raised once this percentage of the relay lifetime has been used.
+const MAINTENANCE_WARNING_PERCENT: u64 = 90;
+/// The interval at which the relay usage is persisted.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// The storage key of the relays a maintenance warning has been raised for.
+const WARNED_KEY: &str = "relay.warned";
+
+/// Keeps track of the wear of the e-kit relays across reboots.
+pub struct RelayWear {
+    storage: Storage,
+    throttle: Throttle,
+    warnings: MaintenanceWarnings,
+}
+
+impl RelayWear {
+    pub fn new(storage: Storage) -> Self {
+        let warned = match storage.get(WARNED_KEY) {
+            Ok(warned) => warned.unwrap_or_default(),
+            Err(e) => {
+                log::error!("failed to restore relay maintenance warnings ({})", e);
+                [false; 3]
+            }
+        };
+        RelayWear {
+            storage,
+            throttle: Throttle::one_run_per(PERSIST_INTERVAL),
+            warnings: MaintenanceWarnings { warned },
+        }
+    }
+
+    /// Returns the persisted usage of the relay.
+    pub fn restore(&self, relay: EKitRelay) -> RelayStats {
+        match self.storage.get(&Self::key(relay)) {
+            Ok(stats) => stats.unwrap_or_default(),
+            Err(e) => {
+                log::error!("failed to restore {} relay usage ({})", relay.name(), e);
+                RelayStats::default()
+            }
+        }
+    }
+
+    /// Update the relay usage.
+    ///
+    /// The usage is persisted periodically, and a fault is published once for every relay that is due for maintenance
+    /// given the number of switching cycles the relays are rated for.
+    pub fn update(
+        &mut self,
+        stats: [(EKitRelay, RelayStats); 3],
+        lifetime_cycles: u64,
+        events: &EventBus<EKitEvent>,
+    ) {
+        let warnings = self.warnings;
+        for (relay, stats) in self.warnings.update(&stats, lifetime_cycles) {
+            log::warn!(
+                "{} relay is due for maintenance ({} cycles)",
+                relay.name(),
+                stats.cycles
+            );
+            events.publish(EKitEvent::Fault {
+                fault: EKitFault::RelayMaintenanceDue { relay },
+            });
+        }
+        // persisted right away, so the warnings aren't raised again after a reboot
+        if self.warnings != warnings {
+            if let Err(e) = self.storage.set(WARNED_KEY, &self.warnings.warned) {
+                log::error!("failed to persist relay maintenance warnings ({})", e);
+            }
+        }
+
+        let storage = &mut self.storage;
+        self.throttle.throttle(|| {
+            for (relay, stats) in stats {
+                if let Err(e) = storage.set(&Self::key(relay), &stats) {
+                    log::error!("failed to persist {} relay usage ({})", relay.name(), e);
+                }
+            }
+        });
+    }
+
+    fn key(relay: EKitRelay) -> String {
+        format!("relay.{}", relay.name())
+    }
+}
+
+/// The relays a maintenance warning has been raised for.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+struct MaintenanceWarnings {
+    warned: [bool; 3],
+}
+
+impl MaintenanceWarnings {
+    /// Update the warnings, returning the relays (and their usage) that are newly due for maintenance.
+    ///
+    /// A relay that is no longer due (e.g. as its rated lifetime was raised) is warned about again once it's due.
+    fn update(
+        &mut self,
+        stats: &[(EKitRelay, RelayStats); 3],
+        lifetime_cycles: u64,
+    ) -> Vec<(EKitRelay, RelayStats)> {
+        let mut due = Vec::new();
+        for ((relay, stats), warned) in stats.iter().zip(self.warned.iter_mut()) {
+            let is_due = is_maintenance_due(stats, lifetime_cycles);
+            if is_due && !*warned {
+                due.push((*relay, *stats));
+            }
+            *warned = is_due;
+        }
+        due
+    }
+}
+
+/// Returns `true` if the relay is approaching the end of its rated lifetime.
+fn is_maintenance_due(stats: &RelayStats, lifetime_cycles: u64) -> bool {
+    stats.cycles * 100 >= lifetime_cycles * MAINTENANCE_WARNING_PERCENT
+}
+
+/// Returns the number of switching cycles the relays are rated for if it's within the accepted range, or an error
+/// otherwise.
+pub fn validated_lifetime(cycles: u64) -> anyhow::Result<u64> {
+    let (min, max) = RELAY_LIFETIME_CYCLES_RANGE;
+    if !(min..=max).contains(&cycles) {
+        return Err(anyhow!(
+            "relay lifetime of {} cycles is outside of {} to {} cycles",
+            cycles,
+            min,
+            max
+        ));
+    }
+    Ok(cycles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maintenance_due_when_lifetime_is_approached() {
+        let stats = |cycles| RelayStats {
+            cycles,
+            closed_duration: Duration::ZERO,
+        };
+        assert!(!is_maintenance_due(&stats(0), RELAY_LIFETIME_CYCLES));
+        assert!(!is_maintenance_due(&stats(89_999), RELAY_LIFETIME_CYCLES));
+        assert!(is_maintenance_due(&stats(90_000), RELAY_LIFETIME_CYCLES));
+        assert!(is_maintenance_due(&stats(150_000), RELAY_LIFETIME_CYCLES));
+        assert!(!is_maintenance_due(&stats(150_000), 200_000));
+    }
+
+    #[test]
+    fn warns_once_per_relay() {
+        let stats = |coil1| {
+            let stats = |cycles| RelayStats {
+                cycles,
+                closed_duration: Duration::ZERO,
+            };
+            [
+                (EKitRelay::Fan, stats(10)),
+                (EKitRelay::Coil1, stats(coil1)),
+                (EKitRelay::Coil2, stats(10)),
+            ]
+        };
+        let mut warnings = MaintenanceWarnings::default();
+
+        assert!(warnings.update(&stats(89_999), 100_000).is_empty());
+        let due = warnings.update(&stats(90_000), 100_000);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0, EKitRelay::Coil1);
+        assert!(warnings.update(&stats(90_001), 100_000).is_empty());
+        assert_eq!(warnings.warned, [false, true, false]);
+
+        // warned about again once due given a raised lifetime
+        assert!(warnings.update(&stats(90_001), 200_000).is_empty());
+        assert_eq!(warnings.warned, [false, false, false]);
+        assert_eq!(warnings.update(&stats(180_000), 200_000).len(), 1);
+    }
+
+    #[test]
+    fn validates_lifetime() {
+        assert_eq!(validated_lifetime(RELAY_LIFETIME_CYCLES).unwrap(), 100_000);
+        assert!(validated_lifetime(0).is_err());
+        assert!(validated_lifetime(100_000_000).is_err());
+    }
+}
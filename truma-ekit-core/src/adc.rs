@@ -2,6 +2,10 @@ use esp_idf_hal::{
     adc::{Adc, AdcChannelDriver, AdcDriver, Attenuation},
     gpio::ADCPin,
 };
+use std::{cell::RefCell, rc::Rc};
+
+/// An ADC driver shared by multiple input pins of the same ADC.
+pub type SharedAdcDriver<'a, ADC> = Rc<RefCell<AdcDriver<'a, ADC>>>;
 
 pub struct AdcInputPin<'a> {
     input: Box<dyn AdcInput + 'a>,
@@ -23,13 +27,37 @@ impl<'a> AdcInputPin<'a> {
         }
     }
 
-    #[cfg(test)]
+    /// Returns an input pin read using a driver shared with other input pins.
+    pub fn shared_pin<P, ADC, ATTEN>(pin: P, driver: SharedAdcDriver<'a, ADC>) -> Self
+    where
+        P: ADCPin,
+        ADC: Adc,
+        ATTEN: Attenuation<<P as ADCPin>::Adc> + 'a,
+    {
+        let input = SharedAdcDriverAndChannelDriver::<_, _, ATTEN> {
+            driver,
+            channel_driver: AdcChannelDriver::new(pin).unwrap(),
+        };
+        AdcInputPin {
+            input: Box::new(input),
+        }
+    }
+
+    #[cfg(any(test, feature = "testing"))]
     pub fn test(value: u16) -> Self {
         AdcInputPin {
             input: Box::new(TestAdcInput(value)),
         }
     }
 
+    /// Returns an input pin replaying the given samples in order, failing once they run out.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn replay(samples: impl IntoIterator<Item = u16>) -> Self {
+        AdcInputPin {
+            input: Box::new(ReplayAdcInput(samples.into_iter().collect())),
+        }
+    }
+
     pub fn read(&mut self) -> anyhow::Result<u16> {
         self.input.read()
     }
@@ -61,6 +89,28 @@ where
     }
 }
 
+struct SharedAdcDriverAndChannelDriver<'a, ADC, GP, ATTEN>
+where
+    ADC: Adc,
+    GP: ADCPin,
+    ATTEN: Attenuation<<GP as ADCPin>::Adc>,
+{
+    driver: SharedAdcDriver<'a, ADC>,
+    channel_driver: AdcChannelDriver<'a, GP, ATTEN>,
+}
+
+impl<'a, ADC, GP, ATTEN> AdcInput for SharedAdcDriverAndChannelDriver<'a, ADC, GP, ATTEN>
+where
+    ADC: Adc,
+    GP: ADCPin,
+    ATTEN: Attenuation<<GP as ADCPin>::Adc>,
+{
+    fn read(&mut self) -> anyhow::Result<u16> {
+        let val = self.driver.borrow_mut().read(&mut self.channel_driver)?;
+        Ok(val)
+    }
+}
+
 struct TestAdcInput(u16);
 
 impl AdcInput for TestAdcInput {
@@ -68,3 +118,15 @@ impl AdcInput for TestAdcInput {
         Ok(self.0)
     }
 }
+
+#[cfg(any(test, feature = "testing"))]
+struct ReplayAdcInput(std::collections::VecDeque<u16>);
+
+#[cfg(any(test, feature = "testing"))]
+impl AdcInput for ReplayAdcInput {
+    fn read(&mut self) -> anyhow::Result<u16> {
+        self.0
+            .pop_front()
+            .ok_or_else(|| anyhow::anyhow!("no more ADC samples to replay"))
+    }
+}
@@ -0,0 +1,17 @@
+use std::time::Instant;
+
+/// A source of the current time, so the logic depending on it can be driven by a fake clock in tests.
+pub trait Clock: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+}
+
+/// The monotonic clock of the system.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
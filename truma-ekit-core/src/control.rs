@@ -0,0 +1,412 @@
+use crate::{
+    ekit::EKitUserRunMode,
+    types::{Temperature, TemperatureDelta, UnitTemperature},
+    util::{celsius, celsius_delta},
+};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// The threshold for running the controller at full capacity.
+/// If the temperature difference is below this value, the controller will be run at half capacity.
+pub const FULL_CAPACITY_TRESHOLD: TemperatureDelta = celsius_delta(1.5);
+/// The strategy deciding on the run mode, on the thermostat and on the controller.
+pub const CONTROL_STRATEGY: ThresholdStrategy = ThresholdStrategy::new();
+/// The minimum time span of the readings needed to determine the rate of change of the actual temperature.
+pub const MIN_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// The range of ambient temperatures (in °C) considered plausible.
+/// Readings outside of it (e.g. of a disconnected sensor) are treated as invalid, rather than heated on.
+pub const PLAUSIBLE_AMBIENT_CELSIUS: (f32, f32) = (-40.0, 60.0);
+
+/// Returns `true` if the ambient temperature is plausible, i.e. finite and within [`PLAUSIBLE_AMBIENT_CELSIUS`].
+pub fn is_plausible_ambient(temperature: Temperature) -> bool {
+    let (min, max) = PLAUSIBLE_AMBIENT_CELSIUS;
+    let celsius = temperature.converted_to(UnitTemperature::celsius()).value;
+    (min..=max).contains(&celsius)
+}
+
+/// The lowest and highest temperatures that can be requested, on the thermostat and from the controller.
+pub const SETPOINT_BOUNDS: SetpointBounds = SetpointBounds::new(celsius(5.0), celsius(30.0));
+
+/// The lowest and highest temperatures that can be requested.
+#[derive(Copy, Clone, Debug)]
+pub struct SetpointBounds {
+    min: Temperature,
+    max: Temperature,
+}
+
+impl SetpointBounds {
+    pub const fn new(min: Temperature, max: Temperature) -> Self {
+        SetpointBounds { min, max }
+    }
+
+    /// Returns the bounds (in °C), e.g. as configured by the user.
+    ///
+    /// Returns an error if they're not finite, not plausible ambient temperatures, or the minimum isn't below the
+    /// maximum.
+    pub fn from_celsius(min_celsius: f32, max_celsius: f32) -> anyhow::Result<Self> {
+        let (min, max) = (celsius(min_celsius), celsius(max_celsius));
+        anyhow::ensure!(
+            is_plausible_ambient(min) && is_plausible_ambient(max),
+            "setpoint bounds {} °C to {} °C are out of the range of {} °C to {} °C",
+            min_celsius,
+            max_celsius,
+            PLAUSIBLE_AMBIENT_CELSIUS.0,
+            PLAUSIBLE_AMBIENT_CELSIUS.1
+        );
+        anyhow::ensure!(
+            min < max,
+            "setpoint minimum {} °C is not below the maximum {} °C",
+            min_celsius,
+            max_celsius
+        );
+        Ok(SetpointBounds::new(min, max))
+    }
+
+    /// Returns the lowest temperature that can be requested.
+    pub fn min(&self) -> Temperature {
+        self.min
+    }
+
+    /// Returns the highest temperature that can be requested.
+    pub fn max(&self) -> Temperature {
+        self.max
+    }
+
+    /// Returns the setpoint, limited to the bounds.
+    ///
+    /// An invalid (NaN) setpoint is limited to the minimum.
+    pub fn clamped(&self, setpoint: Temperature) -> Temperature {
+        if setpoint.value.is_nan() || setpoint < self.min {
+            self.min
+        } else if setpoint > self.max {
+            self.max
+        } else {
+            setpoint
+        }
+    }
+
+    /// Returns the setpoint if it's within the bounds, or an error otherwise.
+    pub fn validated(&self, setpoint: Temperature) -> Result<Temperature, SetpointError> {
+        let celsius =
+            |temperature: Temperature| temperature.converted_to(UnitTemperature::celsius()).value;
+        if !setpoint.value.is_finite() {
+            Err(SetpointError::Invalid {
+                setpoint_celsius: celsius(setpoint),
+            })
+        } else if setpoint < self.min {
+            Err(SetpointError::BelowMinimum {
+                setpoint_celsius: celsius(setpoint),
+                min_celsius: celsius(self.min),
+            })
+        } else if setpoint > self.max {
+            Err(SetpointError::AboveMaximum {
+                setpoint_celsius: celsius(setpoint),
+                max_celsius: celsius(self.max),
+            })
+        } else {
+            Ok(setpoint)
+        }
+    }
+}
+
+/// A setpoint that's not a number, or outside of the setpoint bounds.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SetpointError {
+    Invalid {
+        setpoint_celsius: f32,
+    },
+    BelowMinimum {
+        setpoint_celsius: f32,
+        min_celsius: f32,
+    },
+    AboveMaximum {
+        setpoint_celsius: f32,
+        max_celsius: f32,
+    },
+}
+
+impl fmt::Display for SetpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetpointError::Invalid { setpoint_celsius } => {
+                write!(f, "setpoint {} °C is invalid", setpoint_celsius)
+            }
+            SetpointError::BelowMinimum {
+                setpoint_celsius,
+                min_celsius,
+            } => write!(
+                f,
+                "setpoint {:.1} °C is below the minimum of {:.1} °C",
+                setpoint_celsius, min_celsius
+            ),
+            SetpointError::AboveMaximum {
+                setpoint_celsius,
+                max_celsius,
+            } => write!(
+                f,
+                "setpoint {:.1} °C is above the maximum of {:.1} °C",
+                setpoint_celsius, max_celsius
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SetpointError {}
+
+/// Returns the rate of change of the temperature per minute, fitted through the given readings (oldest first).
+///
+/// Returns `None` if the readings don't span [`MIN_RATE_WINDOW`].
+pub fn rate_of_change(
+    readings: impl IntoIterator<Item = (Instant, Temperature)>,
+) -> Option<TemperatureDelta> {
+    let readings: Vec<_> = readings.into_iter().collect();
+    let (first_at, _) = *readings.first()?;
+    let (last_at, _) = *readings.last()?;
+    if last_at.saturating_duration_since(first_at) < MIN_RATE_WINDOW {
+        return None;
+    }
+
+    // least squares fit of the temperature (°C) against the time (minutes)
+    let points: Vec<(f32, f32)> = readings
+        .iter()
+        .map(|(read_at, temperature)| {
+            (
+                read_at.saturating_duration_since(first_at).as_secs_f32() / 60.0,
+                temperature.converted_to(UnitTemperature::celsius()).value,
+            )
+        })
+        .collect();
+    let n = points.len() as f32;
+    let mean_t = points.iter().map(|(t, _)| t).sum::<f32>() / n;
+    let mean_v = points.iter().map(|(_, v)| v).sum::<f32>() / n;
+    let covariance: f32 = points
+        .iter()
+        .map(|(t, v)| (t - mean_t) * (v - mean_v))
+        .sum();
+    let variance: f32 = points.iter().map(|(t, _)| (t - mean_t).powi(2)).sum();
+    Some(celsius_delta(covariance / variance))
+}
+
+/// The inputs a control strategy decides on.
+#[derive(Copy, Clone, Debug)]
+pub struct ControlInputs {
+    /// The requested temperature.
+    pub setpoint: Temperature,
+    /// The actual temperature.
+    pub ambient: Temperature,
+    /// The rate of change of the actual temperature per minute, if known.
+    pub rate_of_change: Option<TemperatureDelta>,
+    /// The run mode currently requested.
+    pub current_mode: EKitUserRunMode,
+    pub now: Instant,
+}
+
+/// The reason a control strategy decided on a run mode.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ControlReason {
+    /// The actual temperature reached the requested temperature.
+    SetpointReached,
+    /// The actual temperature is slightly below the requested temperature.
+    BelowSetpoint,
+    /// The actual temperature is far below the requested temperature.
+    FarBelowSetpoint,
+}
+
+/// The run mode decided on by a control strategy, and why.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct ControlDecision {
+    pub run_mode: EKitUserRunMode,
+    pub reason: ControlReason,
+}
+
+/// Decides on the run mode needed to reach the requested temperature.
+///
+/// Shared by the thermostat and the controller, so both decide alike, and strategies can be swapped.
+pub trait ControlStrategy {
+    /// Returns the name of the strategy, e.g. for logging.
+    fn name(&self) -> &'static str;
+
+    /// Decide on the run mode for the given inputs.
+    fn decide(&self, inputs: &ControlInputs) -> ControlDecision;
+}
+
+/// Heats at full capacity if the actual temperature is far below the requested temperature, or at half capacity if
+/// it's slightly below.
+#[derive(Copy, Clone, Debug)]
+pub struct ThresholdStrategy {
+    full_capacity_threshold: TemperatureDelta,
+}
+
+impl Default for ThresholdStrategy {
+    fn default() -> Self {
+        ThresholdStrategy::new()
+    }
+}
+
+impl ThresholdStrategy {
+    pub const fn new() -> Self {
+        ThresholdStrategy {
+            full_capacity_threshold: FULL_CAPACITY_TRESHOLD,
+        }
+    }
+
+    /// Use a different threshold for running at full capacity.
+    pub const fn with_full_capacity_threshold(
+        mut self,
+        full_capacity_threshold: TemperatureDelta,
+    ) -> Self {
+        self.full_capacity_threshold = full_capacity_threshold;
+        self
+    }
+}
+
+impl ControlStrategy for ThresholdStrategy {
+    fn name(&self) -> &'static str {
+        "threshold"
+    }
+
+    fn decide(&self, inputs: &ControlInputs) -> ControlDecision {
+        if inputs.ambient >= inputs.setpoint {
+            // the actual temperature is equal to or higher than the requested temperature, turn off the heating
+            ControlDecision {
+                run_mode: EKitUserRunMode::Off,
+                reason: ControlReason::SetpointReached,
+            }
+        } else {
+            // the actual temperature is less than the requested temperature, turn on the heating
+            let temp_diff = inputs.setpoint - inputs.ambient;
+            if temp_diff < self.full_capacity_threshold {
+                // run the heating at half capacity
+                ControlDecision {
+                    run_mode: EKitUserRunMode::Half,
+                    reason: ControlReason::BelowSetpoint,
+                }
+            } else {
+                // run the heating at full capacity
+                ControlDecision {
+                    run_mode: EKitUserRunMode::Full,
+                    reason: ControlReason::FarBelowSetpoint,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::celsius;
+
+    fn inputs(ambient: f32) -> ControlInputs {
+        ControlInputs {
+            setpoint: celsius(21.0),
+            ambient: celsius(ambient),
+            rate_of_change: None,
+            current_mode: EKitUserRunMode::Off,
+            now: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn threshold_strategy_decides() {
+        let sub = ThresholdStrategy::new();
+        assert_eq!(
+            sub.decide(&inputs(21.0)),
+            ControlDecision {
+                run_mode: EKitUserRunMode::Off,
+                reason: ControlReason::SetpointReached,
+            }
+        );
+        assert_eq!(
+            sub.decide(&inputs(19.6)),
+            ControlDecision {
+                run_mode: EKitUserRunMode::Half,
+                reason: ControlReason::BelowSetpoint,
+            }
+        );
+        assert_eq!(
+            sub.decide(&inputs(19.5)),
+            ControlDecision {
+                run_mode: EKitUserRunMode::Full,
+                reason: ControlReason::FarBelowSetpoint,
+            }
+        );
+    }
+
+    #[test]
+    fn plausible_ambient() {
+        assert!(is_plausible_ambient(celsius(21.0)));
+        assert!(is_plausible_ambient(celsius(-40.0)));
+        assert!(is_plausible_ambient(celsius(60.0)));
+        assert!(!is_plausible_ambient(celsius(-50.0)));
+        assert!(!is_plausible_ambient(celsius(f32::NAN)));
+        assert!(!is_plausible_ambient(celsius(f32::NEG_INFINITY)));
+        assert!(!is_plausible_ambient(celsius(-1e30)));
+    }
+
+    #[test]
+    fn clamps_setpoint() {
+        let sub = SETPOINT_BOUNDS;
+        assert_eq!(sub.clamped(celsius(-40.0)), celsius(5.0));
+        assert_eq!(sub.clamped(celsius(21.0)), celsius(21.0));
+        assert_eq!(sub.clamped(celsius(80.0)), celsius(30.0));
+        assert_eq!(sub.clamped(celsius(f32::NAN)), celsius(5.0));
+        assert_eq!(sub.clamped(celsius(f32::INFINITY)), celsius(30.0));
+    }
+
+    #[test]
+    fn validates_setpoint() {
+        let sub = SetpointBounds::new(celsius(10.0), celsius(25.0));
+        assert_eq!(sub.validated(celsius(10.0)), Ok(celsius(10.0)));
+        assert_eq!(sub.validated(celsius(25.0)), Ok(celsius(25.0)));
+        assert_eq!(
+            sub.validated(celsius(9.9)),
+            Err(SetpointError::BelowMinimum {
+                setpoint_celsius: 9.9,
+                min_celsius: 10.0,
+            })
+        );
+        let err = sub.validated(celsius(80.0)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "setpoint 80.0 °C is above the maximum of 25.0 °C"
+        );
+        assert!(matches!(
+            sub.validated(celsius(f32::NAN)),
+            Err(SetpointError::Invalid { .. })
+        ));
+        assert_eq!(
+            sub.validated(celsius(f32::INFINITY)),
+            Err(SetpointError::Invalid {
+                setpoint_celsius: f32::INFINITY,
+            })
+        );
+    }
+
+    #[test]
+    fn setpoint_bounds_from_celsius() {
+        let sub = SetpointBounds::from_celsius(10.0, 25.0).unwrap();
+        assert_eq!(sub.min(), celsius(10.0));
+        assert_eq!(sub.max(), celsius(25.0));
+        assert!(SetpointBounds::from_celsius(25.0, 10.0).is_err());
+        assert!(SetpointBounds::from_celsius(20.0, 20.0).is_err());
+        assert!(SetpointBounds::from_celsius(f32::NAN, 25.0).is_err());
+        assert!(SetpointBounds::from_celsius(10.0, 80.0).is_err());
+    }
+
+    #[test]
+    fn strategies_can_be_swapped() {
+        let strategies: [Box<dyn ControlStrategy>; 2] = [
+            Box::new(ThresholdStrategy::new()),
+            Box::new(ThresholdStrategy::new().with_full_capacity_threshold(celsius_delta(3.0))),
+        ];
+        let run_modes = strategies
+            .iter()
+            .map(|strategy| strategy.decide(&inputs(19.0)).run_mode)
+            .collect::<Vec<_>>();
+        assert_eq!(run_modes, [EKitUserRunMode::Full, EKitUserRunMode::Half]);
+    }
+}
@@ -1,6 +1,14 @@
+use crate::{
+    control::SetpointBounds,
+    measurement::Measurement,
+    schedule::{validated_day, DaySchedule, Presets, Schedule, Weekday},
+    types::{Energy, Power, Temperature, TemperatureScale, UnitTemperature},
+    util::{watt_hours, watts},
+};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum EKitSystemRunMode {
     Off,
     Cooldown,
@@ -12,17 +20,292 @@ pub enum EKitSystemRunMode {
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum EKitUserRunMode {
     Off,
+    /// Off, but the controller heats to keep the ambient temperature above the frost protection setpoint.
+    FrostProtection,
     Cool,
     Half,
     Full,
 }
 
+impl EKitUserRunMode {
+    /// Returns all e-kit user run modes.
+    pub const fn all() -> [EKitUserRunMode; 5] {
+        [
+            EKitUserRunMode::Off,
+            EKitUserRunMode::FrostProtection,
+            EKitUserRunMode::Cool,
+            EKitUserRunMode::Half,
+            EKitUserRunMode::Full,
+        ]
+    }
+}
+
 pub trait EKit {
     /// Request the e-kit user run mode.
     fn request_user_run_mode(&mut self, run_mode: EKitUserRunMode);
+
+    /// Request running at full capacity for the given duration, reverting to the requested user run mode afterwards.
+    ///
+    /// A zero duration ends the boost early.
+    fn request_boost(&mut self, duration: Duration);
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PostEKitRunMode {
     pub run_mode: EKitUserRunMode,
+    /// `true` if the thermostat repeats the run mode it decided on, as it does periodically, rather than the user
+    /// requesting it. Periodic requests don't end running on a setpoint.
+    #[serde(default)]
+    pub periodic: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PostEKitBoost {
+    /// The duration of the boost (in s), or 0 to end the boost.
+    pub duration_secs: u64,
+}
+
+/// The user configuration, stored by the controller.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PostEKitConfig {
+    pub temperature_scale: TemperatureScale,
+}
+
+/// The ambient conditions, as measured by the thermostat.
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct PostEKitAmbient {
+    pub temperature_celsius: f32,
+    /// The relative humidity, if measured.
+    pub humidity_percent: Option<f32>,
+    /// The air pressure, if measured.
+    pub pressure_hpa: Option<f32>,
+    /// The time elapsed between measuring and posting the conditions (in s), if not measured just now.
+    ///
+    /// Relative, as the controller has no wall clock.
+    pub age_secs: Option<u64>,
+}
+
+/// The requested temperature, for the controller to run the e-kit on the reported ambient temperature.
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct PostEKitSetpoint {
+    pub setpoint_celsius: f32,
+}
+
+/// The lowest and highest temperatures that can be requested, as configured on the controller.
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct PostEKitSetpointBounds {
+    pub min_celsius: f32,
+    pub max_celsius: f32,
+}
+
+impl PostEKitSetpointBounds {
+    /// Returns the setpoint bounds, or an error if they're invalid.
+    pub fn bounds(&self) -> anyhow::Result<SetpointBounds> {
+        SetpointBounds::from_celsius(self.min_celsius, self.max_celsius)
+    }
+}
+
+impl From<SetpointBounds> for PostEKitSetpointBounds {
+    fn from(bounds: SetpointBounds) -> Self {
+        let celsius =
+            |temperature: Temperature| temperature.converted_to(UnitTemperature::celsius()).value;
+        PostEKitSetpointBounds {
+            min_celsius: celsius(bounds.min()),
+            max_celsius: celsius(bounds.max()),
+        }
+    }
+}
+
+/// The number of switching cycles the relays are rated for, as configured on the controller.
+#[derive(Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct PostEKitRelayLifetime {
+    pub cycles: u64,
+}
+
+/// The current time, set by a client (e.g. a phone) as the controller has no access to a time server.
+#[derive(Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct PostEKitTime {
+    /// The time in s since the Unix epoch.
+    pub unix_secs: u64,
+    /// The offset of the local time zone from UTC (in minutes), including daylight saving time.
+    pub utc_offset_minutes: i32,
+}
+
+/// The heating schedule followed by the thermostat, as configured on the controller.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct EKitSchedule {
+    pub presets: Presets,
+    /// The blocks of each day, starting on Monday.
+    pub days: [DaySchedule; 7],
+}
+
+impl EKitSchedule {
+    pub fn new(schedule: &Schedule, presets: Presets) -> Self {
+        EKitSchedule {
+            presets,
+            days: Weekday::all().map(|day| schedule.day(day).clone()),
+        }
+    }
+
+    /// Returns the schedule, or an error if any of its days is invalid.
+    pub fn schedule(&self) -> anyhow::Result<Schedule> {
+        let mut schedule = Schedule::default();
+        for day in Weekday::all() {
+            schedule.set_day(day, validated_day(self.days[day.index()].clone())?);
+        }
+        Ok(schedule)
+    }
+}
+
+/// The blocks of a day of the heating schedule.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct PostEKitScheduleDay {
+    /// The number of days since Monday.
+    pub day: usize,
+    pub blocks: DaySchedule,
+}
+
+/// The status of the e-kit, as reported by the controller.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct EKitStatus {
+    pub run_mode: EKitSystemRunMode,
+    /// The temperature scale preferred by the user.
+    pub temperature_scale: TemperatureScale,
+    /// The most recently measured output temperature (in the preferred temperature scale).
+    pub output_temperature: Option<f32>,
+    /// The requested temperature (in the preferred temperature scale), if the controller runs the e-kit on it.
+    pub setpoint: Option<f32>,
+    /// The lowest temperature that can be requested (in the preferred temperature scale).
+    pub setpoint_min: f32,
+    /// The highest temperature that can be requested (in the preferred temperature scale).
+    pub setpoint_max: f32,
+    /// `true` if the run mode has been downgraded to stay within the power budget.
+    pub power_limited: bool,
+    /// The estimated power currently drawn (in W).
+    pub power_w: f32,
+    /// The estimated energy used today (in Wh).
+    pub energy_today_wh: f32,
+    /// The estimated energy used since the last reset (in Wh).
+    pub energy_total_wh: f32,
+    /// The time remaining until the boost ends (in s), if boosting.
+    pub boost_remaining_secs: Option<u64>,
+    /// The current time (in s since the Unix epoch), if it has been set.
+    pub unix_secs: Option<u64>,
+    /// The offset of the local time zone from UTC (in minutes).
+    pub utc_offset_minutes: i32,
+}
+
+impl EKitStatus {
+    /// Returns the most recently measured output temperature.
+    pub fn output_temperature(&self) -> Option<Temperature> {
+        self.output_temperature
+            .map(|temperature| Measurement::new(temperature, self.temperature_scale.unit()))
+    }
+
+    /// Returns the requested temperature, if the controller runs the e-kit on it.
+    pub fn setpoint(&self) -> Option<Temperature> {
+        self.setpoint
+            .map(|temperature| Measurement::new(temperature, self.temperature_scale.unit()))
+    }
+
+    /// Returns the lowest and highest temperatures that can be requested.
+    pub fn setpoint_bounds(&self) -> SetpointBounds {
+        let unit = self.temperature_scale.unit();
+        SetpointBounds::new(
+            Measurement::new(self.setpoint_min, unit),
+            Measurement::new(self.setpoint_max, unit),
+        )
+    }
+
+    /// Returns the estimated power currently drawn.
+    pub fn power(&self) -> Power {
+        watts(self.power_w)
+    }
+
+    /// Returns the estimated energy used today.
+    pub fn energy_today(&self) -> Energy {
+        watt_hours(self.energy_today_wh)
+    }
+
+    /// Returns the estimated energy used since the last reset.
+    pub fn energy_total(&self) -> Energy {
+        watt_hours(self.energy_total_wh)
+    }
+
+    /// Returns the time remaining until the boost ends, if boosting.
+    pub fn boost_remaining(&self) -> Option<Duration> {
+        self.boost_remaining_secs.map(Duration::from_secs)
+    }
+}
+
+/// A relay driving one of the e-kit's peripherals.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize)]
+pub enum EKitRelay {
+    Fan,
+    Coil1,
+    Coil2,
+}
+
+impl EKitRelay {
+    /// Returns all e-kit relays.
+    pub const fn all() -> [EKitRelay; 3] {
+        [EKitRelay::Fan, EKitRelay::Coil1, EKitRelay::Coil2]
+    }
+
+    /// Returns the name of the relay.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EKitRelay::Fan => "fan",
+            EKitRelay::Coil1 => "coil1",
+            EKitRelay::Coil2 => "coil2",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize)]
+pub enum EKitFault {
+    /// The output temperature could not be measured.
+    OutputTemperatureUnavailable,
+    /// The output temperature exceeded the overtemperature protection limit.
+    Overtemperature,
+    /// The readings of the output temperature sensors differ by more than the tolerance.
+    OutputTemperatureSensorsDisagree,
+    /// No recent ambient temperature has been reported to run the e-kit on its setpoint.
+    AmbientTemperatureUnavailable,
+    /// The relay is approaching the end of its rated lifetime.
+    RelayMaintenanceDue { relay: EKitRelay },
+}
+
+/// A change in the state of the e-kit.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum EKitEvent {
+    RunModeChanged {
+        from: EKitSystemRunMode,
+        to: EKitSystemRunMode,
+    },
+    RunModeRequested {
+        run_mode: EKitUserRunMode,
+        accepted: bool,
+    },
+    /// The requested run mode has been downgraded to stay within the power budget.
+    RunModeLimited {
+        requested: EKitSystemRunMode,
+        limited_to: EKitSystemRunMode,
+    },
+    RelayChanged {
+        relay: EKitRelay,
+        closed: bool,
+    },
+    OutputTemperature {
+        temperature: Option<Temperature>,
+    },
+    /// An output temperature sensor (identified by the index of its reading) could not be read, even if the remaining
+    /// sensors cover it.
+    OutputTemperatureSensorFailed {
+        sensor: usize,
+    },
+    Fault {
+        fault: EKitFault,
+    },
 }
@@ -0,0 +1,279 @@
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// The number of published events that are retained for late readers.
+const HISTORY_CAPACITY: usize = 32;
+/// The number of transient events that are retained for late readers, separately from the other events.
+const TRANSIENT_HISTORY_CAPACITY: usize = 16;
+
+/// An event together with its sequence number.
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct Sequenced<E> {
+    pub sequence: u64,
+    pub event: E,
+}
+
+/// An in-process event bus.
+///
+/// Events can be consumed by subscribing to the bus (every subscriber receives every event published after it
+/// subscribed), or by reading the most recent events from the bus' history.
+///
+/// Subscribers are in-process consumers (e.g. the metrics), whose queues aren't bounded: dropping events would corrupt
+/// what they derive from them (e.g. the relay on-times), so they're expected to keep up.
+pub struct EventBus<E> {
+    inner: Arc<(Mutex<Inner<E>>, Condvar)>,
+}
+
+struct Inner<E> {
+    next_sequence: u64,
+    history: VecDeque<Sequenced<E>>,
+    transient_history: VecDeque<Sequenced<E>>,
+    subscribers: Vec<Sender<Sequenced<E>>>,
+}
+
+impl<E: Clone> EventBus<E> {
+    /// Returns a new event bus without any subscribers.
+    pub fn new() -> Self {
+        EventBus {
+            inner: Arc::new((
+                Mutex::new(Inner {
+                    next_sequence: 1,
+                    history: VecDeque::with_capacity(HISTORY_CAPACITY),
+                    transient_history: VecDeque::with_capacity(TRANSIENT_HISTORY_CAPACITY),
+                    subscribers: Vec::new(),
+                }),
+                Condvar::new(),
+            )),
+        }
+    }
+
+    /// Publish an event to all subscribers, retaining it in the history.
+    pub fn publish(&self, event: E) {
+        self.publish_event(event, false);
+    }
+
+    /// Publish an event to all subscribers, retaining it in a separate, shorter history.
+    ///
+    /// Meant for frequent events (e.g. telemetry), which would otherwise push the other events out of the history.
+    pub fn publish_transient(&self, event: E) {
+        self.publish_event(event, true);
+    }
+
+    fn publish_event(&self, event: E, transient: bool) {
+        let (lock, cvar) = &*self.inner;
+        let mut inner = lock.lock().unwrap();
+
+        let event = Sequenced {
+            sequence: inner.next_sequence,
+            event,
+        };
+        inner.next_sequence += 1;
+
+        // drop the subscribers that have gone away
+        inner
+            .subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+
+        let (history, capacity) = if transient {
+            (&mut inner.transient_history, TRANSIENT_HISTORY_CAPACITY)
+        } else {
+            (&mut inner.history, HISTORY_CAPACITY)
+        };
+        if history.len() == capacity {
+            history.pop_front();
+        }
+        history.push_back(event);
+        cvar.notify_all();
+    }
+
+    /// Subscribe to all events published from now on.
+    pub fn subscribe(&self) -> Receiver<Sequenced<E>> {
+        let (sender, receiver) = channel();
+        let (lock, _) = &*self.inner;
+        lock.lock().unwrap().subscribers.push(sender);
+        receiver
+    }
+
+    /// Returns the retained events with a sequence number greater than `sequence`.
+    pub fn events_after(&self, sequence: u64) -> Vec<Sequenced<E>> {
+        let (lock, _) = &*self.inner;
+        Self::retained_after(&lock.lock().unwrap(), sequence)
+    }
+
+    /// Returns the retained events with a sequence number greater than `sequence`.
+    ///
+    /// If there are no such events, this will block until one is published or `timeout` has elapsed.
+    pub fn wait_for_events_after(&self, sequence: u64, timeout: Duration) -> Vec<Sequenced<E>> {
+        let deadline = Instant::now() + timeout;
+        let (lock, cvar) = &*self.inner;
+        let mut inner = lock.lock().unwrap();
+        loop {
+            let events = Self::retained_after(&inner, sequence);
+            let now = Instant::now();
+            if !events.is_empty() || now >= deadline {
+                return events;
+            }
+            inner = cvar.wait_timeout(inner, deadline - now).unwrap().0;
+        }
+    }
+
+    fn retained_after(inner: &Inner<E>, sequence: u64) -> Vec<Sequenced<E>> {
+        let mut events: Vec<_> = inner
+            .history
+            .iter()
+            .chain(inner.transient_history.iter())
+            .filter(|event| event.sequence > sequence)
+            .cloned()
+            .collect();
+        events.sort_by_key(|event| event.sequence);
+        events
+    }
+}
+
+impl<E: Clone> Default for EventBus<E> {
+    fn default() -> Self {
+        EventBus::new()
+    }
+}
+
+impl<E> Clone for EventBus<E> {
+    fn clone(&self) -> Self {
+        EventBus {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscribers_receive_published_events() {
+        let bus = EventBus::new();
+        let first = bus.subscribe();
+        bus.publish("one");
+        let second = bus.subscribe();
+        bus.publish("two");
+
+        assert_eq!(
+            first.try_iter().map(|e| e.event).collect::<Vec<_>>(),
+            vec!["one", "two"]
+        );
+        assert_eq!(
+            second.try_iter().map(|e| e.event).collect::<Vec<_>>(),
+            vec!["two"]
+        );
+    }
+
+    #[test]
+    fn dropped_subscribers_are_removed() {
+        let bus = EventBus::new();
+        drop(bus.subscribe());
+        bus.publish(1);
+        assert!(bus.inner.0.lock().unwrap().subscribers.is_empty());
+    }
+
+    #[test]
+    fn events_after_returns_newer_events() {
+        let bus = EventBus::new();
+        bus.publish('a');
+        bus.publish('b');
+        bus.publish('c');
+
+        let events = bus.events_after(1);
+        assert_eq!(
+            events,
+            vec![
+                Sequenced {
+                    sequence: 2,
+                    event: 'b'
+                },
+                Sequenced {
+                    sequence: 3,
+                    event: 'c'
+                },
+            ]
+        );
+        assert!(bus.events_after(3).is_empty());
+    }
+
+    #[test]
+    fn wait_for_events_after_returns_published_event() {
+        let bus = EventBus::new();
+        bus.publish(1);
+
+        let publisher = bus.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            publisher.publish(2);
+        });
+
+        let events = bus.wait_for_events_after(1, Duration::from_secs(5));
+        handle.join().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, 2);
+    }
+
+    #[test]
+    fn wait_for_events_after_times_out() {
+        let bus: EventBus<u32> = EventBus::new();
+        let events = bus.wait_for_events_after(0, Duration::from_millis(10));
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let bus = EventBus::new();
+        for i in 0..(HISTORY_CAPACITY as u64 + 5) {
+            bus.publish(i);
+        }
+
+        let events = bus.events_after(0);
+        assert_eq!(events.len(), HISTORY_CAPACITY);
+        assert_eq!(events[0].event, 5);
+    }
+
+    #[test]
+    fn transient_events_do_not_push_out_other_events() {
+        let bus = EventBus::new();
+        let subscriber = bus.subscribe();
+        bus.publish("fault");
+        for _ in 0..HISTORY_CAPACITY {
+            bus.publish_transient("telemetry");
+        }
+        bus.publish("relay");
+
+        let events = bus.events_after(0);
+        assert_eq!(events.len(), TRANSIENT_HISTORY_CAPACITY + 2);
+        assert_eq!(events[0].event, "fault");
+        assert!(events[1..=TRANSIENT_HISTORY_CAPACITY]
+            .iter()
+            .all(|event| event.event == "telemetry"));
+        assert_eq!(events[TRANSIENT_HISTORY_CAPACITY + 1].event, "relay");
+        // in order of publication
+        assert!(events
+            .windows(2)
+            .all(|pair| pair[0].sequence < pair[1].sequence));
+        assert_eq!(subscriber.try_iter().count(), HISTORY_CAPACITY + 2);
+    }
+
+    #[test]
+    fn slow_subscribers_receive_every_event() {
+        let bus = EventBus::new();
+        let subscriber = bus.subscribe();
+        for i in 0..1000 {
+            bus.publish(i);
+        }
+
+        let events: Vec<_> = subscriber.try_iter().map(|e| e.event).collect();
+        assert_eq!(events, (0..1000).collect::<Vec<_>>());
+    }
+}
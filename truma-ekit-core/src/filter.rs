@@ -0,0 +1,301 @@
+use crate::{
+    peripherals::temperature_sensor::{SensorReading, TemperatureSensor},
+    types::{Temperature, UnitTemperature},
+    util::celsius,
+};
+use anyhow::anyhow;
+use std::collections::VecDeque;
+
+/// A filter applied to consecutive readings of a sensor.
+pub trait Filter {
+    /// Feed the next reading through the filter, returning the filtered reading.
+    fn apply(&mut self, value: f32) -> f32;
+
+    /// Forget the previous readings, so the next reading starts afresh.
+    fn reset(&mut self);
+}
+
+/// Rejects spikes by returning the median of the last `size` readings.
+pub struct Median {
+    size: usize,
+    window: VecDeque<f32>,
+}
+
+impl Median {
+    pub fn new(size: usize) -> Self {
+        assert!(
+            size > 0,
+            "median filter requires a window of at least 1 reading"
+        );
+        Median {
+            size,
+            window: VecDeque::with_capacity(size),
+        }
+    }
+}
+
+impl Filter for Median {
+    fn apply(&mut self, value: f32) -> f32 {
+        if self.window.len() == self.size {
+            self.window.pop_front();
+        }
+        self.window.push_back(value);
+
+        let mut sorted: Vec<f32> = self.window.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let middle = sorted.len() / 2;
+        match sorted.len() % 2 {
+            // the average of the 2 middle readings
+            0 => (sorted[middle - 1] + sorted[middle]) / 2.0,
+            _ => sorted[middle],
+        }
+    }
+
+    fn reset(&mut self) {
+        self.window.clear();
+    }
+}
+
+/// Smooths the readings, weighing each new reading by `alpha` (between 0 and 1).
+pub struct ExponentialMovingAverage {
+    alpha: f32,
+    average: Option<f32>,
+}
+
+impl ExponentialMovingAverage {
+    pub fn new(alpha: f32) -> Self {
+        assert!(
+            alpha > 0.0 && alpha <= 1.0,
+            "exponential moving average requires 0 < alpha <= 1"
+        );
+        ExponentialMovingAverage {
+            alpha,
+            average: None,
+        }
+    }
+}
+
+impl Filter for ExponentialMovingAverage {
+    fn apply(&mut self, value: f32) -> f32 {
+        let average = match self.average {
+            Some(average) => average + self.alpha * (value - average),
+            None => value,
+        };
+        self.average = Some(average);
+        average
+    }
+
+    fn reset(&mut self) {
+        self.average = None;
+    }
+}
+
+/// Limits the change between consecutive readings to `max_step`.
+///
+/// A genuine change still comes through, spread over multiple readings.
+pub struct OutlierClamp {
+    max_step: f32,
+    previous: Option<f32>,
+}
+
+impl OutlierClamp {
+    pub fn new(max_step: f32) -> Self {
+        OutlierClamp {
+            max_step,
+            previous: None,
+        }
+    }
+}
+
+impl Filter for OutlierClamp {
+    fn apply(&mut self, value: f32) -> f32 {
+        let value = match self.previous {
+            Some(previous) => value.clamp(previous - self.max_step, previous + self.max_step),
+            None => value,
+        };
+        self.previous = Some(value);
+        value
+    }
+
+    fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+/// A temperature sensor whose readings are oversampled and fed through a pipeline of filters.
+///
+/// The filters operate on the temperature in degrees Celsius, in the order they were added.
+pub struct Filtered<S> {
+    sensor: S,
+    oversampling: usize,
+    filters: Vec<Box<dyn Filter>>,
+}
+
+impl<S: TemperatureSensor> Filtered<S> {
+    /// Returns the sensor without any filtering.
+    pub fn new(sensor: S) -> Self {
+        Filtered {
+            sensor,
+            oversampling: 1,
+            filters: Vec::new(),
+        }
+    }
+
+    /// Average `samples` samples per reading.
+    pub fn oversampled(mut self, samples: usize) -> Self {
+        assert!(samples > 0, "oversampling requires at least 1 sample");
+        self.oversampling = samples;
+        self
+    }
+
+    /// Append a filter to the pipeline.
+    pub fn with_filter(mut self, filter: impl Filter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Returns the average of the samples that could be read, failing only if none could.
+    fn sample(&mut self) -> anyhow::Result<(f32, SensorReading)> {
+        let mut sum = 0.0;
+        let mut last = None;
+        let mut error = None;
+        let mut count = 0;
+        for _ in 0..self.oversampling {
+            match self.sensor.measure() {
+                Ok(reading) => {
+                    sum += reading
+                        .temperature
+                        .converted_to(UnitTemperature::celsius())
+                        .value;
+                    count += 1;
+                    last = Some(reading);
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+        match last {
+            Some(reading) => Ok((sum / count as f32, reading)),
+            None => Err(error.unwrap_or_else(|| anyhow!("no samples"))),
+        }
+    }
+}
+
+impl<S: TemperatureSensor> TemperatureSensor for Filtered<S> {
+    fn measure_temperature(&mut self) -> anyhow::Result<Temperature> {
+        Ok(self.measure()?.temperature)
+    }
+
+    /// The filters are reset if the sensor couldn't be read, so stale readings from before the failure (e.g. of a
+    /// disconnected sensor) don't hold back or skew the readings once it recovers.
+    fn measure(&mut self) -> anyhow::Result<SensorReading> {
+        let (average, reading) = match self.sample() {
+            Ok(sample) => sample,
+            Err(e) => {
+                for filter in &mut self.filters {
+                    filter.reset();
+                }
+                return Err(e);
+            }
+        };
+        let filtered = self
+            .filters
+            .iter_mut()
+            .fold(average, |value, filter| filter.apply(value));
+        Ok(SensorReading {
+            temperature: celsius(filtered),
+            ..reading
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{adc::AdcInputPin, peripherals::tmp36::TMP36, testing::ScriptedSensor};
+    use assert_approx_eq::assert_approx_eq;
+
+    fn apply(filter: &mut impl Filter, values: &[f32]) -> Vec<f32> {
+        values.iter().map(|value| filter.apply(*value)).collect()
+    }
+
+    fn measure(sensor: &mut impl TemperatureSensor) -> f32 {
+        sensor
+            .measure_temperature()
+            .unwrap()
+            .converted_to(UnitTemperature::celsius())
+            .value
+    }
+
+    #[test]
+    fn median_rejects_spikes() {
+        let mut median = Median::new(3);
+        assert_eq!(
+            apply(&mut median, &[20.0, 21.0, 80.0, 21.0, 22.0, 22.0]),
+            vec![20.0, 20.5, 21.0, 21.0, 22.0, 22.0]
+        );
+    }
+
+    #[test]
+    fn exponential_moving_average() {
+        let mut ema = ExponentialMovingAverage::new(0.5);
+        assert_eq!(
+            apply(&mut ema, &[20.0, 22.0, 22.0, 22.0]),
+            vec![20.0, 21.0, 21.5, 21.75]
+        );
+    }
+
+    #[test]
+    fn outlier_clamp() {
+        let mut clamp = OutlierClamp::new(1.0);
+        assert_eq!(
+            apply(&mut clamp, &[20.0, 25.0, 25.0, 19.5, 20.0]),
+            vec![20.0, 21.0, 22.0, 21.0, 20.0]
+        );
+    }
+
+    #[test]
+    fn oversampling_averages_samples() {
+        // 740, 750 and 760 mV read as 24, 25 and 26 °C
+        let mut sensor = Filtered::new(TMP36::connected_to(AdcInputPin::replay([
+            740, 760, 750, 750,
+        ])))
+        .oversampled(2);
+        assert_approx_eq!(measure(&mut sensor), 25.0, 1e-3);
+        assert_approx_eq!(measure(&mut sensor), 25.0, 1e-3);
+        // the replayed samples have run out
+        assert!(sensor.measure_temperature().is_err());
+    }
+
+    #[test]
+    fn pipeline_applies_filters_in_order() {
+        // a single spike to 75 °C, then a genuine change to 30 °C
+        let mut sensor = Filtered::new(TMP36::connected_to(AdcInputPin::replay([
+            750, 750, 1250, 750, 800, 800, 800,
+        ])))
+        .with_filter(Median::new(3))
+        .with_filter(OutlierClamp::new(2.0));
+
+        let readings: Vec<f32> = (0..7).map(|_| measure(&mut sensor)).collect();
+        let expected = [25.0, 25.0, 25.0, 25.0, 27.0, 29.0, 30.0];
+        for (reading, expected) in readings.into_iter().zip(expected) {
+            assert_approx_eq!(reading, expected, 1e-3);
+        }
+    }
+
+    #[test]
+    fn failed_reading_resets_filters() {
+        let mut sensor = Filtered::new(ScriptedSensor::new(
+            [Some(20.0), Some(20.0), None, Some(30.0), Some(31.0)]
+                .map(|reading| reading.map(celsius)),
+        ))
+        .with_filter(Median::new(3))
+        .with_filter(ExponentialMovingAverage::new(0.5));
+
+        assert_approx_eq!(measure(&mut sensor), 20.0, 1e-3);
+        assert_approx_eq!(measure(&mut sensor), 20.0, 1e-3);
+        assert!(sensor.measure_temperature().is_err());
+        // not held back by the readings from before the failure
+        assert_approx_eq!(measure(&mut sensor), 30.0, 1e-3);
+        assert_approx_eq!(measure(&mut sensor), 30.25, 1e-3);
+    }
+}
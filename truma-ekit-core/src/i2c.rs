@@ -0,0 +1,87 @@
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+use std::{cell::RefCell, rc::Rc};
+
+/// An I²C bus shared by multiple devices (e.g. a display and a sensor).
+///
+/// Each device gets its own clone, all of which access the same underlying bus.
+pub struct SharedI2c<I2C> {
+    bus: Rc<RefCell<I2C>>,
+}
+
+impl<I2C> SharedI2c<I2C> {
+    pub fn new(bus: I2C) -> Self {
+        SharedI2c {
+            bus: Rc::new(RefCell::new(bus)),
+        }
+    }
+}
+
+impl<I2C> Clone for SharedI2c<I2C> {
+    fn clone(&self) -> Self {
+        SharedI2c {
+            bus: self.bus.clone(),
+        }
+    }
+}
+
+impl<I2C: Read> Read for SharedI2c<I2C> {
+    type Error = I2C::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.bus.borrow_mut().read(address, buffer)
+    }
+}
+
+impl<I2C: Write> Write for SharedI2c<I2C> {
+    type Error = I2C::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.bus.borrow_mut().write(address, bytes)
+    }
+}
+
+impl<I2C: WriteRead> WriteRead for SharedI2c<I2C> {
+    type Error = I2C::Error;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.bus.borrow_mut().write_read(address, bytes, buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Bus {
+        written: Vec<(u8, Vec<u8>)>,
+    }
+
+    impl Write for Bus {
+        type Error = ();
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+            self.written.push((address, bytes.to_vec()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn devices_share_the_bus() {
+        let mut display = SharedI2c::new(Bus::default());
+        let mut sensor = display.clone();
+
+        display.write(0x3c, &[1, 2]).unwrap();
+        sensor.write(0x76, &[3]).unwrap();
+
+        assert_eq!(
+            display.bus.borrow().written,
+            vec![(0x3c, vec![1, 2]), (0x76, vec![3])]
+        );
+    }
+}
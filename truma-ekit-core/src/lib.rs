@@ -1,8 +1,18 @@
 pub mod adc;
+pub mod clock;
+pub mod control;
 pub mod ekit;
+pub mod events;
+pub mod filter;
+pub mod i2c;
 pub mod measurement;
+pub mod onewire;
 pub mod peripherals;
 pub mod powersaving;
+pub mod schedule;
+pub mod storage;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod throttle;
 pub mod types;
 pub mod util;
@@ -1,11 +1,97 @@
+use anyhow::anyhow;
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use std::borrow::Cow;
+
 #[derive(Copy, Clone)]
 pub struct Formatter {
     precision: usize,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+/// The maximum number of iterations used to invert a polynomial converter.
+const POLYNOMIAL_MAX_ITERATIONS: usize = 32;
+/// The precision to which a polynomial converter is inverted.
+const POLYNOMIAL_TOLERANCE: f32 = 1e-5;
+
+/// Converts values between a unit and its dimension's base unit.
+///
+/// Besides converting between units, converters are used to express the transfer functions of sensors, in which case
+/// the base unit is the measured quantity (e.g. °C) and the unit is the sensor's output (e.g. V).
+#[derive(Clone, PartialEq, Debug)]
 pub enum UnitConverter {
-    Linear { coeff: f32, constant: f32 },
+    Linear {
+        coeff: f32,
+        constant: f32,
+    },
+    /// Interpolates linearly between points, see `PiecewiseLinear`.
+    PiecewiseLinear(PiecewiseLinear),
+    /// Evaluates a polynomial with the given coefficients (constant first) of the base value.
+    ///
+    /// The polynomial is inverted numerically, which requires it to be strictly monotonic over the range of values
+    /// converted.
+    Polynomial {
+        coeffs: &'static [f32],
+    },
+}
+
+/// `(base value, unit value)` points to interpolate linearly between, e.g. taken from a datasheet table or
+/// calibration.
+///
+/// Values outside of the points are extrapolated from the outermost segments.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PiecewiseLinear {
+    points: Cow<'static, [(f32, f32)]>,
+}
+
+impl PiecewiseLinear {
+    /// Returns the points, or an error unless there are at least two of them, sorted by strictly increasing base value,
+    /// with the unit values strictly increasing or decreasing.
+    pub fn new(points: &[(f32, f32)]) -> anyhow::Result<Self> {
+        if points.len() < 2 {
+            return Err(anyhow!(
+                "piecewise linear converter requires at least 2 points, got {}",
+                points.len()
+            ));
+        }
+        if let Some((base, unit)) = points
+            .iter()
+            .find(|(base, unit)| !base.is_finite() || !unit.is_finite())
+        {
+            return Err(anyhow!("point ({}, {}) is not finite", base, unit));
+        }
+        if let Some(pair) = points.windows(2).find(|pair| pair[0].0 >= pair[1].0) {
+            return Err(anyhow!(
+                "base values must be strictly increasing, got {} followed by {}",
+                pair[0].0,
+                pair[1].0
+            ));
+        }
+        let increasing = points[0].1 < points[1].1;
+        if let Some(pair) = points
+            .windows(2)
+            .find(|pair| pair[0].1 == pair[1].1 || (pair[0].1 < pair[1].1) != increasing)
+        {
+            return Err(anyhow!(
+                "unit values must be strictly increasing or decreasing, got {} followed by {}",
+                pair[0].1,
+                pair[1].1
+            ));
+        }
+        Ok(PiecewiseLinear {
+            points: Cow::Owned(points.to_vec()),
+        })
+    }
+
+    /// Returns the points without validating them, for constant tables (which are validated in tests instead).
+    pub(crate) const fn new_unchecked(points: &'static [(f32, f32)]) -> Self {
+        PiecewiseLinear {
+            points: Cow::Borrowed(points),
+        }
+    }
+
+    /// Returns the points.
+    pub fn points(&self) -> &[(f32, f32)] {
+        &self.points
+    }
 }
 
 pub trait Dimension: Unit + PartialEq {
@@ -13,8 +99,20 @@ pub trait Dimension: Unit + PartialEq {
     fn base_unit() -> Self;
     /// Returns this dimension's converter.
     fn converter(&self) -> UnitConverter;
+    /// Returns the units measurements are scaled to when formatting, from smallest to largest.
+    fn scaled_units() -> &'static [Self]
+    where
+        Self: Sized,
+    {
+        &[]
+    }
 }
 
+/// A dimension whose measurements can be added to and subtracted from each other.
+///
+/// This doesn't hold for e.g. absolute temperatures, as adding 20 °C to 20 °C is meaningless.
+pub trait Additive: Dimension {}
+
 pub trait Unit {
     /// Returns this unit's symbol.
     fn symbol(&self) -> &str;
@@ -63,10 +161,18 @@ impl<U: Dimension> Measurement<U> {
 }
 
 impl UnitConverter {
+    /// Returns a converter interpolating linearly between the given `(base value, unit value)` points, or an error if
+    /// they are invalid (see `PiecewiseLinear::new`).
+    pub fn piecewise_linear(points: &[(f32, f32)]) -> anyhow::Result<Self> {
+        PiecewiseLinear::new(points).map(Self::PiecewiseLinear)
+    }
+
     /// Convert from base unit value.
     pub fn from_base_unit(&self, val: f32) -> f32 {
         match self {
             Self::Linear { coeff, constant } => (val * coeff) + constant,
+            Self::PiecewiseLinear(points) => interpolate(points.points(), val, false),
+            Self::Polynomial { coeffs } => evaluate_polynomial(coeffs, val).0,
         }
     }
 
@@ -74,8 +180,73 @@ impl UnitConverter {
     pub fn to_base_unit(&self, val: f32) -> f32 {
         match self {
             Self::Linear { coeff, constant } => (val - constant) / coeff,
+            Self::PiecewiseLinear(points) => interpolate(points.points(), val, true),
+            Self::Polynomial { coeffs } => invert_polynomial(coeffs, val),
+        }
+    }
+}
+
+/// Interpolates `x` between the `(base value, unit value)` points (or the other way around if `inverse`),
+/// extrapolating from the outermost segments.
+///
+/// Requires at least two points, as guaranteed by `PiecewiseLinear`.
+fn interpolate(points: &[(f32, f32)], x: f32, inverse: bool) -> f32 {
+    let point = |i: usize| {
+        let (base, unit) = points[i];
+        if inverse {
+            (unit, base)
+        } else {
+            (base, unit)
+        }
+    };
+
+    let last = points.len() - 2;
+    let segment = (0..=last)
+        .find(|&i| {
+            let ((x0, _), (x1, _)) = (point(i), point(i + 1));
+            x0.min(x1) <= x && x <= x0.max(x1)
+        })
+        .unwrap_or_else(|| {
+            if (x - point(0).0).abs() < (x - point(last + 1).0).abs() {
+                0
+            } else {
+                last
+            }
+        });
+
+    let ((x0, y0), (x1, y1)) = (point(segment), point(segment + 1));
+    y0 + (x - x0) * (y1 - y0) / (x1 - x0)
+}
+
+/// Evaluates the polynomial and its derivative at `x`.
+fn evaluate_polynomial(coeffs: &[f32], x: f32) -> (f32, f32) {
+    coeffs
+        .iter()
+        .rev()
+        .fold((0.0, 0.0), |(y, dy), coeff| (y * x + coeff, dy * x + y))
+}
+
+/// Solves `polynomial(x) = y` using Newton's method, starting from the linear approximation.
+fn invert_polynomial(coeffs: &[f32], y: f32) -> f32 {
+    let constant = coeffs.first().copied().unwrap_or(0.0);
+    let coeff = coeffs.get(1).copied().unwrap_or(0.0);
+    let mut x = if coeff != 0.0 {
+        (y - constant) / coeff
+    } else {
+        0.0
+    };
+    for _ in 0..POLYNOMIAL_MAX_ITERATIONS {
+        let (fx, dfx) = evaluate_polynomial(coeffs, x);
+        if dfx == 0.0 {
+            break;
+        }
+        let step = (fx - y) / dfx;
+        x -= step;
+        if step.abs() <= POLYNOMIAL_TOLERANCE * x.abs().max(1.0) {
+            break;
         }
     }
+    x
 }
 
 impl Formatter {
@@ -91,9 +262,29 @@ impl Formatter {
             prec = self.precision
         )
     }
+
+    /// Formats the measurement in the largest of its dimension's scaled units in which its value is at least 1 (e.g.
+    /// `1.5kW` instead of `1500W`).
+    pub fn format_scaled<U: Dimension + Copy + 'static>(
+        &self,
+        measurement: &Measurement<U>,
+    ) -> String {
+        let scaled = U::scaled_units()
+            .iter()
+            .map(|unit| measurement.converted_to(*unit))
+            .reduce(|smaller, larger| {
+                if larger.value.abs() >= 1.0 {
+                    larger
+                } else {
+                    smaller
+                }
+            })
+            .unwrap_or(*measurement);
+        self.format(&scaled)
+    }
 }
 
-impl<U: Dimension> std::ops::Add for Measurement<U> {
+impl<U: Additive> std::ops::Add for Measurement<U> {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
@@ -111,7 +302,7 @@ impl<U: Dimension> std::ops::Add for Measurement<U> {
     }
 }
 
-impl<U: Dimension> std::ops::Sub for Measurement<U> {
+impl<U: Additive> std::ops::Sub for Measurement<U> {
     type Output = Self;
 
     fn sub(self, rhs: Self) -> Self::Output {
@@ -129,6 +320,14 @@ impl<U: Dimension> std::ops::Sub for Measurement<U> {
     }
 }
 
+impl<U: Additive> std::ops::Mul<f32> for Measurement<U> {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Measurement::new(self.value * rhs, self.unit)
+    }
+}
+
 impl<U: Dimension> PartialEq for Measurement<U> {
     fn eq(&self, other: &Self) -> bool {
         self.unit
@@ -147,18 +346,57 @@ impl<U: Dimension> PartialOrd for Measurement<U> {
     }
 }
 
+impl<U: Unit> Serialize for Measurement<U> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Measurement", 2)?;
+        state.serialize_field("value", &self.value)?;
+        state.serialize_field("unit", self.unit.symbol())?;
+        state.end()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use assert_approx_eq::assert_approx_eq;
     use std::cmp::Ordering;
 
-    #[derive(Eq, PartialEq, Debug)]
+    #[derive(Copy, Clone, Eq, PartialEq, Debug)]
     enum Test {
         One,
         Two,
     }
 
+    #[test]
+    fn format() {
+        let formatter = Formatter::with_precision(2);
+        assert_eq!(
+            formatter.format(&Measurement::new(1.5, Test::One)),
+            "1.50One"
+        );
+    }
+
+    #[test]
+    fn format_scaled() {
+        let formatter = Formatter::with_precision(1);
+        assert_eq!(
+            formatter.format_scaled(&Measurement::new(0.25, Test::One)),
+            "0.5Two"
+        );
+        assert_eq!(
+            formatter.format_scaled(&Measurement::new(1.5, Test::Two)),
+            "1.5Two"
+        );
+        assert_eq!(
+            formatter.format_scaled(&Measurement::new(0.4, Test::Two)),
+            "0.4Two"
+        );
+        assert_eq!(
+            formatter.format_scaled(&Measurement::new(4.0, Test::Two)),
+            "2.0One"
+        );
+    }
+
     #[test]
     fn conversion() {
         assert_approx_eq!(
@@ -203,6 +441,80 @@ mod tests {
         );
     }
 
+    #[test]
+    fn mul_scalar() {
+        assert_approx_eq!((Measurement::new(1.5, Test::Two) * -2.0).value, -3.0);
+    }
+
+    #[test]
+    fn piecewise_linear_converter() {
+        let converter =
+            UnitConverter::piecewise_linear(&[(0.0, 10.0), (10.0, 30.0), (20.0, 40.0)]).unwrap();
+        assert_approx_eq!(converter.from_base_unit(5.0), 20.0);
+        assert_approx_eq!(converter.from_base_unit(15.0), 35.0);
+        assert_approx_eq!(converter.to_base_unit(35.0), 15.0);
+
+        // extrapolated from the outermost segments
+        assert_approx_eq!(converter.from_base_unit(-5.0), 0.0);
+        assert_approx_eq!(converter.to_base_unit(50.0), 30.0);
+    }
+
+    #[test]
+    fn piecewise_linear_converter_decreasing() {
+        let converter =
+            UnitConverter::piecewise_linear(&[(0.0, 40.0), (10.0, 20.0), (20.0, 15.0)]).unwrap();
+        assert_approx_eq!(converter.from_base_unit(2.5), 35.0);
+        assert_approx_eq!(converter.to_base_unit(17.5), 15.0);
+        assert_approx_eq!(converter.to_base_unit(50.0), -5.0);
+        assert_approx_eq!(converter.to_base_unit(10.0), 30.0);
+    }
+
+    #[test]
+    fn piecewise_linear_converter_rejects_invalid_points() {
+        // too few points
+        assert!(UnitConverter::piecewise_linear(&[]).is_err());
+        assert!(UnitConverter::piecewise_linear(&[(0.0, 10.0)]).is_err());
+        // not sorted by base value
+        assert!(UnitConverter::piecewise_linear(&[(10.0, 30.0), (0.0, 10.0)]).is_err());
+        assert!(UnitConverter::piecewise_linear(&[(0.0, 10.0), (0.0, 30.0)]).is_err());
+        // not monotonic
+        assert!(
+            UnitConverter::piecewise_linear(&[(0.0, 10.0), (10.0, 30.0), (20.0, 20.0)]).is_err()
+        );
+        assert!(UnitConverter::piecewise_linear(&[(0.0, 10.0), (10.0, 10.0)]).is_err());
+        assert!(UnitConverter::piecewise_linear(&[(0.0, 10.0), (f32::NAN, 30.0)]).is_err());
+    }
+
+    #[test]
+    fn polynomial_converter() {
+        // y = 1 + 2x + x²
+        let converter = UnitConverter::Polynomial {
+            coeffs: &[1.0, 2.0, 1.0],
+        };
+        assert_approx_eq!(converter.from_base_unit(0.0), 1.0);
+        assert_approx_eq!(converter.from_base_unit(3.0), 16.0);
+        assert_approx_eq!(converter.to_base_unit(16.0), 3.0);
+        assert_approx_eq!(converter.to_base_unit(121.0), 10.0, 1e-3);
+    }
+
+    #[test]
+    fn linear_and_polynomial_converters_agree() {
+        let linear = UnitConverter::Linear {
+            coeff: 1.8,
+            constant: 32.0,
+        };
+        let polynomial = UnitConverter::Polynomial {
+            coeffs: &[32.0, 1.8],
+        };
+        for value in [-40.0, 0.0, 37.0, 100.0] {
+            assert_approx_eq!(
+                linear.from_base_unit(value),
+                polynomial.from_base_unit(value)
+            );
+            assert_approx_eq!(linear.to_base_unit(value), polynomial.to_base_unit(value));
+        }
+    }
+
     #[test]
     fn eq() {
         // same dimension
@@ -275,5 +587,11 @@ mod tests {
                 },
             }
         }
+
+        fn scaled_units() -> &'static [Self] {
+            &[Test::Two, Test::One]
+        }
     }
+
+    impl Additive for Test {}
 }
@@ -0,0 +1,464 @@
+use anyhow::anyhow;
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use esp_idf_hal::{delay::Ets, interrupt};
+use std::fmt::{Debug, Display};
+
+/// The ROM command to address all devices on the bus.
+const SKIP_ROM: u8 = 0xcc;
+/// The ROM command to address a single device on the bus.
+const MATCH_ROM: u8 = 0x55;
+/// The ROM command to discover the devices on the bus.
+const SEARCH_ROM: u8 = 0xf0;
+
+/// The 64-bit ROM code uniquely identifying a device on a 1-Wire bus.
+///
+/// It consists of the family code (the first byte), a serial number and a CRC (the last byte).
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Rom(pub [u8; 8]);
+
+impl Rom {
+    /// Returns the family code, identifying the kind of device.
+    pub fn family_code(&self) -> u8 {
+        self.0[0]
+    }
+
+    /// Returns `true` if the ROM code's CRC is valid.
+    pub fn is_valid(&self) -> bool {
+        crc8(&self.0[..7]) == self.0[7]
+    }
+}
+
+impl Display for Rom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Computes the Dallas/Maxim CRC-8 used by 1-Wire devices.
+pub fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, byte| {
+        (0..8)
+            .fold((crc, *byte), |(crc, byte), _| {
+                let crc = if (crc ^ byte) & 0x01 != 0 {
+                    (crc >> 1) ^ 0x8c
+                } else {
+                    crc >> 1
+                };
+                (crc, byte >> 1)
+            })
+            .0
+    })
+}
+
+/// A 1-Wire bus, on which bits are exchanged in time slots initiated by the bus master.
+pub trait OneWireBus {
+    /// Reset the bus, returning `true` if any device signalled its presence.
+    fn reset(&mut self) -> anyhow::Result<bool>;
+    /// Write a bit in a write time slot.
+    fn write_bit(&mut self, bit: bool) -> anyhow::Result<()>;
+    /// Read a bit in a read time slot.
+    fn read_bit(&mut self) -> anyhow::Result<bool>;
+
+    /// Write a byte, least significant bit first.
+    fn write_byte(&mut self, byte: u8) -> anyhow::Result<()> {
+        (0..8).try_for_each(|i| self.write_bit(byte & (1 << i) != 0))
+    }
+
+    /// Read a byte, least significant bit first.
+    fn read_byte(&mut self) -> anyhow::Result<u8> {
+        (0..8).try_fold(0, |byte, i| Ok(byte | (u8::from(self.read_bit()?) << i)))
+    }
+
+    /// Reset the bus and address all devices on it.
+    fn skip_rom(&mut self) -> anyhow::Result<()> {
+        if !self.reset()? {
+            return Err(anyhow!("no 1-Wire devices present"));
+        }
+        self.write_byte(SKIP_ROM)
+    }
+
+    /// Reset the bus and address the device with the given ROM code.
+    fn match_rom(&mut self, rom: &Rom) -> anyhow::Result<()> {
+        if !self.reset()? {
+            return Err(anyhow!("no 1-Wire devices present"));
+        }
+        self.write_byte(MATCH_ROM)?;
+        rom.0.iter().try_for_each(|byte| self.write_byte(*byte))
+    }
+
+    /// Discover the ROM codes of all devices on the bus.
+    fn search(&mut self) -> anyhow::Result<Vec<Rom>> {
+        let mut roms = Vec::new();
+        let mut rom = [0_u8; 8];
+        // the bit index (1-based) of the last branch at which the 0 path was taken
+        let mut last_discrepancy = 0;
+
+        loop {
+            if !self.reset()? {
+                return Ok(roms);
+            }
+            self.write_byte(SEARCH_ROM)?;
+
+            let mut last_zero = 0;
+            for index in 1..=64 {
+                let (byte, mask) = ((index - 1) / 8, 1 << ((index - 1) % 8));
+                let bit = self.read_bit()?;
+                let complement = self.read_bit()?;
+
+                let direction = match (bit, complement) {
+                    (true, true) => return Err(anyhow!("no 1-Wire device responded to search")),
+                    // all remaining devices have the same bit
+                    (bit, complement) if bit != complement => bit,
+                    // the devices disagree: take the 0 path at new branches, retrace at earlier ones
+                    _ => {
+                        let direction = if index < last_discrepancy {
+                            rom[byte] & mask != 0
+                        } else {
+                            index == last_discrepancy
+                        };
+                        if !direction {
+                            last_zero = index;
+                        }
+                        direction
+                    }
+                };
+
+                if direction {
+                    rom[byte] |= mask;
+                } else {
+                    rom[byte] &= !mask;
+                }
+                self.write_bit(direction)?;
+            }
+
+            let found = Rom(rom);
+            if !found.is_valid() {
+                return Err(anyhow!("invalid CRC in 1-Wire ROM code {}", found));
+            }
+            roms.push(found);
+
+            last_discrepancy = last_zero;
+            if last_discrepancy == 0 {
+                return Ok(roms);
+            }
+        }
+    }
+}
+
+/// A 1-Wire bus bit-banged on an open-drain pin with an external pull-up resistor.
+///
+/// The timing-critical part of each time slot runs with interrupts disabled, as e.g. a Wi-Fi interrupt during the
+/// 15 µs after the start of a read time slot would corrupt the bit.
+pub struct OneWirePin<P> {
+    pin: P,
+}
+
+impl<P, E> OneWirePin<P>
+where
+    P: InputPin<Error = E> + OutputPin<Error = E>,
+    E: Debug,
+{
+    pub fn new(pin: P) -> Self {
+        OneWirePin { pin }
+    }
+
+    fn pull_low(&mut self) -> anyhow::Result<()> {
+        self.pin.set_low().map_err(pin_error)
+    }
+
+    fn release(&mut self) -> anyhow::Result<()> {
+        self.pin.set_high().map_err(pin_error)
+    }
+
+    fn is_high(&self) -> anyhow::Result<bool> {
+        self.pin.is_high().map_err(pin_error)
+    }
+}
+
+impl<P, E> OneWireBus for OneWirePin<P>
+where
+    P: InputPin<Error = E> + OutputPin<Error = E>,
+    E: Debug,
+{
+    fn reset(&mut self) -> anyhow::Result<bool> {
+        let present = interrupt::free(|| {
+            self.pull_low()?;
+            Ets::delay_us(480);
+            self.release()?;
+            Ets::delay_us(70);
+            // devices signal their presence by pulling the bus low
+            self.is_high().map(|high| !high)
+        })?;
+        // the rest of the presence pulse may take longer
+        Ets::delay_us(410);
+        Ok(present)
+    }
+
+    fn write_bit(&mut self, bit: bool) -> anyhow::Result<()> {
+        let (low, high) = if bit { (6, 64) } else { (60, 10) };
+        interrupt::free(|| {
+            self.pull_low()?;
+            Ets::delay_us(low);
+            self.release()?;
+            Ets::delay_us(high);
+            Ok(())
+        })
+    }
+
+    fn read_bit(&mut self) -> anyhow::Result<bool> {
+        let bit = interrupt::free(|| {
+            self.pull_low()?;
+            Ets::delay_us(6);
+            self.release()?;
+            Ets::delay_us(9);
+            self.is_high()
+        })?;
+        // the rest of the time slot may take longer
+        Ets::delay_us(55);
+        Ok(bit)
+    }
+}
+
+fn pin_error<E: Debug>(e: E) -> anyhow::Error {
+    anyhow!("1-Wire pin error ({:?})", e)
+}
+
+/// A simulated 1-Wire bus, for testing 1-Wire device drivers on the host.
+#[cfg(test)]
+pub mod simulated {
+    use super::*;
+
+    /// A device on a simulated bus.
+    pub trait SimulatedDevice {
+        fn rom(&self) -> Rom;
+        /// Handle a function command byte sent to this device.
+        fn command(&mut self, command: u8);
+        /// Handle a data byte sent to this device after `command`.
+        fn write(&mut self, command: u8, byte: u8);
+        /// Returns the bit this device writes in a read time slot after `command`.
+        fn read(&mut self, command: u8) -> bool;
+    }
+
+    enum State {
+        Idle,
+        RomCommand,
+        Search { index: usize, slot: u8 },
+        MatchRom { rom: Vec<u8> },
+        FunctionCommand,
+        Function { command: u8 },
+    }
+
+    /// A simulated 1-Wire bus with the given devices on it.
+    pub struct SimulatedBus {
+        pub devices: Vec<Box<dyn SimulatedDevice>>,
+        selected: Vec<bool>,
+        state: State,
+        bits: Vec<bool>,
+    }
+
+    impl SimulatedBus {
+        pub fn new(devices: Vec<Box<dyn SimulatedDevice>>) -> Self {
+            SimulatedBus {
+                selected: vec![false; devices.len()],
+                devices,
+                state: State::Idle,
+                bits: Vec::new(),
+            }
+        }
+
+        fn rom_bit(rom: &Rom, index: usize) -> bool {
+            rom.0[index / 8] & (1 << (index % 8)) != 0
+        }
+
+        fn selected_devices(&mut self) -> impl Iterator<Item = &mut Box<dyn SimulatedDevice>> {
+            self.devices
+                .iter_mut()
+                .zip(self.selected.iter())
+                .filter(|(_, selected)| **selected)
+                .map(|(device, _)| device)
+        }
+
+        fn write_byte(&mut self, byte: u8) {
+            self.state = match std::mem::replace(&mut self.state, State::Idle) {
+                State::Idle => State::Idle,
+                State::RomCommand => match byte {
+                    SKIP_ROM => {
+                        self.selected
+                            .iter_mut()
+                            .for_each(|selected| *selected = true);
+                        State::FunctionCommand
+                    }
+                    MATCH_ROM => State::MatchRom { rom: Vec::new() },
+                    SEARCH_ROM => {
+                        self.selected
+                            .iter_mut()
+                            .for_each(|selected| *selected = true);
+                        State::Search { index: 0, slot: 0 }
+                    }
+                    _ => State::Idle,
+                },
+                State::MatchRom { mut rom } => {
+                    rom.push(byte);
+                    if rom.len() < 8 {
+                        State::MatchRom { rom }
+                    } else {
+                        for (device, selected) in self.devices.iter().zip(self.selected.iter_mut())
+                        {
+                            *selected = device.rom().0[..] == rom[..];
+                        }
+                        State::FunctionCommand
+                    }
+                }
+                State::FunctionCommand => {
+                    self.selected_devices()
+                        .for_each(|device| device.command(byte));
+                    State::Function { command: byte }
+                }
+                State::Function { command } => {
+                    self.selected_devices()
+                        .for_each(|device| device.write(command, byte));
+                    State::Function { command }
+                }
+                search @ State::Search { .. } => search,
+            };
+        }
+    }
+
+    impl OneWireBus for SimulatedBus {
+        fn reset(&mut self) -> anyhow::Result<bool> {
+            self.state = State::RomCommand;
+            self.bits.clear();
+            self.selected
+                .iter_mut()
+                .for_each(|selected| *selected = false);
+            Ok(!self.devices.is_empty())
+        }
+
+        fn write_bit(&mut self, bit: bool) -> anyhow::Result<()> {
+            if let State::Search { index, slot: 2 } = self.state {
+                // deselect the devices that don't match the chosen direction
+                for (device, selected) in self.devices.iter().zip(self.selected.iter_mut()) {
+                    *selected &= Self::rom_bit(&device.rom(), index) == bit;
+                }
+                self.state = if index < 63 {
+                    State::Search {
+                        index: index + 1,
+                        slot: 0,
+                    }
+                } else {
+                    State::Idle
+                };
+                return Ok(());
+            }
+
+            self.bits.push(bit);
+            if self.bits.len() == 8 {
+                let byte = self
+                    .bits
+                    .drain(..)
+                    .enumerate()
+                    .fold(0, |byte, (i, bit)| byte | (u8::from(bit) << i));
+                self.write_byte(byte);
+            }
+            Ok(())
+        }
+
+        fn read_bit(&mut self) -> anyhow::Result<bool> {
+            // the bus is pulled up, so it reads high unless any device pulls it low
+            let bit = match self.state {
+                State::Search { index, slot } if slot < 2 => {
+                    let complement = slot == 1;
+                    self.state = State::Search {
+                        index,
+                        slot: slot + 1,
+                    };
+                    self.devices
+                        .iter()
+                        .zip(self.selected.iter())
+                        .filter(|(_, selected)| **selected)
+                        .all(|(device, _)| Self::rom_bit(&device.rom(), index) != complement)
+                }
+                State::Function { command } => {
+                    let mut bit = true;
+                    for device in self.selected_devices() {
+                        bit &= device.read(command);
+                    }
+                    bit
+                }
+                _ => true,
+            };
+            Ok(bit)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{simulated::*, *};
+
+    struct Device(Rom);
+
+    impl SimulatedDevice for Device {
+        fn rom(&self) -> Rom {
+            self.0
+        }
+
+        fn command(&mut self, _command: u8) {}
+
+        fn write(&mut self, _command: u8, _byte: u8) {}
+
+        fn read(&mut self, _command: u8) -> bool {
+            true
+        }
+    }
+
+    const ROMS: [Rom; 3] = [
+        Rom([0x28, 0xff, 0x4c, 0x1d, 0x62, 0x16, 0x03, 0x6b]),
+        Rom([0x28, 0x61, 0x64, 0x12, 0x3c, 0x7c, 0x2f, 0x27]),
+        Rom([0x28, 0x61, 0x64, 0x12, 0x3c, 0x7c, 0x30, 0xfb]),
+    ];
+
+    #[test]
+    fn crc8_matches_maxim_example() {
+        // from Maxim application note 27
+        assert_eq!(crc8(&[0x02, 0x1c, 0xb8, 0x01, 0x00, 0x00, 0x00]), 0xa2);
+        assert!(ROMS.iter().all(Rom::is_valid));
+        assert!(!Rom([0x28, 0xff, 0x4c, 0x1d, 0x62, 0x16, 0x03, 0x6c]).is_valid());
+    }
+
+    #[test]
+    fn rom_display() {
+        assert_eq!(ROMS[0].to_string(), "28ff4c1d6216036b");
+    }
+
+    #[test]
+    fn search_finds_all_devices() {
+        let mut bus = SimulatedBus::new(
+            ROMS.iter()
+                .map(|rom| Box::new(Device(*rom)) as Box<dyn SimulatedDevice>)
+                .collect(),
+        );
+        let mut found = bus.search().unwrap();
+        found.sort_by_key(|rom| rom.0);
+        let mut expected = ROMS.to_vec();
+        expected.sort_by_key(|rom| rom.0);
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn search_on_empty_bus() {
+        let mut bus = SimulatedBus::new(vec![]);
+        assert_eq!(bus.search().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn search_rejects_invalid_crc() {
+        let mut bus = SimulatedBus::new(vec![Box::new(Device(Rom([
+            0x28, 0xff, 0x4c, 0x1d, 0x62, 0x16, 0x03, 0x00,
+        ])))]);
+        assert!(bus.search().is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{
+    peripherals::temperature_sensor::{SensorReading, TemperatureSensor},
+    types::{Percent, Pressure, Temperature, UnitPressure},
+    util::celsius,
+};
+use anyhow::anyhow;
+use bme280_rs::{Bme280, Configuration, Oversampling, Sample, SensorMode, CHIP_ID};
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+use esp_idf_hal::delay::FreeRtos;
+use std::fmt::Debug;
+
+/// A BME280 temperature, humidity and pressure sensor, connected over I²C.
+///
+/// Measurements are taken on demand (in forced mode), so the sensor doesn't heat itself up in between.
+pub struct BME280<I2C> {
+    sensor: Bme280<I2C, FreeRtos>,
+}
+
+impl<I2C, E> BME280<I2C>
+where
+    I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    E: Debug,
+{
+    /// Returns the sensor at the default address on the given bus, after checking it is actually a BME280.
+    pub fn connected_to(i2c: I2C) -> anyhow::Result<Self> {
+        let mut sensor = Bme280::new(i2c, FreeRtos);
+        sensor.init().map_err(i2c_error)?;
+
+        let chip_id = sensor.chip_id().map_err(i2c_error)?;
+        if chip_id != CHIP_ID {
+            return Err(anyhow!("unexpected BME280 chip id {:#x}", chip_id));
+        }
+
+        sensor
+            .set_sampling_configuration(
+                Configuration::default()
+                    .with_temperature_oversampling(Oversampling::Oversample1)
+                    .with_pressure_oversampling(Oversampling::Oversample1)
+                    .with_humidity_oversampling(Oversampling::Oversample1)
+                    .with_sensor_mode(SensorMode::Forced),
+            )
+            .map_err(i2c_error)?;
+
+        Ok(BME280 { sensor })
+    }
+}
+
+impl<I2C, E> TemperatureSensor for BME280<I2C>
+where
+    I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    E: Debug,
+{
+    fn measure_temperature(&mut self) -> anyhow::Result<Temperature> {
+        Ok(self.measure()?.temperature)
+    }
+
+    fn measure(&mut self) -> anyhow::Result<SensorReading> {
+        self.sensor.take_forced_measurement().map_err(i2c_error)?;
+        let sample = self.sensor.read_sample().map_err(i2c_error)?;
+        sample_to_reading(sample)
+    }
+}
+
+fn sample_to_reading((temperature, pressure, humidity): Sample) -> anyhow::Result<SensorReading> {
+    Ok(SensorReading {
+        temperature: celsius(temperature.ok_or_else(|| anyhow!("BME280 temperature unavailable"))?),
+        humidity: humidity.map(Percent::from_f32),
+        pressure: pressure.map(|pascals| Pressure::new(pascals, UnitPressure::pascals())),
+    })
+}
+
+fn i2c_error<E: Debug>(e: E) -> anyhow::Error {
+    anyhow!("BME280 I2C error ({:?})", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hectopascals;
+
+    #[test]
+    fn bme280_convert_sample() {
+        let reading = sample_to_reading((Some(21.5), Some(101325.0), Some(45.25))).unwrap();
+        assert_eq!(reading.temperature, celsius(21.5));
+        assert_eq!(reading.humidity, Some(Percent::from_f32(45.25)));
+        assert_eq!(reading.pressure, Some(hectopascals(1013.25)));
+    }
+
+    #[test]
+    fn bme280_sample_without_temperature() {
+        assert!(sample_to_reading((None, None, None)).is_err());
+    }
+}
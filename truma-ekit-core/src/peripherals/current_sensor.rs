@@ -0,0 +1,37 @@
+use crate::{adc::AdcInputPin, types::Current, util::amperes};
+
+/// A current sensor with an output voltage proportional to the measured (RMS) current.
+pub struct CurrentSensor<'a> {
+    input: AdcInputPin<'a>,
+    amperes_per_volt: f32,
+}
+
+impl<'a> CurrentSensor<'a> {
+    /// Returns a current sensor with a transfer ratio of `amperes_per_volt`, connected to the given input.
+    pub fn connected_to(input: AdcInputPin<'a>, amperes_per_volt: f32) -> Self {
+        CurrentSensor {
+            input,
+            amperes_per_volt,
+        }
+    }
+
+    fn millivolts_to_current(&self, millivolts: u16) -> Current {
+        amperes(f32::from(millivolts) / 1000.0 * self.amperes_per_volt)
+    }
+
+    pub fn measure_current(&mut self) -> anyhow::Result<Current> {
+        let val = self.input.read()?;
+        Ok(self.millivolts_to_current(val))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_sensor_convert_to_current() {
+        let mut sensor = CurrentSensor::connected_to(AdcInputPin::test(450), 10.0);
+        assert_eq!(sensor.measure_current().unwrap(), amperes(4.5));
+    }
+}
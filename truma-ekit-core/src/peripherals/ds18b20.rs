@@ -0,0 +1,368 @@
+use crate::{
+    onewire::{crc8, OneWireBus, Rom},
+    peripherals::temperature_sensor::TemperatureSensor,
+    types::Temperature,
+    util::celsius,
+};
+use anyhow::anyhow;
+use embedded_hal::blocking::delay::DelayMs;
+
+/// The 1-Wire family code of the DS18B20.
+pub const DS18B20_FAMILY_CODE: u8 = 0x28;
+
+/// The function command to start a temperature conversion.
+const CONVERT_T: u8 = 0x44;
+/// The function command to read the scratchpad.
+const READ_SCRATCHPAD: u8 = 0xbe;
+/// The function command to write the alarm thresholds and configuration to the scratchpad.
+const WRITE_SCRATCHPAD: u8 = 0x4e;
+
+/// The raw temperature in the scratchpad after power-on (85 °C), until a conversion completes.
+///
+/// Read if the sensor reset (e.g. due to a brown-out) during the conversion, so it's treated as a failed reading, even
+/// though a genuine 85 °C reads the same.
+const POWER_ON_RESET_RAW: i16 = 0x0550;
+
+/// The alarm thresholds written along with the configuration (the power-on defaults), unused by the driver.
+const ALARM_HIGH: u8 = 0x4b;
+const ALARM_LOW: u8 = 0x46;
+
+/// The resolution of the temperature conversion, a higher resolution takes longer to convert.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum Resolution {
+    /// 0.5 °C
+    Bits9,
+    /// 0.25 °C
+    Bits10,
+    /// 0.125 °C
+    Bits11,
+    /// 0.0625 °C
+    #[default]
+    Bits12,
+}
+
+impl Resolution {
+    /// Returns the maximum time a conversion takes, in milliseconds.
+    pub fn conversion_time_ms(&self) -> u32 {
+        match self {
+            Resolution::Bits9 => 94,
+            Resolution::Bits10 => 188,
+            Resolution::Bits11 => 375,
+            Resolution::Bits12 => 750,
+        }
+    }
+
+    fn config(&self) -> u8 {
+        match self {
+            Resolution::Bits9 => 0x1f,
+            Resolution::Bits10 => 0x3f,
+            Resolution::Bits11 => 0x5f,
+            Resolution::Bits12 => 0x7f,
+        }
+    }
+
+    /// Returns the mask of the temperature bits that are defined at this resolution.
+    fn mask(&self) -> i16 {
+        match self {
+            Resolution::Bits9 => !0b111,
+            Resolution::Bits10 => !0b11,
+            Resolution::Bits11 => !0b1,
+            Resolution::Bits12 => !0,
+        }
+    }
+}
+
+/// The DS18B20 temperature sensors on a 1-Wire bus.
+///
+/// All sensors convert simultaneously, after which each sensor's reading is read individually.
+pub struct DS18B20<B, D> {
+    bus: B,
+    delay: D,
+    resolution: Resolution,
+    sensors: Vec<Rom>,
+}
+
+impl<B, D> DS18B20<B, D>
+where
+    B: OneWireBus,
+    D: DelayMs<u32>,
+{
+    /// Returns the DS18B20 sensors found on the given bus.
+    ///
+    /// Fails if there are none.
+    pub fn connected_to(mut bus: B, delay: D) -> anyhow::Result<Self> {
+        let sensors: Vec<_> = bus
+            .search()?
+            .into_iter()
+            .filter(|rom| rom.family_code() == DS18B20_FAMILY_CODE)
+            .collect();
+        if sensors.is_empty() {
+            return Err(anyhow!("no DS18B20 sensors found"));
+        }
+
+        Ok(DS18B20 {
+            bus,
+            delay,
+            resolution: Resolution::default(),
+            sensors,
+        })
+    }
+
+    /// Configure all sensors to convert at the given resolution.
+    pub fn with_resolution(mut self, resolution: Resolution) -> anyhow::Result<Self> {
+        self.bus.skip_rom()?;
+        self.bus.write_byte(WRITE_SCRATCHPAD)?;
+        self.bus.write_byte(ALARM_HIGH)?;
+        self.bus.write_byte(ALARM_LOW)?;
+        self.bus.write_byte(resolution.config())?;
+        self.resolution = resolution;
+        Ok(self)
+    }
+
+    /// Returns the ROM codes of the sensors on the bus.
+    pub fn sensors(&self) -> &[Rom] {
+        &self.sensors
+    }
+
+    /// Measure the temperature of all sensors on the bus.
+    ///
+    /// Fails if the conversion couldn't be started, otherwise the reading of each sensor is returned separately.
+    pub fn measure_temperatures(
+        &mut self,
+    ) -> anyhow::Result<Vec<(Rom, anyhow::Result<Temperature>)>> {
+        self.bus.skip_rom()?;
+        self.bus.write_byte(CONVERT_T)?;
+        self.delay.delay_ms(self.resolution.conversion_time_ms());
+
+        let sensors = self.sensors.clone();
+        Ok(sensors
+            .into_iter()
+            .map(|rom| (rom, self.read_temperature(&rom)))
+            .collect())
+    }
+
+    fn read_temperature(&mut self, rom: &Rom) -> anyhow::Result<Temperature> {
+        self.bus.match_rom(rom)?;
+        self.bus.write_byte(READ_SCRATCHPAD)?;
+        let mut scratchpad = [0_u8; 9];
+        for byte in scratchpad.iter_mut() {
+            *byte = self.bus.read_byte()?;
+        }
+
+        // a disconnected sensor reads all ones, which would otherwise pass the CRC check
+        if scratchpad.iter().all(|byte| *byte == 0xff) || crc8(&scratchpad[..8]) != scratchpad[8] {
+            return Err(anyhow!("invalid scratchpad read from DS18B20 {}", rom));
+        }
+
+        let raw = i16::from_le_bytes([scratchpad[0], scratchpad[1]]) & self.resolution.mask();
+        if raw == POWER_ON_RESET_RAW {
+            return Err(anyhow!(
+                "power-on reset value read from DS18B20 {}, no conversion completed",
+                rom
+            ));
+        }
+        Ok(celsius(f32::from(raw) / 16.0))
+    }
+}
+
+/// Measures the highest temperature of all sensors on the bus.
+///
+/// Fails if any of the sensors couldn't be read, as the highest temperature might be the one missing.
+impl<B, D> TemperatureSensor for DS18B20<B, D>
+where
+    B: OneWireBus,
+    D: DelayMs<u32>,
+{
+    fn measure_temperature(&mut self) -> anyhow::Result<Temperature> {
+        let mut highest: Option<Temperature> = None;
+        for (_, reading) in self.measure_temperatures()? {
+            let temperature = reading?;
+            if highest.is_none_or(|highest| temperature > highest) {
+                highest = Some(temperature);
+            }
+        }
+        highest.ok_or_else(|| anyhow!("no DS18B20 readings"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::onewire::simulated::{SimulatedBus, SimulatedDevice};
+    use std::{cell::Cell, rc::Rc};
+
+    struct SimulatedDS18B20 {
+        rom: Rom,
+        temperature: f32,
+        config: u8,
+        corrupt: bool,
+        scratchpad: Vec<bool>,
+        written: usize,
+    }
+
+    impl SimulatedDS18B20 {
+        fn new(rom: Rom, temperature: f32) -> Self {
+            SimulatedDS18B20 {
+                rom,
+                temperature,
+                config: Resolution::Bits12.config(),
+                corrupt: false,
+                scratchpad: Vec::new(),
+                written: 0,
+            }
+        }
+    }
+
+    impl SimulatedDevice for SimulatedDS18B20 {
+        fn rom(&self) -> Rom {
+            self.rom
+        }
+
+        fn command(&mut self, command: u8) {
+            match command {
+                READ_SCRATCHPAD => {
+                    let raw = (self.temperature * 16.0) as i16;
+                    let [lsb, msb] = raw.to_le_bytes();
+                    let mut bytes = vec![
+                        lsb,
+                        msb,
+                        ALARM_HIGH,
+                        ALARM_LOW,
+                        self.config,
+                        0xff,
+                        0x0c,
+                        0x10,
+                    ];
+                    bytes.push(crc8(&bytes));
+                    if self.corrupt {
+                        bytes[0] ^= 0x01;
+                    }
+                    self.scratchpad = bytes
+                        .iter()
+                        .flat_map(|byte| (0..8).map(move |i| byte & (1 << i) != 0))
+                        .rev()
+                        .collect();
+                }
+                WRITE_SCRATCHPAD => self.written = 0,
+                _ => {}
+            }
+        }
+
+        fn write(&mut self, command: u8, byte: u8) {
+            if command == WRITE_SCRATCHPAD {
+                if self.written == 2 {
+                    self.config = byte;
+                }
+                self.written += 1;
+            }
+        }
+
+        fn read(&mut self, command: u8) -> bool {
+            match command {
+                READ_SCRATCHPAD => self.scratchpad.pop().unwrap_or(true),
+                _ => true,
+            }
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Delay(Rc<Cell<u32>>);
+
+    impl DelayMs<u32> for Delay {
+        fn delay_ms(&mut self, ms: u32) {
+            self.0.set(self.0.get() + ms);
+        }
+    }
+
+    const ROMS: [Rom; 2] = [
+        Rom([0x28, 0xff, 0x4c, 0x1d, 0x62, 0x16, 0x03, 0x6b]),
+        Rom([0x28, 0x61, 0x64, 0x12, 0x3c, 0x7c, 0x2f, 0x27]),
+    ];
+
+    fn bus(sensors: Vec<SimulatedDS18B20>) -> SimulatedBus {
+        SimulatedBus::new(
+            sensors
+                .into_iter()
+                .map(|sensor| Box::new(sensor) as Box<dyn SimulatedDevice>)
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn ds18b20_measures_all_sensors() {
+        let delay = Delay::default();
+        let mut ds18b20 = DS18B20::connected_to(
+            bus(vec![
+                SimulatedDS18B20::new(ROMS[0], 21.5),
+                SimulatedDS18B20::new(ROMS[1], -10.125),
+            ]),
+            delay.clone(),
+        )
+        .unwrap();
+        assert_eq!(ds18b20.sensors().len(), 2);
+
+        let readings = ds18b20.measure_temperatures().unwrap();
+        for (rom, expected) in [(ROMS[0], 21.5), (ROMS[1], -10.125)] {
+            let (_, reading) = readings.iter().find(|(r, _)| *r == rom).unwrap();
+            assert_eq!(*reading.as_ref().unwrap(), celsius(expected));
+        }
+
+        // waited for the conversion once, for all sensors
+        assert_eq!(delay.0.get(), 750);
+    }
+
+    #[test]
+    fn ds18b20_resolution() {
+        let delay = Delay::default();
+        let mut ds18b20 = DS18B20::connected_to(
+            bus(vec![SimulatedDS18B20::new(ROMS[0], 21.4375)]),
+            delay.clone(),
+        )
+        .unwrap()
+        .with_resolution(Resolution::Bits9)
+        .unwrap();
+
+        assert_eq!(ds18b20.measure_temperature().unwrap(), celsius(21.0));
+        assert_eq!(delay.0.get(), 94);
+    }
+
+    #[test]
+    fn ds18b20_rejects_corrupt_readings() {
+        let mut corrupt = SimulatedDS18B20::new(ROMS[1], 95.0);
+        corrupt.corrupt = true;
+        let mut ds18b20 = DS18B20::connected_to(
+            bus(vec![SimulatedDS18B20::new(ROMS[0], 60.0), corrupt]),
+            Delay::default(),
+        )
+        .unwrap();
+
+        let readings = ds18b20.measure_temperatures().unwrap();
+        let (_, reading) = readings.iter().find(|(rom, _)| *rom == ROMS[1]).unwrap();
+        assert!(reading.is_err());
+
+        // the highest temperature might be the one that couldn't be read
+        assert!(ds18b20.measure_temperature().is_err());
+    }
+
+    #[test]
+    fn ds18b20_rejects_power_on_reset_value() {
+        let mut ds18b20 = DS18B20::connected_to(
+            bus(vec![
+                SimulatedDS18B20::new(ROMS[0], 60.0),
+                SimulatedDS18B20::new(ROMS[1], 85.0),
+            ]),
+            Delay::default(),
+        )
+        .unwrap();
+
+        let readings = ds18b20.measure_temperatures().unwrap();
+        let (_, reading) = readings.iter().find(|(rom, _)| *rom == ROMS[1]).unwrap();
+        assert!(reading.is_err());
+        assert!(ds18b20.measure_temperature().is_err());
+    }
+
+    #[test]
+    fn ds18b20_without_sensors() {
+        assert!(DS18B20::connected_to(bus(vec![]), Delay::default()).is_err());
+    }
+}
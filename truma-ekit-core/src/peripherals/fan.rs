@@ -21,6 +21,11 @@ where
         self.relay.is_closed()
     }
 
+    /// Returns the relay driving the fan.
+    pub fn relay(&self) -> &Relay<P> {
+        &self.relay
+    }
+
     /// Turn on the fan.
     pub fn turn_on(&mut self) {
         self.relay.close()
@@ -35,17 +40,18 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::RecordingPin;
 
     #[test]
     fn is_on() {
-        let mut relay = Relay::connected_to(TestPin(false));
+        let mut relay = Relay::connected_to(RecordingPin::new(false));
         relay.open();
         assert!(
             !Fan::new(relay).is_turned_on(),
             "fan incorrectly reports being turned on"
         );
 
-        let mut relay = Relay::connected_to(TestPin(false));
+        let mut relay = Relay::connected_to(RecordingPin::new(false));
         relay.close();
         assert!(
             Fan::new(relay).is_turned_on(),
@@ -55,7 +61,7 @@ mod tests {
 
     #[test]
     fn turn_on_closes_relay() {
-        let mut relay = Relay::connected_to(TestPin(false));
+        let mut relay = Relay::connected_to(RecordingPin::new(false));
         relay.open();
         let mut fan = Fan::new(relay);
         fan.turn_on();
@@ -65,37 +71,11 @@ mod tests {
 
     #[test]
     fn turn_off_opens_relay() {
-        let mut relay = Relay::connected_to(TestPin(false));
+        let mut relay = Relay::connected_to(RecordingPin::new(false));
         relay.close();
         let mut fan = Fan::new(relay);
         fan.turn_off();
         assert!(!fan.is_turned_on(), "fan is not turned off");
         assert!(!fan.relay.is_closed(), "fan did not open relay");
     }
-
-    struct TestPin(bool);
-
-    impl OutputPin for TestPin {
-        type Error = std::convert::Infallible;
-
-        fn set_high(&mut self) -> Result<(), Self::Error> {
-            self.0 = true;
-            Ok(())
-        }
-
-        fn set_low(&mut self) -> Result<(), Self::Error> {
-            self.0 = false;
-            Ok(())
-        }
-    }
-
-    impl StatefulOutputPin for TestPin {
-        fn is_set_high(&self) -> Result<bool, Self::Error> {
-            Ok(self.0)
-        }
-
-        fn is_set_low(&self) -> Result<bool, Self::Error> {
-            Ok(!self.0)
-        }
-    }
 }
@@ -1,3 +1,8 @@
+pub mod bme280;
+pub mod current_sensor;
+pub mod ds18b20;
 pub mod fan;
+pub mod ntc;
 pub mod relay;
+pub mod temperature_sensor;
 pub mod tmp36;
@@ -0,0 +1,147 @@
+use crate::{
+    adc::AdcInputPin,
+    measurement::{PiecewiseLinear, UnitConverter},
+    peripherals::temperature_sensor::TemperatureSensor,
+    types::{Temperature, UnitVoltage, Voltage},
+    util::celsius,
+};
+
+/// The resistance curve of a 10 kΩ NTC thermistor with a B value of 3950 K, converting °C to kΩ.
+pub const NTC_10K_B3950: UnitConverter =
+    UnitConverter::PiecewiseLinear(PiecewiseLinear::new_unchecked(&NTC_10K_B3950_POINTS));
+
+/// The resistance (kΩ) of a 10 kΩ NTC thermistor with a B value of 3950 K at temperatures (°C) from the datasheet table
+/// (in 5 °C steps).
+const NTC_10K_B3950_POINTS: [(f32, f32); 25] = [
+    (-20.0, 105.385),
+    (-15.0, 77.898),
+    (-10.0, 58.246),
+    (-5.0, 44.026),
+    (0.0, 33.621),
+    (5.0, 25.925),
+    (10.0, 20.175),
+    (15.0, 15.837),
+    (20.0, 12.535),
+    (25.0, 10.000),
+    (30.0, 8.037),
+    (35.0, 6.506),
+    (40.0, 5.301),
+    (45.0, 4.348),
+    (50.0, 3.588),
+    (55.0, 2.978),
+    (60.0, 2.486),
+    (65.0, 2.086),
+    (70.0, 1.760),
+    (75.0, 1.492),
+    (80.0, 1.270),
+    (85.0, 1.087),
+    (90.0, 0.934),
+    (95.0, 0.805),
+    (100.0, 0.698),
+];
+
+/// A polynomial fit of the resistance curve of a 10 kΩ B3950 NTC thermistor, converting °C to kΩ.
+///
+/// The fit is only accurate (to within 0.1 °C) between 0 °C and 40 °C.
+pub const NTC_10K_B3950_0_TO_40: UnitConverter = UnitConverter::Polynomial {
+    coeffs: &[33.59316, -1.753217, 0.04822314, -7.687656e-4, 5.430642e-6],
+};
+
+/// An NTC thermistor, connected between the input and ground, with a series resistor connected to the supply.
+pub struct Ntc<'a> {
+    input: AdcInputPin<'a>,
+    supply: Voltage,
+    series_resistance_kohms: f32,
+    curve: UnitConverter,
+}
+
+impl<'a> Ntc<'a> {
+    /// Returns a thermistor with the given resistance curve (converting °C to kΩ), connected to the given input.
+    pub fn connected_to(
+        input: AdcInputPin<'a>,
+        supply: Voltage,
+        series_resistance_kohms: f32,
+        curve: UnitConverter,
+    ) -> Self {
+        Ntc {
+            input,
+            supply,
+            series_resistance_kohms,
+            curve,
+        }
+    }
+
+    fn millivolts_to_temperature(&self, millivolts: u16) -> Temperature {
+        let voltage = f32::from(millivolts) / 1000.0;
+        let supply = self.supply.converted_to(UnitVoltage::volts()).value;
+        let resistance_kohms = self.series_resistance_kohms * voltage / (supply - voltage);
+        celsius(self.curve.to_base_unit(resistance_kohms))
+    }
+}
+
+impl<'a> TemperatureSensor for Ntc<'a> {
+    fn measure_temperature(&mut self) -> anyhow::Result<Temperature> {
+        let val = self.input.read()?;
+        Ok(self.millivolts_to_temperature(val))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{types::UnitTemperature, util::volts};
+    use assert_approx_eq::assert_approx_eq;
+
+    /// Resistances (kΩ) at temperatures (°C) in between the points of `NTC_10K_B3950`, from the datasheet.
+    const DATASHEET: [(f32, f32); 9] = [
+        (-12.0, 65.341),
+        (3.0, 28.733),
+        (17.0, 14.409),
+        (22.0, 11.441),
+        (28.0, 8.764),
+        (36.0, 6.241),
+        (44.0, 4.522),
+        (63.0, 2.237),
+        (87.0, 1.022),
+    ];
+
+    #[test]
+    fn ntc_convert_to_temperature() {
+        // half of the supply voltage, i.e. 10 kΩ
+        let mut ntc = Ntc::connected_to(AdcInputPin::test(1650), volts(3.3), 10.0, NTC_10K_B3950);
+        let temperature = ntc
+            .measure_temperature()
+            .unwrap()
+            .converted_to(UnitTemperature::celsius());
+        assert_approx_eq!(temperature.value, 25.0, 1e-3);
+    }
+
+    #[test]
+    fn ntc_table_is_valid() {
+        assert_eq!(
+            UnitConverter::piecewise_linear(&NTC_10K_B3950_POINTS).unwrap(),
+            NTC_10K_B3950
+        );
+    }
+
+    #[test]
+    fn ntc_table_matches_datasheet() {
+        for (temperature, resistance) in DATASHEET {
+            assert_approx_eq!(NTC_10K_B3950.to_base_unit(resistance), temperature, 0.25);
+        }
+    }
+
+    #[test]
+    fn ntc_polynomial_matches_datasheet() {
+        for (temperature, resistance) in DATASHEET
+            .into_iter()
+            .filter(|(temperature, _)| (0.0..=40.0).contains(temperature))
+        {
+            assert_approx_eq!(
+                NTC_10K_B3950_0_TO_40.to_base_unit(resistance),
+                temperature,
+                0.1
+            );
+        }
+    }
+}
@@ -1,7 +1,25 @@
+use crate::clock::{Clock, SystemClock};
 use embedded_hal::digital::v2::{OutputPin, StatefulOutputPin};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 pub struct Relay<P: OutputPin> {
     pin: P,
+    stats: RelayStats,
+    closed_since: Option<Instant>,
+    clock: Arc<dyn Clock>,
+}
+
+/// The accumulated usage of a relay.
+#[derive(Copy, Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct RelayStats {
+    /// The number of times the relay has been closed.
+    pub cycles: u64,
+    /// The total time the relay has been closed.
+    pub closed_duration: Duration,
 }
 
 impl<P> Relay<P>
@@ -10,7 +28,33 @@ where
 {
     /// Returns a new relay connected to the given pin.
     pub fn connected_to(pin: P) -> Self {
-        Relay { pin }
+        Relay {
+            pin,
+            stats: RelayStats::default(),
+            closed_since: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Measure the time the relay has been closed using the given clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Continue accumulating usage on top of the given (previously persisted) usage.
+    pub fn with_stats(mut self, stats: RelayStats) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Returns the accumulated usage of the relay, including the current closed period.
+    pub fn stats(&self) -> RelayStats {
+        let mut stats = self.stats;
+        if let Some(closed_since) = self.closed_since {
+            stats.closed_duration += self.clock.now().saturating_duration_since(closed_since);
+        }
+        stats
     }
 
     /// Returns `true` if the relay is currently closed, i.e. current is currently flowing.
@@ -27,36 +71,46 @@ where
     pub fn close(&mut self) {
         self.pin
             .set_high()
-            .unwrap_or_else(|_| panic!("failed to close relay"))
+            .unwrap_or_else(|_| panic!("failed to close relay"));
+
+        if self.closed_since.is_none() {
+            self.stats.cycles += 1;
+            self.closed_since = Some(self.clock.now());
+        }
     }
 
     /// Open the relay, i.e. stops the current flow.
     pub fn open(&mut self) {
         self.pin
             .set_low()
-            .unwrap_or_else(|_| panic!("failed to open relay"))
+            .unwrap_or_else(|_| panic!("failed to open relay"));
+
+        if let Some(closed_since) = self.closed_since.take() {
+            self.stats.closed_duration += self.clock.now().saturating_duration_since(closed_since);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{FakeClock, RecordingPin};
 
     #[test]
     fn relay_is_closed() {
         assert!(
-            Relay::connected_to(TestPin(true)).is_closed(),
+            Relay::connected_to(RecordingPin::new(true)).is_closed(),
             "relay incorrectly reports being open"
         );
         assert!(
-            !Relay::connected_to(TestPin(false)).is_closed(),
+            !Relay::connected_to(RecordingPin::new(false)).is_closed(),
             "relay incorrectly reports being closed"
         );
     }
 
     #[test]
     fn relay_close_sets_pin_high() {
-        let mut relay = Relay::connected_to(TestPin(false));
+        let mut relay = Relay::connected_to(RecordingPin::new(false));
         relay.close();
         assert!(
             relay.pin.is_set_high().unwrap(),
@@ -66,34 +120,61 @@ mod tests {
 
     #[test]
     fn relay_open_sets_pin_low() {
-        let mut relay = Relay::connected_to(TestPin(true));
+        let mut relay = Relay::connected_to(RecordingPin::new(true));
         relay.open();
         assert!(relay.pin.is_set_low().unwrap(), "relay did not set pin low");
     }
 
-    struct TestPin(bool);
+    #[test]
+    fn relay_counts_cycles() {
+        let mut relay = Relay::connected_to(RecordingPin::new(false));
+        assert_eq!(relay.stats().cycles, 0);
 
-    impl OutputPin for TestPin {
-        type Error = std::convert::Infallible;
+        relay.close();
+        relay.close();
+        assert_eq!(relay.stats().cycles, 1, "repeated close counted as a cycle");
 
-        fn set_high(&mut self) -> Result<(), Self::Error> {
-            self.0 = true;
-            Ok(())
-        }
+        relay.open();
+        relay.open();
+        relay.close();
+        assert_eq!(relay.stats().cycles, 2);
+    }
 
-        fn set_low(&mut self) -> Result<(), Self::Error> {
-            self.0 = false;
-            Ok(())
-        }
+    #[test]
+    fn relay_continues_from_persisted_stats() {
+        let mut relay = Relay::connected_to(RecordingPin::new(false)).with_stats(RelayStats {
+            cycles: 41,
+            closed_duration: Duration::from_secs(3600),
+        });
+        relay.close();
+        relay.open();
+
+        let stats = relay.stats();
+        assert_eq!(stats.cycles, 42);
+        assert!(stats.closed_duration >= Duration::from_secs(3600));
     }
 
-    impl StatefulOutputPin for TestPin {
-        fn is_set_high(&self) -> Result<bool, Self::Error> {
-            Ok(self.0)
-        }
+    #[test]
+    fn relay_measures_closed_duration() {
+        let clock = FakeClock::new();
+        let mut relay =
+            Relay::connected_to(RecordingPin::new(false)).with_clock(Arc::new(clock.clone()));
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(relay.stats().closed_duration, Duration::ZERO);
 
-        fn is_set_low(&self) -> Result<bool, Self::Error> {
-            Ok(!self.0)
-        }
+        relay.close();
+        clock.advance(Duration::from_secs(30));
+        // includes the current closed period
+        assert_eq!(relay.stats().closed_duration, Duration::from_secs(30));
+
+        relay.open();
+        clock.advance(Duration::from_secs(60));
+        relay.close();
+        clock.advance(Duration::from_secs(15));
+        relay.open();
+        assert_eq!(relay.stats().closed_duration, Duration::from_secs(45));
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(relay.stats().closed_duration, Duration::from_secs(45));
     }
 }
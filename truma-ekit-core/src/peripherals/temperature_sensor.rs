@@ -0,0 +1,66 @@
+use crate::{
+    powersaving::Powered,
+    types::{Percent, Pressure, Temperature},
+};
+use embedded_hal::digital::v2::OutputPin;
+
+/// A reading of a temperature sensor, including the humidity and pressure if the sensor measures them.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SensorReading {
+    pub temperature: Temperature,
+    /// The relative humidity.
+    pub humidity: Option<Percent>,
+    pub pressure: Option<Pressure>,
+}
+
+/// A sensor measuring temperature.
+pub trait TemperatureSensor {
+    /// Measure the temperature.
+    fn measure_temperature(&mut self) -> anyhow::Result<Temperature>;
+
+    /// Measure the temperature, and the humidity and pressure if the sensor supports it.
+    fn measure(&mut self) -> anyhow::Result<SensorReading> {
+        Ok(SensorReading {
+            temperature: self.measure_temperature()?,
+            humidity: None,
+            pressure: None,
+        })
+    }
+}
+
+/// A sensor that is only powered up while measuring.
+impl<S: TemperatureSensor, VCC: OutputPin> TemperatureSensor for Powered<S, VCC> {
+    fn measure_temperature(&mut self) -> anyhow::Result<Temperature> {
+        self.power_up().measure_temperature()
+    }
+
+    fn measure(&mut self) -> anyhow::Result<SensorReading> {
+        self.power_up().measure()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::celsius;
+
+    struct Sensor;
+
+    impl TemperatureSensor for Sensor {
+        fn measure_temperature(&mut self) -> anyhow::Result<Temperature> {
+            Ok(celsius(21.0))
+        }
+    }
+
+    #[test]
+    fn measure_defaults_to_temperature_only() {
+        assert_eq!(
+            Sensor.measure().unwrap(),
+            SensorReading {
+                temperature: celsius(21.0),
+                humidity: None,
+                pressure: None,
+            }
+        );
+    }
+}
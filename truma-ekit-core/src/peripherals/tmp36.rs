@@ -1,35 +1,246 @@
-use crate::{adc::AdcInputPin, types::Temperature, util::celsius};
+use crate::{
+    adc::AdcInputPin,
+    measurement::UnitConverter,
+    peripherals::temperature_sensor::TemperatureSensor,
+    types::{Temperature, UnitTemperature},
+    util::celsius,
+};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+/// The transfer function of the TMP36 according to its datasheet, converting °C to V (10 mV/°C, 750 mV at 25 °C).
+pub const TMP36_TRANSFER_FUNCTION: UnitConverter = UnitConverter::Linear {
+    coeff: 0.01,
+    constant: 0.5,
+};
+
+/// The range of calibration gains accepted, as a TMP36 is accurate to within a few percent.
+pub const CALIBRATION_GAIN_RANGE: (f32, f32) = (0.8, 1.2);
+/// The largest calibration offset (in °C) accepted, in either direction.
+pub const MAX_CALIBRATION_OFFSET_CELSIUS: f32 = 5.0;
+
+/// The minimum difference (in °C) between the measured temperatures of the two calibration points.
+const MIN_CALIBRATION_POINT_DISTANCE_CELSIUS: f32 = 1.0;
+
+/// A two-point calibration of a TMP36, correcting a measured temperature `t` (in °C) to `gain * t + offset_celsius`.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Tmp36Calibration {
+    pub offset_celsius: f32,
+    pub gain: f32,
+}
+
+impl Default for Tmp36Calibration {
+    fn default() -> Self {
+        Tmp36Calibration {
+            offset_celsius: 0.0,
+            gain: 1.0,
+        }
+    }
+}
+
+impl Tmp36Calibration {
+    /// Returns the calibration correcting two `(measured, actual)` temperatures, e.g. measured in ice water and at room
+    /// temperature against a reference thermometer.
+    ///
+    /// Returns an error if the measured temperatures are too close together to derive the gain from, or if the resulting
+    /// calibration is out of range.
+    pub fn from_points(
+        first: (Temperature, Temperature),
+        second: (Temperature, Temperature),
+    ) -> anyhow::Result<Self> {
+        let celsius =
+            |temperature: Temperature| temperature.converted_to(UnitTemperature::celsius()).value;
+        let (measured_1, actual_1) = (celsius(first.0), celsius(first.1));
+        let (measured_2, actual_2) = (celsius(second.0), celsius(second.1));
+
+        if (measured_2 - measured_1).abs() < MIN_CALIBRATION_POINT_DISTANCE_CELSIUS {
+            return Err(anyhow!(
+                "measured temperatures {} °C and {} °C are less than {} °C apart",
+                measured_1,
+                measured_2,
+                MIN_CALIBRATION_POINT_DISTANCE_CELSIUS
+            ));
+        }
+        let gain = (actual_2 - actual_1) / (measured_2 - measured_1);
+        Tmp36Calibration {
+            offset_celsius: actual_1 - gain * measured_1,
+            gain,
+        }
+        .validated()
+    }
+
+    /// Returns the calibration if its gain and offset are within the accepted ranges, or an error otherwise.
+    ///
+    /// A calibration out of range would e.g. keep overtemperature protection from triggering.
+    pub fn validated(self) -> anyhow::Result<Self> {
+        let (min_gain, max_gain) = CALIBRATION_GAIN_RANGE;
+        if !(min_gain..=max_gain).contains(&self.gain) {
+            return Err(anyhow!(
+                "gain {} is out of the range {}..={}",
+                self.gain,
+                min_gain,
+                max_gain
+            ));
+        }
+        if !(-MAX_CALIBRATION_OFFSET_CELSIUS..=MAX_CALIBRATION_OFFSET_CELSIUS)
+            .contains(&self.offset_celsius)
+        {
+            return Err(anyhow!(
+                "offset {} °C exceeds ±{} °C",
+                self.offset_celsius,
+                MAX_CALIBRATION_OFFSET_CELSIUS
+            ));
+        }
+        Ok(self)
+    }
+
+    /// Returns the corrected `temperature`.
+    pub fn apply(&self, temperature: Temperature) -> Temperature {
+        let measured = temperature.converted_to(UnitTemperature::celsius()).value;
+        celsius(self.gain * measured + self.offset_celsius)
+    }
+}
 
 pub struct TMP36<'a> {
     input: AdcInputPin<'a>,
+    transfer_function: UnitConverter,
 }
 
 impl<'a> TMP36<'a> {
     pub fn connected_to(input: AdcInputPin<'a>) -> Self {
-        TMP36 { input }
+        TMP36 {
+            input,
+            transfer_function: TMP36_TRANSFER_FUNCTION,
+        }
+    }
+
+    /// Use the given transfer function (converting °C to V) instead of the datasheet's, e.g. one interpolating
+    /// between calibration points.
+    pub fn with_transfer_function(mut self, transfer_function: UnitConverter) -> Self {
+        self.transfer_function = transfer_function;
+        self
     }
 
-    fn adc_to_temperature(adc: u16) -> Temperature {
-        // There are very subtle differences when coding a temperature sensor on the ESP32 board as opposed to the Arduino.
-        // For the ESP32 board, we do not need to multiply the raw sensor reading by the voltage, it simply needs to be normalized by the 2^10 bits.
-        let voltage = f32::from(adc) / 1024.0;
-        let degrees = (voltage - 0.5) * 100.0;
-        celsius(degrees)
+    fn millivolts_to_temperature(&self, millivolts: u16) -> Temperature {
+        // the input is read using the ADC's calibration, which already accounts for its attenuation
+        let voltage = f32::from(millivolts) / 1000.0;
+        celsius(self.transfer_function.to_base_unit(voltage))
     }
+}
 
-    pub fn measure_temperature(&mut self) -> anyhow::Result<Temperature> {
+impl<'a> TemperatureSensor for TMP36<'a> {
+    fn measure_temperature(&mut self) -> anyhow::Result<Temperature> {
         let val = self.input.read()?;
-        Ok(Self::adc_to_temperature(val))
+        Ok(self.millivolts_to_temperature(val))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use assert_approx_eq::assert_approx_eq;
 
     #[test]
     fn tmp36_convert_to_temperature() {
-        let mut tmp36 = TMP36::connected_to(AdcInputPin::test(768));
-        assert_eq!(tmp36.measure_temperature().unwrap(), celsius(25.0));
+        let mut tmp36 = TMP36::connected_to(AdcInputPin::test(750));
+        let temperature = tmp36
+            .measure_temperature()
+            .unwrap()
+            .converted_to(UnitTemperature::celsius());
+        assert_approx_eq!(temperature.value, 25.0, 1e-3);
+    }
+
+    #[test]
+    fn tmp36_transfer_function_matches_datasheet() {
+        // output voltage (V) at temperature (°C), from the datasheet
+        let table = [
+            (-40.0, 0.1),
+            (0.0, 0.5),
+            (25.0, 0.75),
+            (50.0, 1.0),
+            (125.0, 1.75),
+        ];
+        for (temperature, voltage) in table {
+            assert_approx_eq!(
+                TMP36_TRANSFER_FUNCTION.to_base_unit(voltage),
+                temperature,
+                1e-3
+            );
+            assert_approx_eq!(TMP36_TRANSFER_FUNCTION.from_base_unit(temperature), voltage);
+        }
+    }
+
+    #[test]
+    fn tmp36_with_calibrated_transfer_function() {
+        // this sensor reads 1 °C too high around room temperature
+        let tmp36 = TMP36::connected_to(AdcInputPin::test(750)).with_transfer_function(
+            UnitConverter::piecewise_linear(&[(0.0, 0.5), (24.0, 0.75), (50.0, 1.0)]).unwrap(),
+        );
+        let temperature = tmp36
+            .millivolts_to_temperature(750)
+            .converted_to(UnitTemperature::celsius());
+        assert_approx_eq!(temperature.value, 24.0);
+    }
+
+    #[test]
+    fn tmp36_calibration_from_points() {
+        // this sensor reads 0.5 °C in ice water, and 26 °C at 25 °C
+        let calibration = Tmp36Calibration::from_points(
+            (celsius(0.5), celsius(0.0)),
+            (celsius(26.0), celsius(25.0)),
+        )
+        .unwrap();
+        for (measured, actual) in [(0.5, 0.0), (26.0, 25.0), (51.5, 50.0)] {
+            let corrected = calibration
+                .apply(celsius(measured))
+                .converted_to(UnitTemperature::celsius());
+            assert_approx_eq!(corrected.value, actual, 1e-3);
+        }
+    }
+
+    #[test]
+    fn tmp36_calibration_from_close_points() {
+        assert!(Tmp36Calibration::from_points(
+            (celsius(20.0), celsius(20.0)),
+            (celsius(20.0), celsius(21.0)),
+        )
+        .is_err());
+        assert!(Tmp36Calibration::from_points(
+            (celsius(20.0), celsius(20.0)),
+            (celsius(20.5), celsius(21.0)),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn tmp36_calibration_out_of_range() {
+        assert!(Tmp36Calibration::default().validated().is_ok());
+        for (offset_celsius, gain) in [
+            (0.0, 0.0),
+            (0.0, f32::NAN),
+            (0.0, 1.3),
+            (-100.0, 1.0),
+            (f32::INFINITY, 1.0),
+        ] {
+            let calibration = Tmp36Calibration {
+                offset_celsius,
+                gain,
+            };
+            assert!(calibration.validated().is_err(), "{:?}", calibration);
+        }
+        assert!(Tmp36Calibration {
+            offset_celsius: -5.0,
+            gain: 1.2
+        }
+        .validated()
+        .is_ok());
+    }
+
+    #[test]
+    fn tmp36_default_calibration_is_identity() {
+        let corrected = Tmp36Calibration::default()
+            .apply(celsius(21.5))
+            .converted_to(UnitTemperature::celsius());
+        assert_approx_eq!(corrected.value, 21.5, 1e-3);
     }
 }
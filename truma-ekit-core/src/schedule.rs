@@ -0,0 +1,431 @@
+use crate::{
+    control::{is_plausible_ambient, PLAUSIBLE_AMBIENT_CELSIUS},
+    types::Temperature,
+    util::celsius,
+};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The number of minutes in a week.
+const MINUTES_PER_WEEK: u16 = 7 * MINUTES_PER_DAY;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+/// The maximum number of blocks of a day, to stay within the maximum size of a stored value.
+pub const MAX_BLOCKS_PER_DAY: usize = 8;
+/// The range of the offset of a time zone from UTC, in minutes.
+pub const UTC_OFFSET_MINUTES_RANGE: (i32, i32) = (-12 * 60, 14 * 60);
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    pub const fn all() -> [Weekday; 7] {
+        [
+            Weekday::Monday,
+            Weekday::Tuesday,
+            Weekday::Wednesday,
+            Weekday::Thursday,
+            Weekday::Friday,
+            Weekday::Saturday,
+            Weekday::Sunday,
+        ]
+    }
+
+    /// Returns the number of days since Monday.
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    /// Returns the day `index` days since Monday, if it's a day of the week.
+    pub fn from_index(index: usize) -> Option<Weekday> {
+        Weekday::all().get(index).copied()
+    }
+}
+
+/// A point in time within a week, in minutes since Monday 00:00.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct WeekTime(u16);
+
+impl WeekTime {
+    pub fn new(weekday: Weekday, hour: u8, minute: u8) -> Self {
+        assert!(hour < 24 && minute < 60, "invalid time of day");
+        WeekTime(
+            weekday.index() as u16 * MINUTES_PER_DAY + u16::from(hour) * 60 + u16::from(minute),
+        )
+    }
+
+    /// Returns the local time of the week at `time`, in a time zone `utc_offset_minutes` ahead of UTC.
+    pub fn from_system_time(time: SystemTime, utc_offset_minutes: i32) -> Self {
+        let minutes = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_secs() as i64
+            / 60
+            + i64::from(utc_offset_minutes);
+        // the epoch was on a Thursday
+        let since_monday = minutes + 3 * i64::from(MINUTES_PER_DAY);
+        WeekTime(since_monday.rem_euclid(i64::from(MINUTES_PER_WEEK)) as u16)
+    }
+
+    pub fn weekday(&self) -> Weekday {
+        Weekday::all()[usize::from(self.0 / MINUTES_PER_DAY)]
+    }
+
+    /// Returns the number of minutes since midnight.
+    pub fn minute_of_day(&self) -> u16 {
+        self.0 % MINUTES_PER_DAY
+    }
+}
+
+/// A preset setpoint, selected by the blocks of a schedule.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Preset {
+    Comfort,
+    Eco,
+    Away,
+}
+
+/// The setpoints of the presets.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Presets {
+    pub comfort_celsius: f32,
+    pub eco_celsius: f32,
+    pub away_celsius: f32,
+}
+
+impl Default for Presets {
+    fn default() -> Self {
+        Presets {
+            comfort_celsius: 21.0,
+            eco_celsius: 17.0,
+            away_celsius: 12.0,
+        }
+    }
+}
+
+impl Presets {
+    /// Returns the presets if their setpoints are plausible ambient temperatures, or an error otherwise.
+    pub fn validated(self) -> anyhow::Result<Self> {
+        for setpoint_celsius in [self.comfort_celsius, self.eco_celsius, self.away_celsius] {
+            anyhow::ensure!(
+                is_plausible_ambient(celsius(setpoint_celsius)),
+                "preset setpoint {} °C is out of the range of {} °C to {} °C",
+                setpoint_celsius,
+                PLAUSIBLE_AMBIENT_CELSIUS.0,
+                PLAUSIBLE_AMBIENT_CELSIUS.1
+            );
+        }
+        Ok(self)
+    }
+
+    /// Returns the setpoint of the given preset.
+    pub fn setpoint(&self, preset: Preset) -> Temperature {
+        celsius(match preset {
+            Preset::Comfort => self.comfort_celsius,
+            Preset::Eco => self.eco_celsius,
+            Preset::Away => self.away_celsius,
+        })
+    }
+}
+
+/// A block of time starting at `start` (in minutes since midnight), lasting until the next block.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Block {
+    pub start: u16,
+    pub preset: Preset,
+}
+
+impl Block {
+    pub fn new(hour: u8, minute: u8, preset: Preset) -> Self {
+        assert!(hour < 24 && minute < 60, "invalid time of day");
+        Block {
+            start: u16::from(hour) * 60 + u16::from(minute),
+            preset,
+        }
+    }
+}
+
+/// The blocks of a day, in any order.
+pub type DaySchedule = Vec<Block>;
+
+/// Returns the blocks of a day, sorted by their start, or an error if there are too many or one doesn't start within
+/// the day.
+pub fn validated_day(mut blocks: DaySchedule) -> anyhow::Result<DaySchedule> {
+    anyhow::ensure!(
+        blocks.len() <= MAX_BLOCKS_PER_DAY,
+        "{} blocks exceed the maximum of {} per day",
+        blocks.len(),
+        MAX_BLOCKS_PER_DAY
+    );
+    if let Some(block) = blocks.iter().find(|block| block.start >= MINUTES_PER_DAY) {
+        anyhow::bail!("block start {} is not within the day", block.start);
+    }
+    blocks.sort_by_key(|block| block.start);
+    Ok(blocks)
+}
+
+/// Returns the offset of a time zone from UTC (in minutes), or an error if it's out of the range of
+/// [`UTC_OFFSET_MINUTES_RANGE`].
+pub fn validated_utc_offset(utc_offset_minutes: i32) -> anyhow::Result<i32> {
+    let (min, max) = UTC_OFFSET_MINUTES_RANGE;
+    anyhow::ensure!(
+        (min..=max).contains(&utc_offset_minutes),
+        "UTC offset {} min is out of the range of {} min to {} min",
+        utc_offset_minutes,
+        min,
+        max
+    );
+    Ok(utc_offset_minutes)
+}
+
+/// A weekly schedule of blocks.
+///
+/// A block lasts until the next block, which may be on a later day.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Schedule {
+    days: [DaySchedule; 7],
+}
+
+impl Schedule {
+    /// Returns the default schedule: comfort from the morning until the late evening, eco at night.
+    pub fn default_week() -> Self {
+        let weekday = vec![
+            Block::new(6, 30, Preset::Comfort),
+            Block::new(22, 30, Preset::Eco),
+        ];
+        let weekend = vec![
+            Block::new(8, 0, Preset::Comfort),
+            Block::new(23, 0, Preset::Eco),
+        ];
+        let mut schedule = Schedule::default();
+        for day in Weekday::all() {
+            let blocks = match day {
+                Weekday::Saturday | Weekday::Sunday => weekend.clone(),
+                _ => weekday.clone(),
+            };
+            schedule.set_day(day, blocks);
+        }
+        schedule
+    }
+
+    /// Returns the blocks of the given day, sorted by their start.
+    pub fn day(&self, day: Weekday) -> &DaySchedule {
+        &self.days[day.index()]
+    }
+
+    /// Replace the blocks of the given day.
+    pub fn set_day(&mut self, day: Weekday, mut blocks: DaySchedule) {
+        blocks.sort_by_key(|block| block.start);
+        self.days[day.index()] = blocks;
+    }
+
+    /// Returns the block active at `time` and when it started, if the schedule has any blocks.
+    pub fn active_block(&self, time: WeekTime) -> Option<(WeekTime, Block)> {
+        // search backwards from `time`, wrapping around to the end of the week
+        let day = time.weekday().index();
+        (0..=7)
+            .map(|days_back| (day + 7 - days_back % 7) % 7)
+            .enumerate()
+            .find_map(|(days_back, day)| {
+                self.days[day]
+                    .iter()
+                    .rev()
+                    .find(|block| days_back > 0 || block.start <= time.minute_of_day())
+                    .map(|block| (WeekTime(day as u16 * MINUTES_PER_DAY + block.start), *block))
+            })
+    }
+}
+
+/// The wall clock, set from a client (e.g. a phone), as neither the controller nor the thermostat has a real-time clock
+/// or access to a time server.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct WallClock {
+    /// When the clock was set, and the time (in s since the Unix epoch) it was set to.
+    set_at: Option<(Instant, u64)>,
+}
+
+impl WallClock {
+    pub fn new() -> Self {
+        WallClock::default()
+    }
+
+    /// Set the clock at `now` to `unix_secs` (in s since the Unix epoch).
+    pub fn set(&mut self, unix_secs: u64, now: Instant) {
+        self.set_at = Some((now, unix_secs));
+    }
+
+    /// Returns the time at `now` (in s since the Unix epoch), if the clock has been set.
+    pub fn unix_secs(&self, now: Instant) -> Option<u64> {
+        self.set_at
+            .map(|(set_at, unix_secs)| unix_secs + now.saturating_duration_since(set_at).as_secs())
+    }
+
+    /// Returns the local time of the week at `now`, in a time zone `utc_offset_minutes` ahead of UTC, if the clock has
+    /// been set.
+    pub fn week_time(&self, now: Instant, utc_offset_minutes: i32) -> Option<WeekTime> {
+        self.unix_secs(now).map(|unix_secs| {
+            WeekTime::from_system_time(
+                UNIX_EPOCH + Duration::from_secs(unix_secs),
+                utc_offset_minutes,
+            )
+        })
+    }
+
+    /// Returns the local day at `now` (in days since the Unix epoch), in a time zone `utc_offset_minutes` ahead of UTC,
+    /// if the clock has been set.
+    pub fn local_day(&self, now: Instant, utc_offset_minutes: i32) -> Option<i64> {
+        self.unix_secs(now).map(|unix_secs| {
+            (unix_secs as i64 / 60 + i64::from(utc_offset_minutes))
+                .div_euclid(i64::from(MINUTES_PER_DAY))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn week_time_from_system_time() {
+        // Thursday 1 January 1970, 00:00 UTC
+        let time = WeekTime::from_system_time(UNIX_EPOCH, 0);
+        assert_eq!(time, WeekTime::new(Weekday::Thursday, 0, 0));
+
+        // Monday 15 January 2024, 06:45 UTC, 07:45 in UTC+1
+        let time = UNIX_EPOCH + Duration::from_secs(1_705_301_100);
+        assert_eq!(
+            WeekTime::from_system_time(time, 60),
+            WeekTime::new(Weekday::Monday, 7, 45)
+        );
+        // 23:45 on Sunday in UTC-7
+        assert_eq!(
+            WeekTime::from_system_time(time, -7 * 60),
+            WeekTime::new(Weekday::Sunday, 23, 45)
+        );
+    }
+
+    #[test]
+    fn active_block() {
+        let schedule = Schedule::default_week();
+        let block = |weekday, hour, minute| {
+            schedule
+                .active_block(WeekTime::new(weekday, hour, minute))
+                .map(|(_, block)| block.preset)
+        };
+
+        assert_eq!(block(Weekday::Tuesday, 6, 29), Some(Preset::Eco));
+        assert_eq!(block(Weekday::Tuesday, 6, 30), Some(Preset::Comfort));
+        assert_eq!(block(Weekday::Tuesday, 22, 30), Some(Preset::Eco));
+        assert_eq!(block(Weekday::Saturday, 7, 0), Some(Preset::Eco));
+        assert_eq!(block(Weekday::Saturday, 8, 0), Some(Preset::Comfort));
+        // wraps around to Sunday's last block
+        assert_eq!(
+            schedule.active_block(WeekTime::new(Weekday::Monday, 0, 0)),
+            Some((
+                WeekTime::new(Weekday::Sunday, 23, 0),
+                Block::new(23, 0, Preset::Eco)
+            ))
+        );
+
+        assert_eq!(
+            Schedule::default().active_block(WeekTime::new(Weekday::Monday, 12, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn active_block_across_empty_days() {
+        let mut schedule = Schedule::default();
+        schedule.set_day(
+            Weekday::Friday,
+            vec![
+                Block::new(17, 0, Preset::Comfort),
+                Block::new(9, 0, Preset::Away),
+            ],
+        );
+
+        let active = |weekday, hour| {
+            schedule
+                .active_block(WeekTime::new(weekday, hour, 0))
+                .map(|(_, block)| block.preset)
+        };
+        assert_eq!(active(Weekday::Friday, 10), Some(Preset::Away));
+        assert_eq!(active(Weekday::Friday, 18), Some(Preset::Comfort));
+        assert_eq!(active(Weekday::Tuesday, 12), Some(Preset::Comfort));
+        assert_eq!(active(Weekday::Friday, 8), Some(Preset::Comfort));
+    }
+
+    #[test]
+    fn validates_days() {
+        assert_eq!(
+            validated_day(vec![
+                Block::new(22, 30, Preset::Eco),
+                Block::new(6, 30, Preset::Comfort),
+            ])
+            .unwrap(),
+            [
+                Block::new(6, 30, Preset::Comfort),
+                Block::new(22, 30, Preset::Eco),
+            ]
+        );
+        assert!(validated_day(vec![Block {
+            start: MINUTES_PER_DAY,
+            preset: Preset::Eco,
+        }])
+        .is_err());
+        assert!(validated_day(vec![
+            Block::new(12, 0, Preset::Away);
+            MAX_BLOCKS_PER_DAY + 1
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn validates_presets_and_utc_offset() {
+        assert!(Presets::default().validated().is_ok());
+        assert!(Presets {
+            comfort_celsius: f32::NAN,
+            ..Presets::default()
+        }
+        .validated()
+        .is_err());
+        assert!(Presets {
+            away_celsius: -50.0,
+            ..Presets::default()
+        }
+        .validated()
+        .is_err());
+
+        assert_eq!(validated_utc_offset(120).unwrap(), 120);
+        assert!(validated_utc_offset(-13 * 60).is_err());
+        assert!(validated_utc_offset(15 * 60).is_err());
+    }
+
+    #[test]
+    fn wall_clock() {
+        let start = Instant::now();
+        let mut clock = WallClock::new();
+        assert_eq!(clock.unix_secs(start), None);
+        assert_eq!(clock.week_time(start, 60), None);
+        assert_eq!(clock.local_day(start, 60), None);
+
+        // Monday 15 January 2024, 06:45 UTC
+        clock.set(1_705_301_100, start);
+        let now = start + Duration::from_secs(15 * 60);
+        assert_eq!(clock.unix_secs(now), Some(1_705_302_000));
+        assert_eq!(
+            clock.week_time(now, 60),
+            Some(WeekTime::new(Weekday::Monday, 8, 0))
+        );
+        assert_eq!(clock.local_day(now, 60), Some(19737));
+        // still Sunday in UTC-8
+        assert_eq!(clock.local_day(now, -8 * 60), Some(19736));
+    }
+}
@@ -0,0 +1,37 @@
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// The maximum size of a stored value.
+const MAX_VALUE_SIZE: usize = 512;
+
+/// Persistent key-value storage, backed by the default NVS partition.
+///
+/// Values are stored as JSON. Keys are limited to 15 characters.
+pub struct Storage {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl Storage {
+    /// Opens the given namespace of the default NVS partition.
+    pub fn new(partition: EspDefaultNvsPartition, namespace: &str) -> anyhow::Result<Self> {
+        let nvs = EspNvs::new(partition, namespace, true)?;
+        Ok(Storage { nvs })
+    }
+
+    /// Returns the value stored under `key`, or `None` if there is no such value.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> anyhow::Result<Option<T>> {
+        let mut buf = [0_u8; MAX_VALUE_SIZE];
+        match self.nvs.get_raw(key, &mut buf)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores `value` under `key`.
+    pub fn set<T: Serialize>(&mut self, key: &str, value: &T) -> anyhow::Result<()> {
+        let bytes = serde_json::to_vec(value)?;
+        anyhow::ensure!(bytes.len() <= MAX_VALUE_SIZE, "value for {} too large", key);
+        self.nvs.set_raw(key, &bytes)?;
+        Ok(())
+    }
+}
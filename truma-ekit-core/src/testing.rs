@@ -0,0 +1,339 @@
+//! Test doubles for running the e-kit logic on the host.
+//!
+//! Available to the tests of this crate, and to other crates with the `testing` feature enabled.
+//! Scripted ADC readings are provided by `AdcInputPin::test` and `AdcInputPin::replay`.
+
+use crate::{clock::Clock, peripherals::temperature_sensor::TemperatureSensor, types::Temperature};
+use anyhow::anyhow;
+use embedded_hal::digital::v2::{OutputPin, StatefulOutputPin};
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// An output pin recording the levels it was set to.
+///
+/// Clones share the same recording, so a clone can be inspected after the pin has been handed to a driver.
+#[derive(Clone, Debug)]
+pub struct RecordingPin {
+    levels: Arc<Mutex<Vec<bool>>>,
+}
+
+impl RecordingPin {
+    /// Returns a pin that is initially set to the given level.
+    pub fn new(high: bool) -> Self {
+        RecordingPin {
+            levels: Arc::new(Mutex::new(vec![high])),
+        }
+    }
+
+    /// Returns `true` if the pin is currently set high.
+    pub fn is_high(&self) -> bool {
+        *self.levels.lock().unwrap().last().unwrap()
+    }
+
+    /// Returns the levels the pin has been set to, starting with its initial level.
+    pub fn levels(&self) -> Vec<bool> {
+        self.levels.lock().unwrap().clone()
+    }
+
+    /// Returns the number of times the level of the pin has changed.
+    pub fn transitions(&self) -> usize {
+        self.levels
+            .lock()
+            .unwrap()
+            .windows(2)
+            .filter(|levels| levels[0] != levels[1])
+            .count()
+    }
+
+    fn set(&mut self, high: bool) {
+        self.levels.lock().unwrap().push(high);
+    }
+}
+
+impl OutputPin for RecordingPin {
+    type Error = std::convert::Infallible;
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set(true);
+        Ok(())
+    }
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set(false);
+        Ok(())
+    }
+}
+
+impl StatefulOutputPin for RecordingPin {
+    fn is_set_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.is_high())
+    }
+
+    fn is_set_low(&self) -> Result<bool, Self::Error> {
+        Ok(!self.is_high())
+    }
+}
+
+/// A temperature sensor replaying a script of readings, where `None` is a failed reading.
+///
+/// Clones share the same script, so readings can be added after the sensor has been handed to its user.
+#[derive(Clone, Default)]
+pub struct ScriptedSensor {
+    readings: Arc<Mutex<VecDeque<Option<Temperature>>>>,
+}
+
+impl ScriptedSensor {
+    pub fn new(readings: impl IntoIterator<Item = Option<Temperature>>) -> Self {
+        ScriptedSensor {
+            readings: Arc::new(Mutex::new(readings.into_iter().collect())),
+        }
+    }
+
+    /// Append a reading to the script.
+    pub fn push(&self, reading: Option<Temperature>) {
+        self.readings.lock().unwrap().push_back(reading);
+    }
+}
+
+impl TemperatureSensor for ScriptedSensor {
+    fn measure_temperature(&mut self) -> anyhow::Result<Temperature> {
+        match self.readings.lock().unwrap().pop_front() {
+            Some(Some(temperature)) => Ok(temperature),
+            Some(None) => Err(anyhow!("scripted sensor failure")),
+            None => Err(anyhow!("no more scripted readings")),
+        }
+    }
+}
+
+/// A clock that only advances when told to.
+///
+/// Clones share the same time.
+#[derive(Clone, Debug)]
+pub struct FakeClock {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl Default for FakeClock {
+    fn default() -> Self {
+        FakeClock::new()
+    }
+}
+
+impl FakeClock {
+    pub fn new() -> Self {
+        FakeClock {
+            start: Instant::now(),
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Returns the current time.
+    pub fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    /// Returns the time elapsed since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    /// Advance the clock by the given duration.
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    /// Advance the clock until the given time has elapsed since it was created.
+    ///
+    /// The clock never goes back.
+    pub fn advance_to(&self, elapsed: Duration) {
+        let mut current = self.elapsed.lock().unwrap();
+        *current = (*current).max(elapsed);
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        FakeClock::now(self)
+    }
+}
+
+/// A scripted sequence of actions and expectations on a subject (e.g. an e-kit or a thermostat), each at a point in time.
+///
+/// ```ignore
+/// Scenario::new()
+///     .at(secs(30), |ekit, _| ekit.set_output_temperature(Some(celsius(95.0))))
+///     .expect(secs(30), |ekit| ekit.run_mode(), EKitSystemRunMode::Cooldown)
+///     .run(&mut ekit, &FakeClock::new());
+/// ```
+pub struct Scenario<T> {
+    steps: Vec<Step<T>>,
+}
+
+struct Step<T> {
+    at: Duration,
+    kind: StepKind<T>,
+}
+
+type Action<T> = Box<dyn FnOnce(&mut T, Instant)>;
+type Expectation<T> = Box<dyn FnOnce(&T) -> Result<(), String>>;
+
+enum StepKind<T> {
+    Act(Action<T>),
+    Expect(Expectation<T>),
+}
+
+impl<T> Default for Scenario<T> {
+    fn default() -> Self {
+        Scenario::new()
+    }
+}
+
+impl<T> Scenario<T> {
+    pub fn new() -> Self {
+        Scenario { steps: Vec::new() }
+    }
+
+    /// Act on the subject once `at` has elapsed, passing the current time.
+    pub fn at(mut self, at: Duration, action: impl FnOnce(&mut T, Instant) + 'static) -> Self {
+        self.steps.push(Step {
+            at,
+            kind: StepKind::Act(Box::new(action)),
+        });
+        self
+    }
+
+    /// Act on the subject every `period`, from `from` up to and including `to`.
+    pub fn every(
+        mut self,
+        period: Duration,
+        from: Duration,
+        to: Duration,
+        action: impl Fn(&mut T, Instant) + Clone + 'static,
+    ) -> Self {
+        assert!(!period.is_zero(), "the period must not be zero");
+        let mut at = from;
+        while at <= to {
+            self = self.at(at, action.clone());
+            at += period;
+        }
+        self
+    }
+
+    /// Expect the value observed on the subject once `at` has elapsed to equal `expected`.
+    pub fn expect<V>(
+        mut self,
+        at: Duration,
+        observe: impl FnOnce(&T) -> V + 'static,
+        expected: V,
+    ) -> Self
+    where
+        V: PartialEq + Debug + 'static,
+    {
+        self.steps.push(Step {
+            at,
+            kind: StepKind::Expect(Box::new(move |subject| {
+                let actual = observe(subject);
+                if actual == expected {
+                    Ok(())
+                } else {
+                    Err(format!("expected {:?}, got {:?}", expected, actual))
+                }
+            })),
+        });
+        self
+    }
+
+    /// Run the scenario, advancing the clock to each step in turn.
+    ///
+    /// Steps at the same time run in the order they were added.
+    /// Panics on the first expectation that isn't met.
+    pub fn run(mut self, subject: &mut T, clock: &FakeClock) {
+        // stable, so steps at the same time keep their order
+        self.steps.sort_by_key(|step| step.at);
+        for step in self.steps {
+            clock.advance_to(step.at);
+            match step.kind {
+                StepKind::Act(action) => action(subject, clock.now()),
+                StepKind::Expect(expectation) => {
+                    if let Err(message) = expectation(subject) {
+                        panic!("at t={:?}: {}", step.at, message);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns a duration of the given number of seconds, for readable scenarios.
+pub const fn secs(secs: u64) -> Duration {
+    Duration::from_secs(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::celsius;
+
+    #[test]
+    fn recording_pin() {
+        let pin = RecordingPin::new(false);
+        let mut driver = pin.clone();
+        driver.set_high().unwrap();
+        driver.set_high().unwrap();
+        driver.set_low().unwrap();
+
+        assert_eq!(pin.levels(), vec![false, true, true, false]);
+        assert_eq!(pin.transitions(), 2);
+        assert!(!pin.is_high());
+    }
+
+    #[test]
+    fn scripted_sensor() {
+        let mut sensor = ScriptedSensor::new([Some(celsius(20.0)), None]);
+        assert_eq!(sensor.measure_temperature().unwrap(), celsius(20.0));
+        assert!(sensor.measure_temperature().is_err());
+        assert!(sensor.measure_temperature().is_err());
+
+        sensor.push(Some(celsius(21.0)));
+        assert_eq!(sensor.measure_temperature().unwrap(), celsius(21.0));
+    }
+
+    #[test]
+    fn fake_clock() {
+        let clock = FakeClock::new();
+        let start = clock.now();
+        clock.advance(secs(5));
+        clock.advance_to(secs(3));
+        assert_eq!(clock.now() - start, secs(5));
+        clock.advance_to(secs(30));
+        assert_eq!(clock.clone().elapsed(), secs(30));
+    }
+
+    #[test]
+    fn scenario_runs_steps_in_time_order() {
+        let clock = FakeClock::new();
+        let mut log = Vec::new();
+        Scenario::new()
+            .at(secs(20), |log: &mut Vec<u64>, _| log.push(20))
+            .every(secs(10), secs(0), secs(20), |log, _| log.push(0))
+            .expect(secs(20), |log| log.len(), 4)
+            .run(&mut log, &clock);
+
+        assert_eq!(log, vec![0, 0, 20, 0]);
+        assert_eq!(clock.elapsed(), secs(20));
+    }
+
+    #[test]
+    #[should_panic(expected = "at t=10s: expected 2, got 1")]
+    fn scenario_fails_on_unmet_expectation() {
+        Scenario::new()
+            .at(secs(0), |count: &mut u32, _| *count += 1)
+            .expect(secs(10), |count| *count, 2)
+            .run(&mut 0, &FakeClock::new());
+    }
+}
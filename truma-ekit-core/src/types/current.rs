@@ -0,0 +1,74 @@
+use crate::measurement::{Additive, Dimension, Measurement, Unit, UnitConverter};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct UnitCurrent {
+    symbol: &'static str,
+    /// The coefficient converting from the base unit.
+    coeff: f32,
+}
+
+pub type Current = Measurement<UnitCurrent>;
+
+const SCALED_UNITS: [UnitCurrent; 2] = [UnitCurrent::milliamperes(), UnitCurrent::amperes()];
+
+impl UnitCurrent {
+    const fn new_with_coeff(symbol: &'static str, coeff: f32) -> Self {
+        UnitCurrent { symbol, coeff }
+    }
+
+    pub const fn amperes() -> Self {
+        UnitCurrent::new_with_coeff("A", 1.0)
+    }
+
+    pub const fn milliamperes() -> Self {
+        UnitCurrent::new_with_coeff("mA", 1000.0)
+    }
+}
+
+impl Unit for UnitCurrent {
+    fn symbol(&self) -> &str {
+        self.symbol
+    }
+}
+
+impl Dimension for UnitCurrent {
+    fn base_unit() -> Self {
+        Self::amperes()
+    }
+
+    fn converter(&self) -> UnitConverter {
+        UnitConverter::Linear {
+            coeff: self.coeff,
+            constant: 0.0,
+        }
+    }
+
+    fn scaled_units() -> &'static [Self] {
+        &SCALED_UNITS
+    }
+}
+
+impl Additive for UnitCurrent {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn amperes_to_milliamperes() {
+        assert_approx_eq!(
+            Measurement::new(6.5, UnitCurrent::amperes())
+                .converted_to(UnitCurrent::milliamperes())
+                .value,
+            6500.0
+        );
+
+        assert_approx_eq!(
+            Measurement::new(250.0, UnitCurrent::milliamperes())
+                .converted_to(UnitCurrent::amperes())
+                .value,
+            0.25
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use crate::measurement::{Additive, Dimension, Measurement, Unit, UnitConverter};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct UnitDuration {
+    symbol: &'static str,
+    /// The coefficient converting from the base unit.
+    coeff: f32,
+}
+
+pub type Duration = Measurement<UnitDuration>;
+
+const SCALED_UNITS: [UnitDuration; 3] = [
+    UnitDuration::seconds(),
+    UnitDuration::minutes(),
+    UnitDuration::hours(),
+];
+
+impl UnitDuration {
+    const fn new_with_coeff(symbol: &'static str, coeff: f32) -> Self {
+        UnitDuration { symbol, coeff }
+    }
+
+    pub const fn seconds() -> Self {
+        UnitDuration::new_with_coeff("s", 1.0)
+    }
+
+    pub const fn minutes() -> Self {
+        UnitDuration::new_with_coeff("min", 1.0 / 60.0)
+    }
+
+    pub const fn hours() -> Self {
+        UnitDuration::new_with_coeff("h", 1.0 / 3600.0)
+    }
+}
+
+impl Unit for UnitDuration {
+    fn symbol(&self) -> &str {
+        self.symbol
+    }
+}
+
+impl Dimension for UnitDuration {
+    fn base_unit() -> Self {
+        Self::seconds()
+    }
+
+    fn converter(&self) -> UnitConverter {
+        UnitConverter::Linear {
+            coeff: self.coeff,
+            constant: 0.0,
+        }
+    }
+
+    fn scaled_units() -> &'static [Self] {
+        &SCALED_UNITS
+    }
+}
+
+impl Additive for UnitDuration {}
+
+impl From<std::time::Duration> for Duration {
+    fn from(duration: std::time::Duration) -> Self {
+        Measurement::new(duration.as_secs_f32(), UnitDuration::seconds())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn seconds_to_other() {
+        assert_approx_eq!(
+            Measurement::new(90.0, UnitDuration::seconds())
+                .converted_to(UnitDuration::minutes())
+                .value,
+            1.5
+        );
+
+        assert_approx_eq!(
+            Measurement::new(1.5, UnitDuration::hours())
+                .converted_to(UnitDuration::seconds())
+                .value,
+            5400.0,
+            1e-3
+        );
+    }
+
+    #[test]
+    fn from_std_duration() {
+        assert_eq!(
+            Duration::from(std::time::Duration::from_millis(2500)),
+            Measurement::new(2.5, UnitDuration::seconds())
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{
+    measurement::{Additive, Dimension, Measurement, Unit, UnitConverter},
+    types::{Duration, Power},
+};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct UnitEnergy {
+    symbol: &'static str,
+    /// The coefficient converting from the base unit.
+    coeff: f32,
+}
+
+pub type Energy = Measurement<UnitEnergy>;
+
+const SCALED_UNITS: [UnitEnergy; 2] = [UnitEnergy::watt_hours(), UnitEnergy::kilowatt_hours()];
+
+impl UnitEnergy {
+    const fn new_with_coeff(symbol: &'static str, coeff: f32) -> Self {
+        UnitEnergy { symbol, coeff }
+    }
+
+    pub const fn joules() -> Self {
+        UnitEnergy::new_with_coeff("J", 1.0)
+    }
+
+    pub const fn watt_hours() -> Self {
+        UnitEnergy::new_with_coeff("Wh", 1.0 / 3600.0)
+    }
+
+    pub const fn kilowatt_hours() -> Self {
+        UnitEnergy::new_with_coeff("kWh", 1.0 / 3_600_000.0)
+    }
+}
+
+impl Unit for UnitEnergy {
+    fn symbol(&self) -> &str {
+        self.symbol
+    }
+}
+
+impl Dimension for UnitEnergy {
+    fn base_unit() -> Self {
+        Self::joules()
+    }
+
+    fn converter(&self) -> UnitConverter {
+        UnitConverter::Linear {
+            coeff: self.coeff,
+            constant: 0.0,
+        }
+    }
+
+    fn scaled_units() -> &'static [Self] {
+        &SCALED_UNITS
+    }
+}
+
+impl Additive for UnitEnergy {}
+
+impl Power {
+    /// Returns the energy used when drawing this power for `duration`.
+    pub fn over(&self, duration: std::time::Duration) -> Energy {
+        *self * Duration::from(duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::UnitPower;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn joules_to_other() {
+        assert_approx_eq!(
+            Measurement::new(7200.0, UnitEnergy::joules())
+                .converted_to(UnitEnergy::watt_hours())
+                .value,
+            2.0
+        );
+
+        assert_approx_eq!(
+            Measurement::new(1800.0, UnitEnergy::watt_hours())
+                .converted_to(UnitEnergy::kilowatt_hours())
+                .value,
+            1.8
+        );
+    }
+
+    #[test]
+    fn power_over_duration() {
+        assert_approx_eq!(
+            Measurement::new(900.0, UnitPower::watts())
+                .over(std::time::Duration::from_secs(30 * 60))
+                .converted_to(UnitEnergy::watt_hours())
+                .value,
+            450.0
+        );
+
+        assert_approx_eq!(
+            Measurement::new(1.8, UnitPower::kilowatts())
+                .over(std::time::Duration::from_secs(2 * 60 * 60))
+                .converted_to(UnitEnergy::kilowatt_hours())
+                .value,
+            3.6
+        );
+    }
+}
@@ -1,4 +1,17 @@
+mod current;
+mod duration;
+mod energy;
+mod ops;
 mod percent;
+mod power;
+mod pressure;
 mod temperature;
+mod voltage;
+pub use current::*;
+pub use duration::*;
+pub use energy::*;
 pub use percent::*;
+pub use power::*;
+pub use pressure::*;
 pub use temperature::*;
+pub use voltage::*;
@@ -0,0 +1,110 @@
+//! Operations across dimensions, e.g. power × duration = energy.
+//!
+//! Only physically meaningful combinations are implemented, so mixing up dimensions fails to compile:
+//!
+//! ```compile_fail
+//! use truma_ekit_core::util::{amperes, watts};
+//!
+//! let _ = watts(900.0) * amperes(4.0);
+//! ```
+
+use crate::{
+    measurement::{Dimension, Measurement},
+    types::{UnitCurrent, UnitDuration, UnitEnergy, UnitPower, UnitVoltage},
+};
+
+/// Implements `lhs × rhs = out` (and `rhs × lhs = out`), `out / lhs = rhs` and `out / rhs = lhs`.
+///
+/// All of these operate on the base units of the dimensions, which must be coherent (e.g. W × s = J).
+macro_rules! impl_product {
+    ($lhs:ty, $rhs:ty => $out:ty) => {
+        impl_product!(@mul $lhs, $rhs => $out);
+        impl_product!(@mul $rhs, $lhs => $out);
+        impl_product!(@div $out, $lhs => $rhs);
+        impl_product!(@div $out, $rhs => $lhs);
+    };
+    (@mul $lhs:ty, $rhs:ty => $out:ty) => {
+        impl std::ops::Mul<Measurement<$rhs>> for Measurement<$lhs> {
+            type Output = Measurement<$out>;
+
+            fn mul(self, rhs: Measurement<$rhs>) -> Self::Output {
+                Measurement::new(
+                    self.converted_to(<$lhs>::base_unit()).value
+                        * rhs.converted_to(<$rhs>::base_unit()).value,
+                    <$out>::base_unit(),
+                )
+            }
+        }
+    };
+    (@div $lhs:ty, $rhs:ty => $out:ty) => {
+        impl std::ops::Div<Measurement<$rhs>> for Measurement<$lhs> {
+            type Output = Measurement<$out>;
+
+            fn div(self, rhs: Measurement<$rhs>) -> Self::Output {
+                Measurement::new(
+                    self.converted_to(<$lhs>::base_unit()).value
+                        / rhs.converted_to(<$rhs>::base_unit()).value,
+                    <$out>::base_unit(),
+                )
+            }
+        }
+    };
+}
+
+impl_product!(UnitPower, UnitDuration => UnitEnergy);
+impl_product!(UnitVoltage, UnitCurrent => UnitPower);
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        measurement::Measurement,
+        types::{UnitCurrent, UnitDuration, UnitEnergy, UnitPower, UnitVoltage},
+        util::{amperes, volts, watt_hours, watts},
+    };
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn power_times_duration() {
+        let energy = watts(900.0) * Measurement::new(30.0, UnitDuration::minutes());
+        assert_approx_eq!(
+            energy.converted_to(UnitEnergy::watt_hours()).value,
+            450.0,
+            1e-3
+        );
+
+        let energy = Measurement::new(2.0, UnitDuration::hours()) * watts(25.0);
+        assert_approx_eq!(
+            energy.converted_to(UnitEnergy::watt_hours()).value,
+            50.0,
+            1e-3
+        );
+    }
+
+    #[test]
+    fn energy_divided_by_power_or_duration() {
+        let duration = watt_hours(450.0) / watts(900.0);
+        assert_approx_eq!(duration.converted_to(UnitDuration::hours()).value, 0.5);
+
+        let power = watt_hours(450.0) / Measurement::new(30.0, UnitDuration::minutes());
+        assert_approx_eq!(power.converted_to(UnitPower::watts()).value, 900.0, 1e-3);
+    }
+
+    #[test]
+    fn voltage_times_current() {
+        let power = volts(230.0) * amperes(6.0);
+        assert_approx_eq!(power.converted_to(UnitPower::kilowatts()).value, 1.38);
+
+        let power = Measurement::new(500.0, UnitCurrent::milliamperes())
+            * Measurement::new(3300.0, UnitVoltage::millivolts());
+        assert_approx_eq!(power.converted_to(UnitPower::watts()).value, 1.65, 1e-5);
+    }
+
+    #[test]
+    fn power_divided_by_voltage_or_current() {
+        let current = watts(1825.0) / volts(230.0);
+        assert_approx_eq!(current.converted_to(UnitCurrent::amperes()).value, 7.934783);
+
+        let voltage = watts(1380.0) / amperes(6.0);
+        assert_approx_eq!(voltage.converted_to(UnitVoltage::volts()).value, 230.0);
+    }
+}
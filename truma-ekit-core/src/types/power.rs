@@ -0,0 +1,75 @@
+use crate::measurement::{Additive, Dimension, Measurement, Unit, UnitConverter};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct UnitPower {
+    symbol: &'static str,
+    /// The coefficient converting from the base unit.
+    coeff: f32,
+}
+
+pub type Power = Measurement<UnitPower>;
+
+const SCALED_UNITS: [UnitPower; 2] = [UnitPower::watts(), UnitPower::kilowatts()];
+
+impl UnitPower {
+    const fn new_with_coeff(symbol: &'static str, coeff: f32) -> Self {
+        UnitPower { symbol, coeff }
+    }
+
+    pub const fn watts() -> Self {
+        UnitPower::new_with_coeff("W", 1.0)
+    }
+
+    pub const fn kilowatts() -> Self {
+        UnitPower::new_with_coeff("kW", 0.001)
+    }
+}
+
+impl Unit for UnitPower {
+    fn symbol(&self) -> &str {
+        self.symbol
+    }
+}
+
+impl Dimension for UnitPower {
+    fn base_unit() -> Self {
+        Self::watts()
+    }
+
+    fn converter(&self) -> UnitConverter {
+        UnitConverter::Linear {
+            coeff: self.coeff,
+            constant: 0.0,
+        }
+    }
+
+    fn scaled_units() -> &'static [Self] {
+        &SCALED_UNITS
+    }
+}
+
+impl Additive for UnitPower {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn watts_to_kilowatts() {
+        assert_approx_eq!(
+            Measurement::new(1800.0, UnitPower::watts())
+                .converted_to(UnitPower::kilowatts())
+                .value,
+            1.8
+        );
+
+        assert_approx_eq!(
+            Measurement::new(0.9, UnitPower::kilowatts())
+                .converted_to(UnitPower::watts())
+                .value,
+            900.0,
+            1e-3
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use crate::measurement::{Additive, Dimension, Measurement, Unit, UnitConverter};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct UnitPressure {
+    symbol: &'static str,
+    /// The coefficient converting from the base unit.
+    coeff: f32,
+}
+
+pub type Pressure = Measurement<UnitPressure>;
+
+impl UnitPressure {
+    const fn new_with_coeff(symbol: &'static str, coeff: f32) -> Self {
+        UnitPressure { symbol, coeff }
+    }
+
+    pub const fn pascals() -> Self {
+        UnitPressure::new_with_coeff("Pa", 1.0)
+    }
+
+    pub const fn hectopascals() -> Self {
+        UnitPressure::new_with_coeff("hPa", 0.01)
+    }
+}
+
+impl Unit for UnitPressure {
+    fn symbol(&self) -> &str {
+        self.symbol
+    }
+}
+
+impl Dimension for UnitPressure {
+    fn base_unit() -> Self {
+        Self::pascals()
+    }
+
+    fn converter(&self) -> UnitConverter {
+        UnitConverter::Linear {
+            coeff: self.coeff,
+            constant: 0.0,
+        }
+    }
+}
+
+impl Additive for UnitPressure {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn pascals_to_hectopascals() {
+        assert_approx_eq!(
+            Measurement::new(101325.0, UnitPressure::pascals())
+                .converted_to(UnitPressure::hectopascals())
+                .value,
+            1013.25,
+            1e-3
+        );
+    }
+}
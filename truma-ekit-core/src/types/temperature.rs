@@ -1,18 +1,48 @@
-use crate::measurement::{Dimension, Measurement, Unit, UnitConverter};
+use crate::measurement::{Additive, Dimension, Measurement, Unit, UnitConverter};
+use serde::{Deserialize, Serialize};
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub struct UnitTemperature {
     symbol: &'static str,
-    converter: UnitConverter,
+    /// The coefficient and constant converting from the base unit.
+    coeff: f32,
+    constant: f32,
+    delta: UnitTemperatureDelta,
 }
 
+/// The unit of a difference between two temperatures.
+///
+/// Unlike absolute temperatures, temperature differences are converted without an offset (a difference of 1 °C is a
+/// difference of 1 K).
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct UnitTemperatureDelta {
+    symbol: &'static str,
+    /// The coefficient converting from the base unit.
+    coeff: f32,
+}
+
+/// An absolute temperature.
+///
+/// Subtracting two temperatures results in a [`TemperatureDelta`], which can be added to or subtracted from a
+/// temperature. Adding two absolute temperatures is meaningless, and fails to compile:
+///
+/// ```compile_fail
+/// use truma_ekit_core::util::celsius;
+///
+/// let _ = celsius(20.0) + celsius(1.5);
+/// ```
 pub type Temperature = Measurement<UnitTemperature>;
 
+/// The difference between two temperatures.
+pub type TemperatureDelta = Measurement<UnitTemperatureDelta>;
+
 impl UnitTemperature {
     const fn new_with_coeff_constant(symbol: &'static str, coeff: f32, constant: f32) -> Self {
         UnitTemperature {
             symbol,
-            converter: UnitConverter::Linear { coeff, constant },
+            coeff,
+            constant,
+            delta: UnitTemperatureDelta::new_with_coeff(symbol, coeff),
         }
     }
 
@@ -27,6 +57,29 @@ impl UnitTemperature {
     pub const fn fahrenheit() -> Self {
         UnitTemperature::new_with_coeff_constant("°F", 1.8, -459.67)
     }
+
+    /// Returns the unit of a difference between two temperatures in this unit.
+    pub const fn delta(&self) -> UnitTemperatureDelta {
+        self.delta
+    }
+}
+
+impl UnitTemperatureDelta {
+    const fn new_with_coeff(symbol: &'static str, coeff: f32) -> Self {
+        UnitTemperatureDelta { symbol, coeff }
+    }
+
+    pub const fn kelvin() -> Self {
+        UnitTemperature::kelvin().delta()
+    }
+
+    pub const fn celsius() -> Self {
+        UnitTemperature::celsius().delta()
+    }
+
+    pub const fn fahrenheit() -> Self {
+        UnitTemperature::fahrenheit().delta()
+    }
 }
 
 impl Unit for UnitTemperature {
@@ -41,7 +94,93 @@ impl Dimension for UnitTemperature {
     }
 
     fn converter(&self) -> UnitConverter {
-        self.converter
+        UnitConverter::Linear {
+            coeff: self.coeff,
+            constant: self.constant,
+        }
+    }
+}
+
+impl Unit for UnitTemperatureDelta {
+    fn symbol(&self) -> &str {
+        self.symbol
+    }
+}
+
+impl Dimension for UnitTemperatureDelta {
+    fn base_unit() -> Self {
+        Self::kelvin()
+    }
+
+    fn converter(&self) -> UnitConverter {
+        UnitConverter::Linear {
+            coeff: self.coeff,
+            constant: 0.0,
+        }
+    }
+}
+
+impl Additive for UnitTemperatureDelta {}
+
+impl std::ops::Sub for Temperature {
+    type Output = TemperatureDelta;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        let rhs = rhs.converted_to(self.unit);
+        Measurement::new(self.value - rhs.value, self.unit.delta())
+    }
+}
+
+impl std::ops::Add<TemperatureDelta> for Temperature {
+    type Output = Self;
+
+    fn add(self, rhs: TemperatureDelta) -> Self::Output {
+        let rhs = rhs.converted_to(self.unit.delta());
+        Measurement::new(self.value + rhs.value, self.unit)
+    }
+}
+
+impl std::ops::Sub<TemperatureDelta> for Temperature {
+    type Output = Self;
+
+    fn sub(self, rhs: TemperatureDelta) -> Self::Output {
+        let rhs = rhs.converted_to(self.unit.delta());
+        Measurement::new(self.value - rhs.value, self.unit)
+    }
+}
+
+/// The temperature scale preferred by the user.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum TemperatureScale {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureScale {
+    /// Returns the unit temperatures are displayed in.
+    pub const fn unit(&self) -> UnitTemperature {
+        match self {
+            TemperatureScale::Celsius => UnitTemperature::celsius(),
+            TemperatureScale::Fahrenheit => UnitTemperature::fahrenheit(),
+        }
+    }
+
+    /// Returns the step size used to adjust the requested temperature.
+    pub const fn step_size(&self) -> TemperatureDelta {
+        match self {
+            TemperatureScale::Celsius => Measurement::new(0.5, UnitTemperatureDelta::celsius()),
+            TemperatureScale::Fahrenheit => {
+                Measurement::new(1.0, UnitTemperatureDelta::fahrenheit())
+            }
+        }
+    }
+
+    /// Converts the temperature to this scale, rounded to the step size.
+    pub fn rounded(&self, temperature: Temperature) -> Temperature {
+        let value = temperature.converted_to(self.unit()).value;
+        let step = self.step_size().value;
+        Measurement::new((value / step).round() * step, self.unit())
     }
 }
 
@@ -97,4 +236,69 @@ mod tests {
             372.3167
         );
     }
+
+    #[test]
+    fn delta_conversion_ignores_offset() {
+        assert_approx_eq!(
+            Measurement::new(1.5, UnitTemperatureDelta::celsius())
+                .converted_to(UnitTemperatureDelta::kelvin())
+                .value,
+            1.5
+        );
+
+        assert_approx_eq!(
+            Measurement::new(1.0, UnitTemperatureDelta::celsius())
+                .converted_to(UnitTemperatureDelta::fahrenheit())
+                .value,
+            1.8
+        );
+    }
+
+    #[test]
+    fn absolute_minus_absolute() {
+        let delta = Measurement::new(21.0, UnitTemperature::celsius())
+            - Measurement::new(19.5, UnitTemperature::celsius());
+        assert_eq!(delta.unit, UnitTemperatureDelta::celsius());
+        assert_approx_eq!(delta.value, 1.5);
+
+        // 70 °F = 21.11 °C
+        let delta = Measurement::new(70.0, UnitTemperature::fahrenheit())
+            - Measurement::new(20.0, UnitTemperature::celsius());
+        assert_eq!(delta.unit, UnitTemperatureDelta::fahrenheit());
+        assert_approx_eq!(
+            delta.converted_to(UnitTemperatureDelta::celsius()).value,
+            1.111111,
+            1e-4
+        );
+    }
+
+    #[test]
+    fn absolute_plus_delta() {
+        let temperature = Measurement::new(20.5, UnitTemperature::celsius())
+            + Measurement::new(0.5, UnitTemperatureDelta::celsius());
+        assert_eq!(temperature.unit, UnitTemperature::celsius());
+        assert_approx_eq!(temperature.value, 21.0);
+
+        let temperature = Measurement::new(68.0, UnitTemperature::fahrenheit())
+            + Measurement::new(1.0, UnitTemperatureDelta::celsius());
+        assert_eq!(temperature.unit, UnitTemperature::fahrenheit());
+        assert_approx_eq!(temperature.value, 69.8, 1e-4);
+
+        let temperature = Measurement::new(20.0, UnitTemperature::celsius())
+            - Measurement::new(1.8, UnitTemperatureDelta::fahrenheit());
+        assert_approx_eq!(temperature.value, 19.0);
+    }
+
+    #[test]
+    fn temperature_scale_rounding() {
+        let celsius = TemperatureScale::Celsius;
+        let temperature = celsius.rounded(Measurement::new(69.0, UnitTemperature::fahrenheit()));
+        assert_eq!(temperature.unit, UnitTemperature::celsius());
+        assert_approx_eq!(temperature.value, 20.5);
+
+        let fahrenheit = TemperatureScale::Fahrenheit;
+        let temperature = fahrenheit.rounded(Measurement::new(20.5, UnitTemperature::celsius()));
+        assert_eq!(temperature.unit, UnitTemperature::fahrenheit());
+        assert_approx_eq!(temperature.value, 69.0);
+    }
 }
@@ -0,0 +1,75 @@
+use crate::measurement::{Additive, Dimension, Measurement, Unit, UnitConverter};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct UnitVoltage {
+    symbol: &'static str,
+    /// The coefficient converting from the base unit.
+    coeff: f32,
+}
+
+pub type Voltage = Measurement<UnitVoltage>;
+
+const SCALED_UNITS: [UnitVoltage; 2] = [UnitVoltage::millivolts(), UnitVoltage::volts()];
+
+impl UnitVoltage {
+    const fn new_with_coeff(symbol: &'static str, coeff: f32) -> Self {
+        UnitVoltage { symbol, coeff }
+    }
+
+    pub const fn volts() -> Self {
+        UnitVoltage::new_with_coeff("V", 1.0)
+    }
+
+    pub const fn millivolts() -> Self {
+        UnitVoltage::new_with_coeff("mV", 1000.0)
+    }
+}
+
+impl Unit for UnitVoltage {
+    fn symbol(&self) -> &str {
+        self.symbol
+    }
+}
+
+impl Dimension for UnitVoltage {
+    fn base_unit() -> Self {
+        Self::volts()
+    }
+
+    fn converter(&self) -> UnitConverter {
+        UnitConverter::Linear {
+            coeff: self.coeff,
+            constant: 0.0,
+        }
+    }
+
+    fn scaled_units() -> &'static [Self] {
+        &SCALED_UNITS
+    }
+}
+
+impl Additive for UnitVoltage {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn volts_to_millivolts() {
+        assert_approx_eq!(
+            Measurement::new(3.3, UnitVoltage::volts())
+                .converted_to(UnitVoltage::millivolts())
+                .value,
+            3300.0,
+            1e-3
+        );
+
+        assert_approx_eq!(
+            Measurement::new(750.0, UnitVoltage::millivolts())
+                .converted_to(UnitVoltage::volts())
+                .value,
+            0.75
+        );
+    }
+}
@@ -1,12 +1,39 @@
 use crate::{
     measurement::{Formatter, Measurement},
-    types::{Temperature, UnitTemperature},
+    types::{
+        Current, Energy, Power, Pressure, Temperature, TemperatureDelta, UnitCurrent, UnitEnergy,
+        UnitPower, UnitPressure, UnitTemperature, UnitTemperatureDelta, UnitVoltage, Voltage,
+    },
 };
 
 pub const fn celsius(temperature: f32) -> Temperature {
     Measurement::new(temperature, UnitTemperature::celsius())
 }
 
+pub const fn celsius_delta(delta: f32) -> TemperatureDelta {
+    Measurement::new(delta, UnitTemperatureDelta::celsius())
+}
+
+pub const fn amperes(current: f32) -> Current {
+    Measurement::new(current, UnitCurrent::amperes())
+}
+
+pub const fn volts(voltage: f32) -> Voltage {
+    Measurement::new(voltage, UnitVoltage::volts())
+}
+
+pub const fn watts(power: f32) -> Power {
+    Measurement::new(power, UnitPower::watts())
+}
+
+pub const fn hectopascals(pressure: f32) -> Pressure {
+    Measurement::new(pressure, UnitPressure::hectopascals())
+}
+
+pub const fn watt_hours(energy: f32) -> Energy {
+    Measurement::new(energy, UnitEnergy::watt_hours())
+}
+
 pub fn format_temperature(temperature: &Temperature) -> String {
     Formatter::with_precision(2).format(temperature)
 }
@@ -0,0 +1,182 @@
+use std::time::{Duration, Instant};
+
+/// A change of the push-button's state is only accepted once it's been stable for this long, to ignore contact bounce.
+const DEBOUNCE: Duration = Duration::from_millis(30);
+/// The time the push-button has to be held down for a long press.
+const LONG_PRESS: Duration = Duration::from_secs(1);
+/// A second press within this time after releasing the push-button makes a double press.
+const DOUBLE_PRESS_WINDOW: Duration = Duration::from_millis(300);
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ButtonPress {
+    Short,
+    Long,
+    Double,
+}
+
+/// Detects presses of a push-button, based on its (bouncing) state.
+///
+/// A short press is reported once no second press followed within the double press window, a double press when the
+/// second press is released, and a long press once while the push-button is held down.
+#[derive(Debug)]
+pub struct Button {
+    debounce: Duration,
+    pressed: bool,
+    changing_since: Option<Instant>,
+    pressed_since: Option<Instant>,
+    long_press_reported: bool,
+    second_press: bool,
+    released_at: Option<Instant>,
+}
+
+impl Default for Button {
+    fn default() -> Self {
+        Button::new()
+    }
+}
+
+impl Button {
+    pub fn new() -> Self {
+        Button {
+            debounce: DEBOUNCE,
+            pressed: false,
+            changing_since: None,
+            pressed_since: None,
+            long_press_reported: false,
+            second_press: false,
+            released_at: None,
+        }
+    }
+
+    /// Signals the state of the push-button at `now`.
+    ///
+    /// Returns the press detected, if any.
+    pub fn update(&mut self, pressed: bool, now: Instant) -> Option<ButtonPress> {
+        if pressed == self.pressed {
+            self.changing_since = None;
+        } else {
+            let changing_since = *self.changing_since.get_or_insert(now);
+            if now.saturating_duration_since(changing_since) >= self.debounce {
+                self.changing_since = None;
+                self.pressed = pressed;
+                return if pressed {
+                    self.on_press(now)
+                } else {
+                    self.on_release(now)
+                };
+            }
+        }
+
+        match self.pressed_since {
+            Some(since) => {
+                if !self.long_press_reported && now.saturating_duration_since(since) >= LONG_PRESS {
+                    self.long_press_reported = true;
+                    self.second_press = false;
+                    return Some(ButtonPress::Long);
+                }
+                None
+            }
+            None => {
+                // no second press followed
+                let released_at = self.released_at?;
+                if now.saturating_duration_since(released_at) >= DOUBLE_PRESS_WINDOW {
+                    self.released_at = None;
+                    return Some(ButtonPress::Short);
+                }
+                None
+            }
+        }
+    }
+
+    fn on_press(&mut self, now: Instant) -> Option<ButtonPress> {
+        self.pressed_since = Some(now);
+        self.long_press_reported = false;
+        self.second_press = self.released_at.take().is_some();
+        None
+    }
+
+    fn on_release(&mut self, now: Instant) -> Option<ButtonPress> {
+        self.pressed_since = None;
+        if self.long_press_reported {
+            None
+        } else if self.second_press {
+            self.second_press = false;
+            Some(ButtonPress::Double)
+        } else {
+            self.released_at = Some(now);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds the push-button states for the given durations (in ms, sampled every 10 ms), returning the presses detected
+    /// and when.
+    fn presses(states: &[(bool, u64)]) -> Vec<(u64, ButtonPress)> {
+        let start = Instant::now();
+        let mut sub = Button::new();
+        let mut presses = Vec::new();
+        let mut ms = 0;
+        for &(pressed, duration_ms) in states {
+            for _ in 0..duration_ms / 10 {
+                if let Some(press) = sub.update(pressed, start + Duration::from_millis(ms)) {
+                    presses.push((ms, press));
+                }
+                ms += 10;
+            }
+        }
+        presses
+    }
+
+    #[test]
+    fn detects_short_press() {
+        assert_eq!(
+            presses(&[(true, 200), (false, 500)]),
+            // released at 230 ms, reported after the double press window
+            [(530, ButtonPress::Short)]
+        );
+    }
+
+    #[test]
+    fn ignores_bounce() {
+        assert_eq!(
+            presses(&[
+                (true, 10),
+                (false, 10),
+                (true, 10),
+                (false, 10),
+                (true, 200),
+                (false, 10),
+                (true, 10),
+                (false, 500),
+            ]),
+            [(590, ButtonPress::Short)]
+        );
+        // too short to be a press
+        assert_eq!(presses(&[(true, 20), (false, 500)]), []);
+    }
+
+    #[test]
+    fn detects_long_press() {
+        assert_eq!(
+            presses(&[(true, 1500), (false, 500)]),
+            [(1030, ButtonPress::Long)]
+        );
+    }
+
+    #[test]
+    fn detects_double_press() {
+        assert_eq!(
+            presses(&[(true, 100), (false, 200), (true, 100), (false, 500)]),
+            [(430, ButtonPress::Double)]
+        );
+        // the second press came too late
+        assert_eq!(
+            presses(&[(true, 100), (false, 400), (true, 100), (false, 500)]),
+            [(430, ButtonPress::Short), (930, ButtonPress::Short)]
+        );
+    }
+}
@@ -1,8 +1,38 @@
-use truma_ekit_core::types::Temperature;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+use truma_ekit_core::{
+    control::{self, is_plausible_ambient},
+    types::{Temperature, TemperatureDelta},
+    util::celsius_delta,
+};
+
+/// The time span of the valid readings that are kept.
+const HISTORY_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// The maximum number of valid readings that are kept, older readings are dropped first.
+const HISTORY_CAPACITY: usize = 600;
+/// At most one reading per interval is kept, so the history spans its whole window although the main loop reads the
+/// temperature far more often.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// The temperature is considered to be rising or falling if it changes faster than this rate (per minute).
+const TREND_THRESHOLD: TemperatureDelta = celsius_delta(0.05);
+
+/// The direction the temperature is changing in.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Trend {
+    Rising,
+    Steady,
+    Falling,
+}
 
 pub struct CachedTemperature {
     most_recent_temperature: Option<Temperature>,
     last_known_temperature: Option<Temperature>,
+    /// The time of the last valid reading.
+    last_read_at: Option<Instant>,
+    /// The valid readings within the history window, oldest first.
+    history: VecDeque<(Instant, Temperature)>,
 }
 
 impl CachedTemperature {
@@ -10,6 +40,8 @@ impl CachedTemperature {
         CachedTemperature {
             most_recent_temperature: temperature,
             last_known_temperature: temperature,
+            last_read_at: None,
+            history: VecDeque::new(),
         }
     }
 
@@ -23,21 +55,95 @@ impl CachedTemperature {
         self.most_recent_temperature.or(self.last_known_temperature)
     }
 
-    /// Update the actual temperature.
-    pub fn update(&mut self, temperature: Option<Temperature>) {
+    /// Update the actual temperature, as read at `now`.
+    ///
+    /// Implausible readings (e.g. of a disconnected sensor) are treated as failed readings, so they age into a sensor
+    /// fault rather than being heated on.
+    pub fn update(&mut self, temperature: Option<Temperature>, now: Instant) {
+        let temperature = temperature.filter(|temperature| {
+            let plausible = is_plausible_ambient(*temperature);
+            if !plausible {
+                log::warn!("ignoring implausible temperature reading {:?}", temperature);
+            }
+            plausible
+        });
         if let Some(temperature) = temperature {
             self.most_recent_temperature = Some(temperature);
             self.last_known_temperature = Some(temperature);
+            self.last_read_at = Some(now);
+
+            let sample_due = self.history.back().is_none_or(|(read_at, _)| {
+                now.saturating_duration_since(*read_at) >= SAMPLE_INTERVAL
+            });
+            if sample_due {
+                if self.history.len() == HISTORY_CAPACITY {
+                    self.history.pop_front();
+                }
+                self.history.push_back((now, temperature));
+            }
         } else {
             self.most_recent_temperature = None;
         }
+
+        while let Some((read_at, _)) = self.history.front() {
+            if now.saturating_duration_since(*read_at) <= HISTORY_WINDOW {
+                break;
+            }
+            self.history.pop_front();
+        }
+    }
+
+    /// Returns the time since the last valid reading, if any.
+    pub fn time_since_last_reading(&self, now: Instant) -> Option<Duration> {
+        self.last_read_at
+            .map(|read_at| now.saturating_duration_since(read_at))
+    }
+
+    /// Returns `true` if there hasn't been a valid reading for longer than `max_age`.
+    pub fn is_stale(&self, max_age: Duration, now: Instant) -> bool {
+        self.time_since_last_reading(now)
+            .is_none_or(|age| age > max_age)
+    }
+
+    /// Returns the rate of change of the temperature per minute, fitted through the readings in the history window.
+    ///
+    /// Returns `None` if the readings don't span enough time.
+    pub fn rate_of_change(&self) -> Option<TemperatureDelta> {
+        control::rate_of_change(self.history.iter().copied())
+    }
+
+    /// Returns the direction the temperature is changing in, if known.
+    pub fn trend(&self) -> Option<Trend> {
+        let rate = self.rate_of_change()?;
+        Some(if rate > TREND_THRESHOLD {
+            Trend::Rising
+        } else if rate < -TREND_THRESHOLD {
+            Trend::Falling
+        } else {
+            Trend::Steady
+        })
+    }
+
+    /// Returns the lowest and highest temperature read within `window` before `now`.
+    pub fn min_max(&self, window: Duration, now: Instant) -> Option<(Temperature, Temperature)> {
+        self.history
+            .iter()
+            .filter(|(read_at, _)| now.saturating_duration_since(*read_at) <= window)
+            .map(|(_, temperature)| (*temperature, *temperature))
+            .reduce(|(min, max), (temperature, _)| {
+                (
+                    if temperature < min { temperature } else { min },
+                    if temperature > max { temperature } else { max },
+                )
+            })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use truma_ekit_core::util::celsius;
+    use assert_approx_eq::assert_approx_eq;
+    use truma_ekit_core::{types::UnitTemperature, util::celsius};
 
     #[test]
     fn initial_values() {
@@ -52,36 +158,172 @@ mod tests {
 
     #[test]
     fn updated_values() {
+        let now = Instant::now();
         let mut sub = CachedTemperature::new(None);
         assert_eq!(sub.most_recent_temperature(), None);
         assert_eq!(sub.last_known_temperature(), None);
 
-        sub.update(None);
+        sub.update(None, now);
         assert_eq!(sub.most_recent_temperature(), None);
         assert_eq!(sub.last_known_temperature(), None);
 
-        sub.update(Some(celsius(13.5)));
+        sub.update(Some(celsius(13.5)), now);
         assert_eq!(sub.most_recent_temperature(), Some(celsius(13.5)));
         assert_eq!(sub.last_known_temperature(), Some(celsius(13.5)));
 
-        sub.update(None);
+        sub.update(None, now);
         assert_eq!(sub.most_recent_temperature(), None);
         assert_eq!(sub.last_known_temperature(), Some(celsius(13.5)));
 
-        sub.update(Some(celsius(15.0)));
+        sub.update(Some(celsius(15.0)), now);
         assert_eq!(sub.most_recent_temperature(), Some(celsius(15.0)));
         assert_eq!(sub.last_known_temperature(), Some(celsius(15.0)));
 
-        sub.update(Some(celsius(0.0)));
+        sub.update(Some(celsius(0.0)), now);
         assert_eq!(sub.most_recent_temperature(), Some(celsius(0.0)));
         assert_eq!(sub.last_known_temperature(), Some(celsius(0.0)));
 
-        sub.update(None);
+        sub.update(None, now);
         assert_eq!(sub.most_recent_temperature(), None);
         assert_eq!(sub.last_known_temperature(), Some(celsius(0.0)));
 
-        sub.update(None);
+        sub.update(None, now);
         assert_eq!(sub.most_recent_temperature(), None);
         assert_eq!(sub.last_known_temperature(), Some(celsius(0.0)));
     }
+
+    #[test]
+    fn ignores_implausible_readings() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let mut sub = CachedTemperature::new(None);
+        sub.update(Some(celsius(20.0)), at(0));
+        // a disconnected TMP36 reads -50 °C
+        sub.update(Some(celsius(-50.0)), at(10));
+        sub.update(Some(celsius(f32::NAN)), at(20));
+        assert_eq!(sub.most_recent_temperature(), None);
+        assert_eq!(sub.last_known_temperature(), Some(celsius(20.0)));
+        assert_eq!(
+            sub.time_since_last_reading(at(20)),
+            Some(Duration::from_secs(20))
+        );
+        assert_eq!(
+            sub.min_max(HISTORY_WINDOW, at(20)),
+            Some((celsius(20.0), celsius(20.0)))
+        );
+    }
+
+    #[test]
+    fn staleness() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let max_age = Duration::from_secs(60);
+
+        let mut sub = CachedTemperature::new(None);
+        assert!(sub.is_stale(max_age, at(0)));
+
+        sub.update(Some(celsius(20.0)), at(0));
+        sub.update(None, at(30));
+        assert_eq!(
+            sub.time_since_last_reading(at(30)),
+            Some(Duration::from_secs(30))
+        );
+        assert!(!sub.is_stale(max_age, at(60)));
+        assert!(sub.is_stale(max_age, at(61)));
+
+        // readings older than the history window are dropped, but their age is still known
+        sub.update(None, at(3600));
+        assert!(sub.is_stale(max_age, at(3600)));
+        assert_eq!(
+            sub.time_since_last_reading(at(3600)),
+            Some(Duration::from_secs(3600))
+        );
+        assert_eq!(sub.min_max(HISTORY_WINDOW, at(3600)), None);
+        assert_eq!(sub.last_known_temperature(), Some(celsius(20.0)));
+
+        sub.update(Some(celsius(20.5)), at(3601));
+        assert!(!sub.is_stale(max_age, at(3601)));
+    }
+
+    #[test]
+    fn rate_of_change_and_trend() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let mut sub = CachedTemperature::new(None);
+        sub.update(Some(celsius(18.0)), at(0));
+        sub.update(Some(celsius(18.5)), at(30));
+        // not enough readings yet
+        assert_eq!(sub.rate_of_change(), None);
+        assert_eq!(sub.trend(), None);
+
+        // rising by 1 °C per minute
+        sub.update(Some(celsius(19.0)), at(60));
+        sub.update(Some(celsius(19.5)), at(90));
+        let rate = sub.rate_of_change().unwrap();
+        assert_approx_eq!(
+            rate.converted_to(UnitTemperature::celsius().delta()).value,
+            1.0,
+            1e-3
+        );
+        assert_eq!(sub.trend(), Some(Trend::Rising));
+
+        // falling by 1 °C per minute, once the rising readings have left the window
+        for (i, secs) in (700..=760).step_by(30).enumerate() {
+            sub.update(Some(celsius(19.0 - 0.5 * i as f32)), at(secs));
+        }
+        assert_eq!(sub.trend(), Some(Trend::Falling));
+
+        let mut steady = CachedTemperature::new(None);
+        for secs in (0..=120).step_by(30) {
+            steady.update(Some(celsius(21.0)), at(secs));
+        }
+        assert_eq!(steady.trend(), Some(Trend::Steady));
+    }
+
+    #[test]
+    fn rate_of_change_at_loop_cadence() {
+        let start = Instant::now();
+        let mut sub = CachedTemperature::new(None);
+
+        // the main loop reads the temperature every few ms, rising by 1 °C per minute
+        for ms in (0..=120_000).step_by(5) {
+            let temperature = celsius(18.0 + ms as f32 / 60_000.0);
+            sub.update(Some(temperature), start + Duration::from_millis(ms));
+            if ms == 30_000 {
+                assert_eq!(sub.rate_of_change(), None);
+            }
+        }
+
+        let rate = sub.rate_of_change().unwrap();
+        assert_approx_eq!(
+            rate.converted_to(UnitTemperature::celsius().delta()).value,
+            1.0,
+            1e-2
+        );
+        assert_eq!(sub.trend(), Some(Trend::Rising));
+    }
+
+    #[test]
+    fn min_max() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let mut sub = CachedTemperature::new(None);
+        assert_eq!(sub.min_max(Duration::from_secs(60), at(0)), None);
+
+        sub.update(Some(celsius(15.0)), at(0));
+        sub.update(Some(celsius(21.0)), at(60));
+        sub.update(None, at(90));
+        sub.update(Some(celsius(19.0)), at(120));
+        assert_eq!(
+            sub.min_max(Duration::from_secs(300), at(120)),
+            Some((celsius(15.0), celsius(21.0)))
+        );
+        assert_eq!(
+            sub.min_max(Duration::from_secs(60), at(120)),
+            Some((celsius(19.0), celsius(21.0)))
+        );
+    }
 }
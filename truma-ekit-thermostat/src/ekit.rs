@@ -2,7 +2,7 @@ use crate::wifi::{WifiClient, WifiClientError};
 use embedded_svc::{
     http::{
         client::{Client as HttpClient, Connection, Response},
-        Status,
+        Method, Status,
     },
     io::{Read, Write},
 };
@@ -11,7 +11,15 @@ use esp_idf_svc::{
     http::client::{Configuration, EspHttpConnection},
 };
 use esp_idf_sys::EspError;
-use truma_ekit_core::ekit::{EKit as EKitCore, EKitUserRunMode, PostEKitRunMode};
+use std::time::Duration;
+use truma_ekit_core::{
+    ekit::{
+        EKit as EKitCore, EKitSchedule, EKitStatus, EKitUserRunMode, PostEKitAmbient,
+        PostEKitBoost, PostEKitConfig, PostEKitRunMode,
+    },
+    peripherals::temperature_sensor::SensorReading,
+    types::{TemperatureScale, UnitPressure, UnitTemperature},
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -19,6 +27,8 @@ pub enum Error {
     Esp(#[from] EspError),
     #[error("ESP IO error: {0}")]
     Io(#[from] EspIOError),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
     #[error("unexpected status {0}")]
     UnexpectedStatus(u16),
     #[error(transparent)]
@@ -49,6 +59,87 @@ impl<'a> EKitHttp<'a> {
         self.wifi.is_connected()
     }
 
+    /// Returns the status reported by the e-kit.
+    pub fn status(&mut self) -> Result<EKitStatus, Error> {
+        let body = self.get("/status")?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Returns the heating schedule configured on the e-kit.
+    pub fn schedule(&mut self) -> Result<EKitSchedule, Error> {
+        let body = self.get("/schedule")?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Report the ambient conditions to the e-kit, as read `age` ago.
+    pub fn post_ambient(&mut self, reading: &SensorReading, age: Duration) -> Result<(), Error> {
+        let ambient = PostEKitAmbient {
+            temperature_celsius: reading
+                .temperature
+                .converted_to(UnitTemperature::celsius())
+                .value,
+            humidity_percent: reading.humidity.map(|humidity| humidity.to_f32()),
+            pressure_hpa: reading
+                .pressure
+                .map(|pressure| pressure.converted_to(UnitPressure::hectopascals()).value),
+            age_secs: Some(age.as_secs()),
+        };
+        let payload = serde_urlencoded::to_string(ambient).unwrap();
+        self.post("/ambient", payload.as_bytes())
+    }
+
+    /// Request the run mode the thermostat decided on, as it does periodically.
+    ///
+    /// Unlike a run mode requested by the user, it doesn't end the controller running on a setpoint.
+    pub fn request_periodic_run_mode(&mut self, run_mode: EKitUserRunMode) {
+        self.post_run_mode(run_mode, true);
+    }
+
+    fn post_run_mode(&mut self, run_mode: EKitUserRunMode, periodic: bool) {
+        log::info!("requesting e-kit run mode {:?}...", run_mode);
+
+        let payload = serde_urlencoded::to_string(PostEKitRunMode { run_mode, periodic }).unwrap();
+        match self.post("/run-mode", payload.as_bytes()) {
+            Ok(_) => log::info!("e-kit run mode requested"),
+            Err(e) => log::error!("failed to request e-kit run mode ({})", e),
+        }
+    }
+
+    /// Configure the temperature scale preferred by the user on the e-kit.
+    pub fn post_config(&mut self, temperature_scale: TemperatureScale) -> Result<(), Error> {
+        let payload = serde_urlencoded::to_string(PostEKitConfig { temperature_scale }).unwrap();
+        self.post("/config", payload.as_bytes())
+    }
+
+    fn get(&mut self, path: &str) -> Result<Vec<u8>, Error> {
+        log::info!("GET {}", path);
+
+        self.wifi.connect()?;
+
+        let headers = [("accept", "application/json"), ("connection", "close")];
+
+        let url = format!("{}{}", self.hostname, path);
+        let req = self.client.request(Method::Get, &url, &headers)?;
+
+        let mut res = req.submit()?;
+        let status = res.status();
+        if status != 200 {
+            EKitHttp::drain_response(res);
+            return Err(Error::UnexpectedStatus(status));
+        }
+
+        let (_headers, body) = res.split();
+        let mut payload = Vec::new();
+        let mut buf = [0_u8; 256];
+        loop {
+            match body.read(&mut buf)? {
+                0 => break,
+                len => payload.extend_from_slice(&buf[..len]),
+            }
+        }
+        Ok(payload)
+    }
+
     fn post(&mut self, path: &str, payload: &[u8]) -> Result<(), Error> {
         log::info!("POST {} {:?}", path, payload);
 
@@ -97,12 +188,19 @@ impl<'a> EKitHttp<'a> {
 
 impl<'a> EKitCore for EKitHttp<'a> {
     fn request_user_run_mode(&mut self, run_mode: EKitUserRunMode) {
-        log::info!("requesting e-kit run mode {:?}...", run_mode);
+        self.post_run_mode(run_mode, false);
+    }
 
-        let payload = serde_urlencoded::to_string(PostEKitRunMode { run_mode }).unwrap();
-        match self.post("/run-mode", payload.as_bytes()) {
-            Ok(_) => log::info!("e-kit run mode requested"),
-            Err(e) => log::error!("failed to request e-kit run mode ({})", e),
+    fn request_boost(&mut self, duration: Duration) {
+        log::info!("requesting e-kit boost for {:?}...", duration);
+
+        let payload = serde_urlencoded::to_string(PostEKitBoost {
+            duration_secs: duration.as_secs(),
+        })
+        .unwrap();
+        match self.post("/boost", payload.as_bytes()) {
+            Ok(_) => log::info!("e-kit boost requested"),
+            Err(e) => log::error!("failed to request e-kit boost ({})", e),
         }
     }
 }
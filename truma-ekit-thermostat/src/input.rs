@@ -1,31 +1,51 @@
-use esp_idf_hal::gpio::{AnyInputPin, AnyOutputPin, PinDriver};
+use crate::button::{Button, ButtonPress};
+use esp_idf_hal::gpio::{AnyInputPin, AnyOutputPin, PinDriver, Pull};
 use rotary_encoder_hal::{Direction, Rotary};
-use std::ops::Neg;
-use truma_ekit_core::{
-    adc::AdcInputPin, peripherals::tmp36::TMP36, powersaving::Powered, types::Temperature,
-};
+use std::time::Instant;
+use truma_ekit_core::peripherals::temperature_sensor::{SensorReading, TemperatureSensor};
 
+/// The sensors the ambient temperature can be measured with.
+// only the configured sensor is ever constructed
+#[allow(dead_code)]
+pub enum AmbientSensor {
+    /// A TMP36 connected to the ADC.
+    Tmp36,
+    /// A BME280 sharing the I²C bus with the display, also measuring humidity and pressure.
+    Bme280,
+}
+
+/// Returns the number of steps the requested temperature should be adjusted by, if any.
 pub fn temperature_adjustment<'a>(
     pin_a: AnyInputPin,
     pin_b: AnyInputPin,
-    step_size: Temperature,
-) -> impl FnMut() -> Option<Temperature> + 'a {
+) -> impl FnMut() -> Option<i8> + 'a {
     let mut encoder = Rotary::new(
         PinDriver::input(pin_a).unwrap(),
         PinDriver::input(pin_b).unwrap(),
     );
     move || match encoder.update() {
-        Ok(Direction::Clockwise) => Some(step_size),
-        Ok(Direction::CounterClockwise) => Some(step_size.neg()),
+        Ok(Direction::Clockwise) => Some(1),
+        Ok(Direction::CounterClockwise) => Some(-1),
         _ => None,
     }
 }
 
-pub fn ambient_temperature<'a>(
-    pin: AdcInputPin<'a>,
-    vcc: AnyOutputPin,
-) -> impl FnMut() -> Option<Temperature> + 'a {
-    let tmp36 = TMP36::connected_to(pin);
-    let mut tmp36 = Powered::new(tmp36, PinDriver::output(vcc).unwrap());
-    move || tmp36.power_up().measure_temperature().ok()
+/// Returns each press of the (active low) push-button once, debounced (see [`Button`]).
+pub fn button_press<'a>(pin: AnyInputPin) -> impl FnMut() -> Option<ButtonPress> + 'a {
+    let mut pin = PinDriver::input(pin).unwrap();
+    pin.set_pull(Pull::Up).unwrap();
+    let mut button = Button::new();
+    move || button.update(pin.is_low(), Instant::now())
+}
+
+pub fn ambient<'a>(
+    mut sensor: Box<dyn TemperatureSensor + 'a>,
+) -> impl FnMut() -> Option<SensorReading> + 'a {
+    move || match sensor.measure() {
+        Ok(reading) => Some(reading),
+        Err(e) => {
+            log::error!("failed to measure ambient temperature ({})", e);
+            None
+        }
+    }
 }
@@ -1,29 +1,60 @@
+mod button;
 mod caching;
 mod ekit;
 mod input;
+mod menu;
 mod output;
 mod peripherals;
+mod schedule;
 mod thermostat;
 mod wifi;
 
-use esp_idf_hal::adc::{AdcConfig, AdcDriver, Atten0dB};
+use button::ButtonPress;
+use esp_idf_hal::{
+    adc::{AdcConfig, AdcDriver, Atten6dB},
+    gpio::PinDriver,
+    i2c::{I2cConfig, I2cDriver},
+};
 use esp_idf_svc::{eventloop::EspSystemEventLoop, nvs::EspDefaultNvsPartition};
 use esp_idf_sys as _;
+use input::AmbientSensor;
+use menu::{Menu, MenuAction, Mode};
 use output::Output;
 use peripherals::SystemPeripherals;
-use std::time::Duration;
+use schedule::{Presets, Schedule, Scheduler};
+use std::time::{Duration, Instant};
 use thermostat::Thermostat;
 use truma_ekit_core::{
-    adc::AdcInputPin, ekit::EKit, throttle::Throttle, types::Temperature, util::celsius,
+    adc::AdcInputPin,
+    ekit::EKit,
+    filter::{ExponentialMovingAverage, Filtered, Median},
+    i2c::SharedI2c,
+    peripherals::{
+        bme280::BME280,
+        temperature_sensor::{SensorReading, TemperatureSensor},
+        tmp36::{Tmp36Calibration, TMP36},
+    },
+    powersaving::Powered,
+    schedule::WallClock,
+    storage::Storage,
+    throttle::Throttle,
+    types::{Temperature, TemperatureScale},
+    util::celsius,
 };
 use wifi::WifiClient;
 
 /// The hostname of the e-kit controller.
 const EKIT_HOSTNAME: &str = "http://192.168.71.1";
+/// The sensor used to measure the ambient temperature.
+const AMBIENT_SENSOR: AmbientSensor = AmbientSensor::Tmp36;
 /// The default requested temperature.
 const DEFAULT_REQUESTED_TEMPERATURE: Temperature = celsius(20.5);
-/// The step size to use when rotating the encoder.
-const INPUT_STEP_SIZE: Temperature = celsius(0.5);
+/// The duration of a boost, requested with a long press of the rotary encoder or using the menu.
+const BOOST_DURATION: Duration = Duration::from_secs(20 * 60);
+/// The storage key of the temperature scale preferred by the user (as configured on the controller).
+const TEMPERATURE_SCALE_KEY: &str = "temp.scale";
+/// The storage key of the calibration of the ambient temperature sensor.
+const TMP36_CALIBRATION_KEY: &str = "tmp36.cal";
 
 esp_idf_sys::esp_app_desc!();
 
@@ -35,69 +66,287 @@ fn main() -> anyhow::Result<()> {
     let sysloop = EspSystemEventLoop::take()?;
     let nvs_default_partition = EspDefaultNvsPartition::take()?;
 
+    let mut storage = Storage::new(nvs_default_partition.clone(), "thermostat")?;
+
     let mut wifi = WifiClient::new(peripherals.modem, sysloop, nvs_default_partition)?;
     wifi.start()?;
 
     let mut ekit = ekit::EKitHttp::new(EKIT_HOSTNAME, wifi);
     let mut thermostat = Thermostat::new(DEFAULT_REQUESTED_TEMPERATURE);
-
-    let mut read_requested_temperature_adjustment = input::temperature_adjustment(
-        peripherals.rot.pin_a,
-        peripherals.rot.pin_b,
-        INPUT_STEP_SIZE,
-    );
-    let mut read_actual_temperature = input::ambient_temperature(
-        AdcInputPin::pin::<_, _, Atten0dB<_>>(
-            peripherals.temperature.voltage,
-            AdcDriver::new(
-                peripherals.temperature.adc,
-                &AdcConfig::default().calibration(true),
-            )?,
-        ),
-        peripherals.temperature.vcc,
+    match storage.get::<TemperatureScale>(TEMPERATURE_SCALE_KEY) {
+        Ok(Some(temperature_scale)) => thermostat.set_temperature_scale(temperature_scale),
+        Ok(None) => {}
+        Err(e) => log::error!("failed to restore temperature scale ({})", e),
+    }
+    let mut menu = Menu::new(
+        match storage
+            .get::<Tmp36Calibration>(TMP36_CALIBRATION_KEY)
+            .and_then(|calibration| calibration.map(Tmp36Calibration::validated).transpose())
+        {
+            Ok(calibration) => calibration.unwrap_or_default(),
+            Err(e) => {
+                log::error!("failed to restore TMP36 calibration ({})", e);
+                Tmp36Calibration::default()
+            }
+        },
     );
+    menu.set_temperature_scale(thermostat.temperature_scale());
+
+    // the schedule and the time are configured on the controller (the soft-AP has no access to a time server), the
+    // schedule is only followed once both are known
+    let mut scheduler = Scheduler::new(Schedule::default(), Presets::default());
+    let mut clock = WallClock::new();
+    let mut utc_offset_minutes = 0;
 
-    let mut display = output::display(
+    let mut read_requested_temperature_adjustment =
+        input::temperature_adjustment(peripherals.rot.pin_a, peripherals.rot.pin_b);
+    let mut read_button_press = input::button_press(peripherals.rot.switch);
+
+    // the display and the BME280 share the I²C bus
+    let i2c = SharedI2c::new(I2cDriver::new(
         peripherals.i2c.i2c,
         peripherals.i2c.sda,
         peripherals.i2c.scl,
-    );
+        &I2cConfig::default(),
+    )?);
+
+    let ambient_sensor: Box<dyn TemperatureSensor> = match AMBIENT_SENSOR {
+        AmbientSensor::Tmp36 => {
+            // 6 dB attenuation covers the TMP36 output up to ~80 °C
+            let tmp36 = TMP36::connected_to(AdcInputPin::pin::<_, _, Atten6dB<_>>(
+                peripherals.temperature.voltage,
+                AdcDriver::new(
+                    peripherals.temperature.adc,
+                    &AdcConfig::default().calibration(true),
+                )?,
+            ));
+            // the TMP36 is noisy, and only the slowly changing ambient temperature is of interest
+            let tmp36 = Filtered::new(tmp36)
+                .oversampled(16)
+                .with_filter(Median::new(5))
+                .with_filter(ExponentialMovingAverage::new(0.1));
+            Box::new(Powered::new(
+                tmp36,
+                PinDriver::output(peripherals.temperature.vcc)?,
+            ))
+        }
+        AmbientSensor::Bme280 => Box::new(BME280::connected_to(i2c.clone())?),
+    };
+    let mut read_ambient = input::ambient(ambient_sensor);
+
+    let mut display = output::display(i2c);
 
     let mut actual_temperature = caching::CachedTemperature::new(None);
 
     let mut display_throttler = Throttle::max_runs_per_sec(10);
     let mut request_throttler = Throttle::one_run_per(Duration::from_secs(2));
+    let mut status_throttler = Throttle::one_run_per(Duration::from_secs(10));
+    let mut schedule_throttler = Throttle::one_run_per(Duration::from_secs(60));
+    let mut ambient_throttler = Throttle::one_run_per(Duration::from_secs(30));
+
+    let mut ekit_status = None;
+    // the end of the boost, counted down locally between status updates
+    let mut boost_until: Option<Instant> = None;
+    let mut ambient = None;
+    // set once a mode has been selected, until it has been requested (ending the controller running on a setpoint)
+    let mut mode_selected = false;
 
     loop {
-        // adjust the requested temperature using the rotary encoder
-        if let Some(adjustment) = read_requested_temperature_adjustment() {
-            let requested_temperature = thermostat.requested_temperature() + adjustment;
-            thermostat.set_requested_temperature(requested_temperature);
+        // follow boosts started or ended elsewhere (e.g. using the controller's API)
+        let boosting = boost_until.is_some_and(|until| until > Instant::now());
+        match menu.mode() {
+            Mode::Boost if !boosting => menu.set_mode(Mode::Auto),
+            Mode::Auto if boosting => menu.set_mode(Mode::Boost),
+            _ => {}
+        }
+
+        let selected_mode = match read_button_press() {
+            // start or cancel a boost with a long press, unless the menu is open
+            Some(ButtonPress::Long) if menu.screen().is_none() => {
+                Some(if boosting { Mode::Auto } else { Mode::Boost })
+            }
+            // open and navigate the menu using the encoder's push-button
+            Some(press) => match menu.press(press) {
+                Some(MenuAction::SetMode(mode)) => Some(mode),
+                Some(MenuAction::SetTemperatureScale(temperature_scale)) => {
+                    log::info!("temperature scale {:?} selected", temperature_scale);
+                    // configured on the controller, which the thermostat follows
+                    if let Err(e) = ekit.post_config(temperature_scale) {
+                        log::error!("failed to configure temperature scale ({})", e);
+                    }
+                    thermostat.set_temperature_scale(temperature_scale);
+                    if let Err(e) = storage.set(TEMPERATURE_SCALE_KEY, &temperature_scale) {
+                        log::error!("failed to persist temperature scale ({})", e);
+                    }
+                    None
+                }
+                Some(MenuAction::SetCalibration(calibration)) => {
+                    log::info!("TMP36 calibration {:?} configured", calibration);
+                    if let Err(e) = storage.set(TMP36_CALIBRATION_KEY, &calibration) {
+                        log::error!("failed to persist TMP36 calibration ({})", e);
+                    }
+                    None
+                }
+                None => None,
+            },
+            None => None,
+        };
+        if let Some(mode) = selected_mode {
+            log::info!("mode {:?} selected", mode);
+            menu.set_mode(mode);
+            mode_selected = true;
+            if mode == Mode::Boost {
+                ekit.request_boost(BOOST_DURATION);
+                boost_until = Some(Instant::now() + BOOST_DURATION);
+            } else if boosting {
+                ekit.request_boost(Duration::ZERO);
+                boost_until = None;
+            }
+        }
+
+        // navigate the menu or adjust the requested temperature using the rotary encoder
+        if let Some(steps) = read_requested_temperature_adjustment() {
+            if !menu.rotate(steps) {
+                thermostat.adjust_requested_temperature(steps);
+                // a manual adjustment overrides the schedule until its next block
+                if let Some(week_time) = clock.week_time(Instant::now(), utc_offset_minutes) {
+                    scheduler.override_setpoint(thermostat.requested_temperature(), week_time);
+                }
+            }
             // continue reading input as long as changes are requested
             continue;
         }
 
-        // update the actual temperature
-        actual_temperature.update(read_actual_temperature());
+        // follow the schedule (or its manual override)
+        if let Some(setpoint) = clock
+            .week_time(Instant::now(), utc_offset_minutes)
+            .and_then(|week_time| scheduler.setpoint(week_time))
+        {
+            let setpoint = thermostat.bounded(setpoint);
+            if setpoint != thermostat.requested_temperature() {
+                log::info!("schedule requests {:?}", setpoint);
+                thermostat.set_requested_temperature(setpoint);
+            }
+        }
+
+        // update the actual temperature (and humidity and pressure, if measured), calibrating a TMP36 (the BME280 is
+        // calibrated at the factory)
+        let calibration = menu.calibration();
+        let reading = read_ambient().map(|reading| match AMBIENT_SENSOR {
+            AmbientSensor::Tmp36 => SensorReading {
+                temperature: calibration.apply(reading.temperature),
+                ..reading
+            },
+            AmbientSensor::Bme280 => reading,
+        });
+        actual_temperature.update(reading.map(|reading| reading.temperature), Instant::now());
+        // implausible readings aren't reported either
+        if actual_temperature.most_recent_temperature().is_some() {
+            ambient = reading;
+        }
+
+        // report the ambient conditions to the e-kit
+        if let Some(reading) = ambient {
+            ambient_throttler.throttle(|| {
+                let age = actual_temperature
+                    .time_since_last_reading(Instant::now())
+                    .unwrap_or_default();
+                if let Err(e) = ekit.post_ambient(&reading, age) {
+                    log::error!("failed to report ambient conditions ({})", e);
+                }
+            });
+        }
+
+        // update the e-kit status (e.g. its energy usage)
+        status_throttler.throttle(|| {
+            ekit_status = match ekit.status() {
+                Ok(status) => {
+                    boost_until = status
+                        .boost_remaining()
+                        .map(|remaining| Instant::now() + remaining);
+                    Some(status)
+                }
+                Err(e) => {
+                    log::error!("failed to get e-kit status ({})", e);
+                    None
+                }
+            };
+
+            // follow the temperature scale, setpoint bounds and time configured on the controller
+            if let Some(status) = ekit_status {
+                if let Some(unix_secs) = status.unix_secs {
+                    clock.set(unix_secs, Instant::now());
+                }
+                utc_offset_minutes = status.utc_offset_minutes;
+                if status.temperature_scale != thermostat.temperature_scale() {
+                    log::info!(
+                        "switching to temperature scale {:?}",
+                        status.temperature_scale
+                    );
+                    thermostat.set_temperature_scale(status.temperature_scale);
+                    menu.set_temperature_scale(status.temperature_scale);
+                    if let Err(e) = storage.set(TEMPERATURE_SCALE_KEY, &status.temperature_scale) {
+                        log::error!("failed to persist temperature scale ({})", e);
+                    }
+                }
+                thermostat.set_setpoint_bounds(status.setpoint_bounds());
+            }
+        });
+
+        // follow the schedule configured on the controller
+        schedule_throttler.throttle(|| {
+            match ekit
+                .schedule()
+                .map_err(anyhow::Error::from)
+                .and_then(|schedule| Ok((schedule.schedule()?, schedule.presets)))
+            {
+                Ok((schedule, presets)) => {
+                    if scheduler.set_schedule(schedule, presets) {
+                        log::info!("schedule updated");
+                    }
+                }
+                Err(e) => log::error!("failed to get schedule ({})", e),
+            }
+        });
 
         display_throttler.throttle(|| {
             let output = Output {
+                temperature_scale: thermostat.temperature_scale(),
+                mode: menu.mode(),
                 requested_temperature: thermostat.requested_temperature(),
+                boost_remaining: boost_until
+                    .map(|until| until.saturating_duration_since(Instant::now()))
+                    .filter(|remaining| !remaining.is_zero()),
+                preset: clock
+                    .week_time(Instant::now(), utc_offset_minutes)
+                    .and_then(|week_time| scheduler.preset(week_time)),
+                schedule_overridden: scheduler.is_overridden(),
                 actual_temperature: actual_temperature.last_known_temperature(),
+                trend: actual_temperature.trend(),
+                sensor_fault: thermostat.has_sensor_fault(),
+                humidity: ambient.and_then(|reading| reading.humidity),
+                pressure: ambient.and_then(|reading| reading.pressure),
+                energy_today: ekit_status.map(|status| status.energy_today()),
+                power_limited: ekit_status
+                    .map(|status| status.power_limited)
+                    .unwrap_or(false),
                 wifi_connected: ekit.is_connected(),
+                menu: menu.screen(),
+                calibration: menu.calibration(),
             };
             display(output);
         });
 
-        // run the e-kit based on the *last known* actual temperature
-        let actual_temperature = match actual_temperature.last_known_temperature() {
-            Some(temperature) => temperature,
-            _ => continue,
-        };
-
+        // run the e-kit based on the *last known* actual temperature, as long as it isn't stale, unless turned off or
+        // cooling
         request_throttler.throttle(|| {
-            let run_mode = thermostat.suggested_ekit_run_mode(actual_temperature);
-            ekit.request_user_run_mode(run_mode);
+            let run_mode =
+                thermostat.request(menu.mode(), &actual_temperature, boosting, Instant::now());
+            if std::mem::take(&mut mode_selected) {
+                ekit.request_user_run_mode(run_mode);
+            } else {
+                ekit.request_periodic_run_mode(run_mode);
+            }
         })
     }
 }
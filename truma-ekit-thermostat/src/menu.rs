@@ -0,0 +1,512 @@
+use crate::button::ButtonPress;
+use truma_ekit_core::{
+    peripherals::tmp36::{
+        Tmp36Calibration, CALIBRATION_GAIN_RANGE, MAX_CALIBRATION_OFFSET_CELSIUS,
+    },
+    types::TemperatureScale,
+};
+
+/// The amount the calibration offset (in °C) is adjusted by per step of the rotary encoder.
+const OFFSET_STEP: f32 = 0.1;
+/// The amount the calibration gain is adjusted by per step of the rotary encoder.
+const GAIN_STEP: f32 = 0.01;
+
+/// The mode of the thermostat.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Mode {
+    /// The e-kit is turned off.
+    Off,
+    /// The e-kit is turned off, but the controller heats to keep the ambient temperature above the frost protection
+    /// setpoint.
+    FrostProtection,
+    /// Only the fan of the e-kit runs.
+    Cool,
+    /// The e-kit heats to reach the requested temperature.
+    Auto,
+    /// The e-kit heats at full capacity for a while, returning to `Auto` afterwards.
+    Boost,
+}
+
+impl Mode {
+    /// Returns all modes, in the order they're selected in.
+    pub const fn all() -> [Mode; 5] {
+        [
+            Mode::Off,
+            Mode::FrostProtection,
+            Mode::Cool,
+            Mode::Auto,
+            Mode::Boost,
+        ]
+    }
+
+    /// Returns the name of the mode.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Off => "Off",
+            Mode::FrostProtection => "Frost",
+            Mode::Cool => "Cool",
+            Mode::Auto => "Auto",
+            Mode::Boost => "Boost",
+        }
+    }
+}
+
+/// An entry of the menu.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MenuItem {
+    Mode,
+    Unit,
+    Calibration,
+    Wifi,
+    About,
+}
+
+impl MenuItem {
+    /// Returns all entries, in the order they're listed in.
+    pub const fn all() -> [MenuItem; 5] {
+        [
+            MenuItem::Mode,
+            MenuItem::Unit,
+            MenuItem::Calibration,
+            MenuItem::Wifi,
+            MenuItem::About,
+        ]
+    }
+
+    /// Returns the name of the entry.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MenuItem::Mode => "Mode",
+            MenuItem::Unit => "Unit",
+            MenuItem::Calibration => "Calibration",
+            MenuItem::Wifi => "Wi-Fi",
+            MenuItem::About => "About",
+        }
+    }
+}
+
+/// What the menu shows.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MenuScreen {
+    /// The entries, with the given entry selected.
+    Items(MenuItem),
+    /// The mode being selected.
+    Mode(Mode),
+    /// The temperature scale being selected.
+    Unit(TemperatureScale),
+    /// A page of the calibration menu.
+    Calibration(CalibrationPage),
+    /// The Wi-Fi status.
+    Wifi,
+    /// The firmware version.
+    About,
+}
+
+/// A change made using the menu, to be applied (and persisted) by the thermostat.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MenuAction {
+    SetMode(Mode),
+    SetTemperatureScale(TemperatureScale),
+    SetCalibration(Tmp36Calibration),
+}
+
+/// The menu, navigated using the rotary encoder and its push-button.
+///
+/// A short press opens the menu or an entry and confirms a selection, a long or double press goes back, and rotating
+/// the encoder moves through the entries or changes the value shown. Rendering is left to the display.
+pub struct Menu {
+    screen: Option<MenuScreen>,
+    mode: Mode,
+    temperature_scale: TemperatureScale,
+    calibration_menu: CalibrationMenu,
+}
+
+impl Menu {
+    pub fn new(calibration: Tmp36Calibration) -> Self {
+        Menu {
+            screen: None,
+            mode: Mode::Auto,
+            temperature_scale: TemperatureScale::default(),
+            calibration_menu: CalibrationMenu::new(calibration),
+        }
+    }
+
+    /// Get what the menu shows, if it's open.
+    pub fn screen(&self) -> Option<MenuScreen> {
+        self.screen
+    }
+
+    /// Get the mode of the thermostat.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Set the mode of the thermostat, e.g. when a boost ends.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// Set the temperature scale, e.g. when configured on the controller.
+    pub fn set_temperature_scale(&mut self, temperature_scale: TemperatureScale) {
+        self.temperature_scale = temperature_scale;
+    }
+
+    /// Get the current calibration.
+    pub fn calibration(&self) -> Tmp36Calibration {
+        self.calibration_menu.calibration()
+    }
+
+    /// Handle a press of the push-button.
+    ///
+    /// Returns the change made, if any.
+    pub fn press(&mut self, press: ButtonPress) -> Option<MenuAction> {
+        let back = press != ButtonPress::Short;
+        let (screen, action) = match self.screen {
+            None if back => (None, None),
+            None => (Some(MenuScreen::Items(MenuItem::Mode)), None),
+            Some(MenuScreen::Items(_)) if back => (None, None),
+            Some(MenuScreen::Items(item)) => (Some(self.open(item)), None),
+            Some(MenuScreen::Mode(_)) if back => (Some(MenuScreen::Items(MenuItem::Mode)), None),
+            Some(MenuScreen::Mode(mode)) => {
+                self.mode = mode;
+                (
+                    Some(MenuScreen::Items(MenuItem::Mode)),
+                    Some(MenuAction::SetMode(mode)),
+                )
+            }
+            Some(MenuScreen::Unit(_)) if back => (Some(MenuScreen::Items(MenuItem::Unit)), None),
+            Some(MenuScreen::Unit(temperature_scale)) => {
+                self.temperature_scale = temperature_scale;
+                (
+                    Some(MenuScreen::Items(MenuItem::Unit)),
+                    Some(MenuAction::SetTemperatureScale(temperature_scale)),
+                )
+            }
+            Some(MenuScreen::Calibration(_)) => {
+                let calibration = if back {
+                    self.calibration_menu.close()
+                } else {
+                    self.calibration_menu.press()
+                };
+                match calibration {
+                    Some(calibration) => (
+                        Some(MenuScreen::Items(MenuItem::Calibration)),
+                        Some(MenuAction::SetCalibration(calibration)),
+                    ),
+                    None => (
+                        self.calibration_menu.page().map(MenuScreen::Calibration),
+                        None,
+                    ),
+                }
+            }
+            Some(MenuScreen::Wifi) => (Some(MenuScreen::Items(MenuItem::Wifi)), None),
+            Some(MenuScreen::About) => (Some(MenuScreen::Items(MenuItem::About)), None),
+        };
+        self.screen = screen;
+        action
+    }
+
+    /// Handle a rotation of the encoder by a number of steps.
+    ///
+    /// Returns `false` if the menu is closed, i.e. the steps should adjust the requested temperature instead.
+    pub fn rotate(&mut self, steps: i8) -> bool {
+        self.screen = match self.screen {
+            None => return false,
+            Some(MenuScreen::Items(item)) => {
+                Some(MenuScreen::Items(cycle(&MenuItem::all(), item, steps)))
+            }
+            Some(MenuScreen::Mode(mode)) => {
+                Some(MenuScreen::Mode(cycle(&Mode::all(), mode, steps)))
+            }
+            Some(MenuScreen::Unit(temperature_scale)) => Some(MenuScreen::Unit(cycle(
+                &[TemperatureScale::Celsius, TemperatureScale::Fahrenheit],
+                temperature_scale,
+                steps,
+            ))),
+            Some(MenuScreen::Calibration(page)) => {
+                self.calibration_menu.adjust(steps);
+                Some(MenuScreen::Calibration(page))
+            }
+            screen @ Some(MenuScreen::Wifi | MenuScreen::About) => screen,
+        };
+        true
+    }
+
+    fn open(&mut self, item: MenuItem) -> MenuScreen {
+        match item {
+            MenuItem::Mode => MenuScreen::Mode(self.mode),
+            MenuItem::Unit => MenuScreen::Unit(self.temperature_scale),
+            MenuItem::Calibration => {
+                self.calibration_menu.press();
+                MenuScreen::Calibration(CalibrationPage::Offset)
+            }
+            MenuItem::Wifi => MenuScreen::Wifi,
+            MenuItem::About => MenuScreen::About,
+        }
+    }
+}
+
+/// Returns the item a number of steps away from the current one, wrapping around.
+fn cycle<T: Copy + PartialEq>(items: &[T], current: T, steps: i8) -> T {
+    let index = items.iter().position(|item| *item == current).unwrap_or(0) as i32;
+    let len = items.len() as i32;
+    items[(index + i32::from(steps)).rem_euclid(len) as usize]
+}
+
+/// A page of the calibration menu.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CalibrationPage {
+    Offset,
+    Gain,
+}
+
+/// A menu to calibrate the ambient temperature sensor using the rotary encoder.
+///
+/// Pressing the encoder opens the menu and cycles through its pages, rotating the encoder adjusts the value on the
+/// current page.
+pub struct CalibrationMenu {
+    page: Option<CalibrationPage>,
+    calibration: Tmp36Calibration,
+}
+
+impl CalibrationMenu {
+    pub fn new(calibration: Tmp36Calibration) -> Self {
+        CalibrationMenu {
+            page: None,
+            calibration,
+        }
+    }
+
+    /// Get the current calibration.
+    pub fn calibration(&self) -> Tmp36Calibration {
+        self.calibration
+    }
+
+    /// Get the page currently shown, if the menu is open.
+    pub fn page(&self) -> Option<CalibrationPage> {
+        self.page
+    }
+
+    /// Move to the next page, opening or closing the menu as needed.
+    ///
+    /// Returns the calibration when the menu is closed, so it can be persisted.
+    pub fn press(&mut self) -> Option<Tmp36Calibration> {
+        self.page = match self.page {
+            None => Some(CalibrationPage::Offset),
+            Some(CalibrationPage::Offset) => Some(CalibrationPage::Gain),
+            Some(CalibrationPage::Gain) => None,
+        };
+        match self.page {
+            None => Some(self.calibration),
+            Some(_) => None,
+        }
+    }
+
+    /// Close the menu, if open.
+    ///
+    /// Returns the calibration if the menu was open, so it can be persisted.
+    pub fn close(&mut self) -> Option<Tmp36Calibration> {
+        self.page.take().map(|_| self.calibration)
+    }
+
+    /// Adjust the value on the current page by a number of steps.
+    ///
+    /// The value is kept within the accepted range of the calibration.
+    ///
+    /// Returns `false` if the menu is closed, i.e. the steps should adjust the requested temperature instead.
+    pub fn adjust(&mut self, steps: i8) -> bool {
+        let adjust = |value: f32, step: f32, (min, max): (f32, f32)| {
+            (((value / step).round() + f32::from(steps)) * step).clamp(min, max)
+        };
+        match self.page {
+            Some(CalibrationPage::Offset) => {
+                self.calibration.offset_celsius = adjust(
+                    self.calibration.offset_celsius,
+                    OFFSET_STEP,
+                    (
+                        -MAX_CALIBRATION_OFFSET_CELSIUS,
+                        MAX_CALIBRATION_OFFSET_CELSIUS,
+                    ),
+                )
+            }
+            Some(CalibrationPage::Gain) => {
+                self.calibration.gain =
+                    adjust(self.calibration.gain, GAIN_STEP, CALIBRATION_GAIN_RANGE)
+            }
+            None => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn cycles_through_pages() {
+        let mut menu = CalibrationMenu::new(Tmp36Calibration::default());
+        assert_eq!(menu.page(), None);
+
+        assert_eq!(menu.press(), None);
+        assert_eq!(menu.page(), Some(CalibrationPage::Offset));
+        assert_eq!(menu.press(), None);
+        assert_eq!(menu.page(), Some(CalibrationPage::Gain));
+        assert_eq!(menu.press(), Some(Tmp36Calibration::default()));
+        assert_eq!(menu.page(), None);
+    }
+
+    #[test]
+    fn adjusts_calibration() {
+        let mut menu = CalibrationMenu::new(Tmp36Calibration::default());
+        assert!(!menu.adjust(1));
+
+        menu.press();
+        assert!(menu.adjust(-3));
+        menu.press();
+        assert!(menu.adjust(2));
+
+        let calibration = menu.press().unwrap();
+        assert_approx_eq!(calibration.offset_celsius, -0.3);
+        assert_approx_eq!(calibration.gain, 1.02);
+    }
+
+    #[test]
+    fn keeps_calibration_within_range() {
+        let mut menu = CalibrationMenu::new(Tmp36Calibration::default());
+        menu.press();
+        menu.adjust(100);
+        menu.press();
+        menu.adjust(-100);
+
+        let calibration = menu.press().unwrap();
+        assert_approx_eq!(calibration.offset_celsius, 5.0);
+        assert_approx_eq!(calibration.gain, 0.8);
+        assert!(calibration.validated().is_ok());
+    }
+
+    #[test]
+    fn navigates_items() {
+        let mut menu = Menu::new(Tmp36Calibration::default());
+        assert!(!menu.rotate(1));
+        assert_eq!(menu.screen(), None);
+
+        menu.press(ButtonPress::Short);
+        assert_eq!(menu.screen(), Some(MenuScreen::Items(MenuItem::Mode)));
+        assert!(menu.rotate(3));
+        assert_eq!(menu.screen(), Some(MenuScreen::Items(MenuItem::Wifi)));
+        menu.rotate(2);
+        assert_eq!(menu.screen(), Some(MenuScreen::Items(MenuItem::Mode)));
+        menu.rotate(-1);
+        assert_eq!(menu.screen(), Some(MenuScreen::Items(MenuItem::About)));
+
+        assert_eq!(menu.press(ButtonPress::Short), None);
+        assert_eq!(menu.screen(), Some(MenuScreen::About));
+        menu.press(ButtonPress::Short);
+        assert_eq!(menu.screen(), Some(MenuScreen::Items(MenuItem::About)));
+
+        menu.press(ButtonPress::Long);
+        assert_eq!(menu.screen(), None);
+    }
+
+    #[test]
+    fn selects_mode() {
+        let mut menu = Menu::new(Tmp36Calibration::default());
+        assert_eq!(menu.mode(), Mode::Auto);
+
+        menu.press(ButtonPress::Short);
+        menu.press(ButtonPress::Short);
+        assert_eq!(menu.screen(), Some(MenuScreen::Mode(Mode::Auto)));
+        menu.rotate(1);
+        assert_eq!(menu.screen(), Some(MenuScreen::Mode(Mode::Boost)));
+        menu.rotate(1);
+        assert_eq!(menu.screen(), Some(MenuScreen::Mode(Mode::Off)));
+
+        // going back discards the selection
+        assert_eq!(menu.press(ButtonPress::Double), None);
+        assert_eq!(menu.screen(), Some(MenuScreen::Items(MenuItem::Mode)));
+        assert_eq!(menu.mode(), Mode::Auto);
+
+        menu.press(ButtonPress::Short);
+        menu.rotate(-1);
+        assert_eq!(
+            menu.press(ButtonPress::Short),
+            Some(MenuAction::SetMode(Mode::Cool))
+        );
+        assert_eq!(menu.screen(), Some(MenuScreen::Items(MenuItem::Mode)));
+        assert_eq!(menu.mode(), Mode::Cool);
+
+        menu.press(ButtonPress::Short);
+        menu.rotate(-1);
+        assert_eq!(
+            menu.press(ButtonPress::Short),
+            Some(MenuAction::SetMode(Mode::FrostProtection))
+        );
+        assert_eq!(menu.mode(), Mode::FrostProtection);
+    }
+
+    #[test]
+    fn selects_temperature_scale() {
+        let mut menu = Menu::new(Tmp36Calibration::default());
+        menu.set_temperature_scale(TemperatureScale::Fahrenheit);
+
+        menu.press(ButtonPress::Short);
+        menu.rotate(1);
+        menu.press(ButtonPress::Short);
+        assert_eq!(
+            menu.screen(),
+            Some(MenuScreen::Unit(TemperatureScale::Fahrenheit))
+        );
+        menu.rotate(1);
+        assert_eq!(
+            menu.press(ButtonPress::Short),
+            Some(MenuAction::SetTemperatureScale(TemperatureScale::Celsius))
+        );
+        assert_eq!(menu.screen(), Some(MenuScreen::Items(MenuItem::Unit)));
+    }
+
+    #[test]
+    fn calibrates() {
+        let mut menu = Menu::new(Tmp36Calibration::default());
+        menu.press(ButtonPress::Short);
+        menu.rotate(2);
+        menu.press(ButtonPress::Short);
+        assert_eq!(
+            menu.screen(),
+            Some(MenuScreen::Calibration(CalibrationPage::Offset))
+        );
+        menu.rotate(5);
+        menu.press(ButtonPress::Short);
+        assert_eq!(
+            menu.screen(),
+            Some(MenuScreen::Calibration(CalibrationPage::Gain))
+        );
+        menu.rotate(-1);
+
+        let calibration = match menu.press(ButtonPress::Short) {
+            Some(MenuAction::SetCalibration(calibration)) => calibration,
+            action => panic!("unexpected action {:?}", action),
+        };
+        assert_approx_eq!(calibration.offset_celsius, 0.5);
+        assert_approx_eq!(calibration.gain, 0.99);
+        assert_eq!(menu.calibration(), calibration);
+        assert_eq!(
+            menu.screen(),
+            Some(MenuScreen::Items(MenuItem::Calibration))
+        );
+
+        // going back keeps the calibration
+        menu.press(ButtonPress::Short);
+        menu.rotate(1);
+        assert!(matches!(
+            menu.press(ButtonPress::Long),
+            Some(MenuAction::SetCalibration(_))
+        ));
+        assert_approx_eq!(menu.calibration().offset_celsius, 0.6);
+        assert_eq!(
+            menu.screen(),
+            Some(MenuScreen::Items(MenuItem::Calibration))
+        );
+    }
+}
@@ -1,18 +1,20 @@
+use crate::{
+    caching::Trend,
+    menu::{CalibrationPage, MenuScreen, Mode},
+    schedule::Preset,
+};
 use embedded_graphics::{
     mono_font::{
         iso_8859_1::{FONT_10X20, FONT_6X10},
         MonoTextStyleBuilder,
     },
     pixelcolor::BinaryColor,
-    prelude::Point,
+    prelude::{Point, Primitive},
+    primitives::{PrimitiveStyle, Triangle},
     text::{renderer::TextRenderer, Alignment, Baseline, Text, TextStyle},
     Drawable,
 };
-use esp_idf_hal::{
-    gpio::AnyIOPin,
-    i2c::{I2c, I2cConfig, I2cDriver},
-    peripheral::Peripheral,
-};
+use esp_idf_hal::i2c::I2cDriver;
 use ssd1306::{
     mode::{BufferedGraphicsMode, DisplayConfig},
     prelude::WriteOnlyDataCommand,
@@ -20,22 +22,49 @@ use ssd1306::{
     size::DisplaySize128x64,
     I2CDisplayInterface, Ssd1306,
 };
-use truma_ekit_core::{measurement::Formatter as MeasurementFormatter, types::Temperature};
+use std::time::Duration;
+use truma_ekit_core::{
+    i2c::SharedI2c,
+    measurement::Formatter as MeasurementFormatter,
+    peripherals::tmp36::Tmp36Calibration,
+    types::{Energy, Percent, Pressure, Temperature, TemperatureScale, UnitPressure},
+};
 
 #[derive(Debug)]
 pub struct Output {
+    /// The temperature scale temperatures are displayed in.
+    pub temperature_scale: TemperatureScale,
+    /// The mode of the thermostat.
+    pub mode: Mode,
     pub requested_temperature: Temperature,
+    /// The time remaining until the boost ends, if boosting.
+    pub boost_remaining: Option<Duration>,
+    /// The preset selected by the schedule, if it is being followed.
+    pub preset: Option<Preset>,
+    /// `true` if the schedule has been overridden until its next block.
+    pub schedule_overridden: bool,
     pub actual_temperature: Option<Temperature>,
+    /// The direction the actual temperature is changing in, if known.
+    pub trend: Option<Trend>,
+    /// `true` if the e-kit has been turned off because there are no fresh temperature readings.
+    pub sensor_fault: bool,
+    /// The relative humidity, if measured by the ambient sensor.
+    pub humidity: Option<Percent>,
+    /// The air pressure, if measured by the ambient sensor.
+    pub pressure: Option<Pressure>,
+    /// The energy used by the e-kit today, if known.
+    pub energy_today: Option<Energy>,
+    /// `true` if the e-kit run mode has been downgraded to stay within the power budget.
+    pub power_limited: bool,
     pub wifi_connected: bool,
+    /// What the menu shows, if it's open.
+    pub menu: Option<MenuScreen>,
+    /// The calibration of the ambient temperature sensor.
+    pub calibration: Tmp36Calibration,
 }
 
-pub fn display<'a, I2C: I2c>(
-    i2c: impl Peripheral<P = I2C> + 'a,
-    sda: AnyIOPin,
-    scl: AnyIOPin,
-) -> impl FnMut(Output) + 'a {
-    let driver = I2cDriver::new(i2c, sda, scl, &I2cConfig::default()).unwrap();
-    let interface = I2CDisplayInterface::new(driver);
+pub fn display<'a>(i2c: SharedI2c<I2cDriver<'a>>) -> impl FnMut(Output) + 'a {
+    let interface = I2CDisplayInterface::new(i2c);
     let mut display = Ssd1306::new(interface, DisplaySize128x64, DisplayRotation::Rotate0)
         .into_buffered_graphics_mode();
     display.init().unwrap();
@@ -69,9 +98,11 @@ fn render_output<DI, TN, TL>(
 
     let formatter = MeasurementFormatter::with_precision(1);
 
-    // actual temperature
-    let actual_temperature = if let Some(actual_temperature) = output.actual_temperature {
-        formatter.format(&actual_temperature)
+    // actual temperature, or the sensor fault
+    let actual_temperature = if output.sensor_fault {
+        String::from("SENSOR FAULT")
+    } else if let Some(actual_temperature) = output.actual_temperature {
+        formatter.format(&actual_temperature.converted_to(output.temperature_scale.unit()))
     } else {
         String::from("??")
     };
@@ -84,9 +115,102 @@ fn render_output<DI, TN, TL>(
     .draw(display)
     .unwrap_or_else(|_| panic!("failed to render output"));
 
-    // requested temperature
-    let requested_temperature = formatter.format(&output.requested_temperature);
-    let requested_temperature = format!("Requested: {}", requested_temperature);
+    // rising or falling arrow next to the actual temperature
+    let arrow = match output.trend.filter(|_| !output.sensor_fault) {
+        Some(Trend::Rising) => Some(Triangle::new(
+            Point::new(116, 20),
+            Point::new(112, 26),
+            Point::new(120, 26),
+        )),
+        Some(Trend::Falling) => Some(Triangle::new(
+            Point::new(112, 20),
+            Point::new(120, 20),
+            Point::new(116, 26),
+        )),
+        Some(Trend::Steady) | None => None,
+    };
+    if let Some(arrow) = arrow {
+        arrow
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(display)
+            .unwrap_or_else(|_| panic!("failed to render output"));
+    }
+
+    // humidity and pressure
+    let climate = [
+        output
+            .humidity
+            .map(|humidity| format!("{:.0}%", humidity.to_f32())),
+        output.pressure.map(|pressure| {
+            MeasurementFormatter::with_precision(0)
+                .format(&pressure.converted_to(UnitPressure::hectopascals()))
+        }),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join("  ");
+    if !climate.is_empty() {
+        let mut text_style = TextStyle::with_alignment(Alignment::Center);
+        text_style.baseline = Baseline::Top;
+
+        Text::with_text_style(
+            &climate,
+            Point::new(64, 11),
+            normal_text_style.clone(),
+            text_style,
+        )
+        .draw(display)
+        .unwrap_or_else(|_| panic!("failed to render output"));
+    }
+
+    // requested temperature, the boost countdown, the mode if not heating, or the menu
+    let calibration = output.calibration;
+    let requested_temperature = match output.menu {
+        Some(MenuScreen::Items(item)) => format!("> {}", item.name()),
+        Some(MenuScreen::Mode(mode)) => format!("Mode: < {} >", mode.name()),
+        Some(MenuScreen::Unit(temperature_scale)) => {
+            let unit = match temperature_scale {
+                TemperatureScale::Celsius => "°C",
+                TemperatureScale::Fahrenheit => "°F",
+            };
+            format!("Unit: < {} >", unit)
+        }
+        Some(MenuScreen::Calibration(CalibrationPage::Offset)) => {
+            format!("Offset: {:+.1}°C", calibration.offset_celsius)
+        }
+        Some(MenuScreen::Calibration(CalibrationPage::Gain)) => {
+            format!("Gain: {:.2}", calibration.gain)
+        }
+        Some(MenuScreen::Wifi) if output.wifi_connected => String::from("Wi-Fi: connected"),
+        Some(MenuScreen::Wifi) => String::from("Wi-Fi: disconnected"),
+        Some(MenuScreen::About) => format!("Thermostat v{}", env!("CARGO_PKG_VERSION")),
+        None if matches!(output.mode, Mode::Off | Mode::FrostProtection | Mode::Cool) => {
+            format!("Mode: {}", output.mode.name())
+        }
+        None => match output.boost_remaining {
+            // countdown of the boost
+            Some(remaining) => {
+                let remaining = remaining.as_secs();
+                format!("Boost: {}:{:02}", remaining / 60, remaining % 60)
+            }
+            None => {
+                let requested_temperature = formatter.format(
+                    &output
+                        .requested_temperature
+                        .converted_to(output.temperature_scale.unit()),
+                );
+                let label = match output.preset {
+                    _ if output.schedule_overridden => "Manual",
+                    Some(Preset::Comfort) => "Comfort",
+                    Some(Preset::Eco) => "Eco",
+                    Some(Preset::Away) => "Away",
+                    None => "Requested",
+                };
+                format!("{}: {}", label, requested_temperature)
+            }
+        },
+    };
     Text::with_alignment(
         &requested_temperature,
         Point::new(64, 48),
@@ -96,6 +220,18 @@ fn render_output<DI, TN, TL>(
     .draw(display)
     .unwrap_or_else(|_| panic!("failed to render output"));
 
+    // power budget
+    if output.power_limited {
+        Text::with_alignment(
+            "POWER LIMITED",
+            Point::new(64, 62),
+            normal_text_style.clone(),
+            Alignment::Center,
+        )
+        .draw(display)
+        .unwrap_or_else(|_| panic!("failed to render output"));
+    }
+
     // wifi status
     let status = if output.wifi_connected {
         "CONNECTED"
@@ -105,9 +241,30 @@ fn render_output<DI, TN, TL>(
     let mut text_style = TextStyle::with_alignment(Alignment::Right);
     text_style.baseline = Baseline::Top;
 
-    Text::with_text_style(status, Point::new(128, 0), normal_text_style, text_style)
+    Text::with_text_style(
+        status,
+        Point::new(128, 0),
+        normal_text_style.clone(),
+        text_style,
+    )
+    .draw(display)
+    .unwrap_or_else(|_| panic!("failed to render output"));
+
+    // energy used today
+    if let Some(energy_today) = output.energy_today {
+        let energy_today = formatter.format_scaled(&energy_today);
+        let mut text_style = TextStyle::with_alignment(Alignment::Left);
+        text_style.baseline = Baseline::Top;
+
+        Text::with_text_style(
+            &energy_today,
+            Point::new(0, 0),
+            normal_text_style,
+            text_style,
+        )
         .draw(display)
         .unwrap_or_else(|_| panic!("failed to render output"));
+    }
 
     display.flush().unwrap();
 }
@@ -25,6 +25,7 @@ impl SystemPeripherals<I2C0, ADC1, Gpio2> {
             rot: RotaryPeripherals {
                 pin_a: peripherals.pins.gpio10.into(),
                 pin_b: peripherals.pins.gpio11.into(),
+                switch: peripherals.pins.gpio4.into(),
             },
             temperature: ThermometerPeripherals {
                 adc: peripherals.adc1,
@@ -45,6 +46,8 @@ pub struct I2cPeripherals<I2C> {
 pub struct RotaryPeripherals {
     pub pin_a: AnyInputPin,
     pub pin_b: AnyInputPin,
+    /// The push-button of the rotary encoder.
+    pub switch: AnyInputPin,
 }
 
 pub struct ThermometerPeripherals<ADC, GP> {
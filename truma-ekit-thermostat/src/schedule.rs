@@ -0,0 +1,137 @@
+use truma_ekit_core::types::Temperature;
+
+pub use truma_ekit_core::schedule::{Preset, Presets, Schedule, WeekTime, Weekday};
+
+/// A manually requested setpoint, overriding the schedule until the next block starts.
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct Override {
+    setpoint: Temperature,
+    /// The start of the block that was active when the override was requested.
+    block_start: Option<WeekTime>,
+}
+
+/// Determines the setpoint from the schedule and any manual override.
+pub struct Scheduler {
+    schedule: Schedule,
+    presets: Presets,
+    manual_override: Option<Override>,
+}
+
+impl Scheduler {
+    pub fn new(schedule: Schedule, presets: Presets) -> Self {
+        Scheduler {
+            schedule,
+            presets,
+            manual_override: None,
+        }
+    }
+
+    /// Replace the schedule and the setpoints of its presets, e.g. with the ones configured on the controller.
+    ///
+    /// Returns `true` if they changed.
+    pub fn set_schedule(&mut self, schedule: Schedule, presets: Presets) -> bool {
+        if schedule == self.schedule && presets == self.presets {
+            return false;
+        }
+        self.schedule = schedule;
+        self.presets = presets;
+        true
+    }
+
+    /// Returns the preset selected by the schedule at `time`, if any.
+    pub fn preset(&self, time: WeekTime) -> Option<Preset> {
+        self.schedule
+            .active_block(time)
+            .map(|(_, block)| block.preset)
+    }
+
+    /// Returns `true` if the schedule is currently overridden.
+    pub fn is_overridden(&self) -> bool {
+        self.manual_override.is_some()
+    }
+
+    /// Override the schedule at `time` with the given setpoint, until the next block starts.
+    pub fn override_setpoint(&mut self, setpoint: Temperature, time: WeekTime) {
+        self.manual_override = Some(Override {
+            setpoint,
+            block_start: self.schedule.active_block(time).map(|(start, _)| start),
+        });
+    }
+
+    /// Returns the setpoint at `time`, if the schedule has any blocks (or is overridden).
+    ///
+    /// An override expires once the next block starts.
+    pub fn setpoint(&mut self, time: WeekTime) -> Option<Temperature> {
+        let active_block = self.schedule.active_block(time);
+        if let Some(manual_override) = self.manual_override {
+            if manual_override.block_start == active_block.map(|(start, _)| start) {
+                return Some(manual_override.setpoint);
+            }
+            self.manual_override = None;
+        }
+        active_block.map(|(_, block)| self.presets.setpoint(block.preset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use truma_ekit_core::util::celsius;
+
+    #[test]
+    fn setpoint_follows_schedule() {
+        let mut scheduler = Scheduler::new(Schedule::default_week(), Presets::default());
+        assert_eq!(
+            scheduler.setpoint(WeekTime::new(Weekday::Wednesday, 7, 0)),
+            Some(celsius(21.0))
+        );
+        assert_eq!(
+            scheduler.setpoint(WeekTime::new(Weekday::Wednesday, 23, 0)),
+            Some(celsius(17.0))
+        );
+
+        let mut scheduler = Scheduler::new(Schedule::default(), Presets::default());
+        assert_eq!(
+            scheduler.setpoint(WeekTime::new(Weekday::Wednesday, 7, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn follows_updated_schedule() {
+        let mut scheduler = Scheduler::new(Schedule::default(), Presets::default());
+        let time = WeekTime::new(Weekday::Wednesday, 7, 0);
+        assert_eq!(scheduler.setpoint(time), None);
+
+        let presets = Presets {
+            comfort_celsius: 22.0,
+            ..Presets::default()
+        };
+        assert!(scheduler.set_schedule(Schedule::default_week(), presets));
+        assert_eq!(scheduler.setpoint(time), Some(celsius(22.0)));
+        assert!(!scheduler.set_schedule(Schedule::default_week(), presets));
+    }
+
+    #[test]
+    fn override_lasts_until_next_block() {
+        let mut scheduler = Scheduler::new(Schedule::default_week(), Presets::default());
+
+        scheduler.override_setpoint(celsius(19.0), WeekTime::new(Weekday::Wednesday, 20, 0));
+        assert!(scheduler.is_overridden());
+        assert_eq!(
+            scheduler.setpoint(WeekTime::new(Weekday::Wednesday, 22, 29)),
+            Some(celsius(19.0))
+        );
+
+        // the eco block starts
+        assert_eq!(
+            scheduler.setpoint(WeekTime::new(Weekday::Wednesday, 22, 30)),
+            Some(celsius(17.0))
+        );
+        assert!(!scheduler.is_overridden());
+        assert_eq!(
+            scheduler.setpoint(WeekTime::new(Weekday::Wednesday, 22, 29)),
+            Some(celsius(21.0))
+        );
+    }
+}
@@ -1,54 +1,227 @@
-use crate::celsius;
-use truma_ekit_core::{ekit::EKitUserRunMode, types::Temperature};
+use crate::{caching::CachedTemperature, menu::Mode};
+use std::time::{Duration, Instant};
+use truma_ekit_core::{
+    control::{
+        ControlDecision, ControlInputs, ControlStrategy, SetpointBounds, CONTROL_STRATEGY,
+        SETPOINT_BOUNDS,
+    },
+    ekit::EKitUserRunMode,
+    types::{Temperature, TemperatureDelta, TemperatureScale},
+};
 
-/// The threshold for running the controller at full capacity.
-/// If the temperature difference is below this value, the controller will be run at half capacity.
-const FULL_CAPACITY_TRESHOLD: Temperature = celsius(1.5);
+/// The maximum age of the temperature used to run the e-kit.
+/// If there hasn't been a valid reading for longer, the e-kit is turned off until fresh readings return.
+const MAX_TEMPERATURE_AGE: Duration = Duration::from_secs(2 * 60);
 
 pub struct Thermostat {
     requested_temperature: Temperature,
+    temperature_scale: TemperatureScale,
+    setpoint_bounds: SetpointBounds,
+    strategy: Box<dyn ControlStrategy>,
+    max_temperature_age: Duration,
+    sensor_fault: bool,
+    decision: Option<ControlDecision>,
+    /// The run mode requested last, e.g. the one selected in the menu rather than the one decided on.
+    requested_run_mode: EKitUserRunMode,
 }
 
 impl Thermostat {
     pub fn new(requested_temperature: Temperature) -> Self {
         Thermostat {
             requested_temperature,
+            temperature_scale: TemperatureScale::default(),
+            setpoint_bounds: SETPOINT_BOUNDS,
+            strategy: Box::new(CONTROL_STRATEGY),
+            max_temperature_age: MAX_TEMPERATURE_AGE,
+            sensor_fault: false,
+            decision: None,
+            requested_run_mode: EKitUserRunMode::Off,
         }
     }
 
+    /// Use a different strategy to decide on the run mode.
+    #[cfg(test)]
+    pub fn with_strategy(mut self, strategy: impl ControlStrategy + 'static) -> Self {
+        self.strategy = Box::new(strategy);
+        self
+    }
+
+    /// Use a different maximum age of the temperature used to run the e-kit.
+    #[cfg(test)]
+    pub fn with_max_temperature_age(mut self, max_temperature_age: Duration) -> Self {
+        self.max_temperature_age = max_temperature_age;
+        self
+    }
+
+    /// Returns `true` if the e-kit has been turned off because there are no fresh temperature readings.
+    pub fn has_sensor_fault(&self) -> bool {
+        self.sensor_fault
+    }
+
+    /// Get the temperature scale preferred by the user.
+    pub fn temperature_scale(&self) -> TemperatureScale {
+        self.temperature_scale
+    }
+
+    /// Set the temperature scale preferred by the user.
+    ///
+    /// The requested temperature is converted to the new scale, and rounded to its step size.
+    pub fn set_temperature_scale(&mut self, temperature_scale: TemperatureScale) {
+        self.temperature_scale = temperature_scale;
+        self.requested_temperature = temperature_scale.rounded(self.requested_temperature);
+    }
+
     /// Get the requested temperature.
     pub fn requested_temperature(&self) -> Temperature {
         self.requested_temperature
     }
 
     /// Set the requested temperature.
+    ///
+    /// The requested temperature is kept within the setpoint bounds, and rounded to the step size.
     pub fn set_requested_temperature(&mut self, temperature: Temperature) {
-        self.requested_temperature = temperature;
+        self.requested_temperature = self.bounded(temperature);
     }
 
-    /// Get the suggested run mode for the given actual temperature.
-    pub fn suggested_ekit_run_mode(&self, actual_temperature: Temperature) -> EKitUserRunMode {
-        if actual_temperature >= self.requested_temperature {
-            // the actual temperature is equal to or higher than the requested temperature, turn off the heating
-            EKitUserRunMode::Off
+    /// Returns the temperature kept within the setpoint bounds, converted to the temperature scale and rounded to its
+    /// step size, as it would be requested.
+    pub fn bounded(&self, temperature: Temperature) -> Temperature {
+        self.temperature_scale
+            .rounded(self.setpoint_bounds.clamped(temperature))
+    }
+
+    /// Set the lowest and highest temperatures that can be requested.
+    ///
+    /// The requested temperature is kept within the new bounds.
+    pub fn set_setpoint_bounds(&mut self, setpoint_bounds: SetpointBounds) {
+        self.setpoint_bounds = setpoint_bounds;
+        self.requested_temperature = self.bounded(self.requested_temperature);
+    }
+
+    /// Adjust the requested temperature by a number of steps of the temperature scale's step size.
+    ///
+    /// The requested temperature is kept within the setpoint bounds.
+    pub fn adjust_requested_temperature(&mut self, steps: i8) {
+        let adjustment = self.temperature_scale.step_size() * f32::from(steps);
+        self.requested_temperature = self.bounded(self.requested_temperature + adjustment);
+    }
+
+    /// Get the run mode to request at `now` in the mode selected in the menu, deciding on it based on the actual
+    /// temperature in `Auto` (or `Boost`) mode.
+    pub fn request(
+        &mut self,
+        mode: Mode,
+        actual_temperature: &CachedTemperature,
+        boosting: bool,
+        now: Instant,
+    ) -> EKitUserRunMode {
+        // the controller runs at full capacity while boosting
+        let current_mode = if boosting {
+            EKitUserRunMode::Full
         } else {
-            // the actual temperature is less than the requested temperature, turn on the heating
-            let temp_diff = self.requested_temperature - actual_temperature;
-            if temp_diff < FULL_CAPACITY_TRESHOLD {
-                // run the heating at half capacity
-                EKitUserRunMode::Half
-            } else {
-                // run the heating at full capacity
-                EKitUserRunMode::Full
+            self.requested_run_mode
+        };
+        let run_mode = self.control(actual_temperature, current_mode, now);
+        self.requested_run_mode = match mode {
+            Mode::Off => EKitUserRunMode::Off,
+            // the controller keeps running it when requested repeatedly
+            Mode::FrostProtection => EKitUserRunMode::FrostProtection,
+            Mode::Cool => EKitUserRunMode::Cool,
+            Mode::Auto | Mode::Boost => run_mode,
+        };
+        self.requested_run_mode
+    }
+
+    /// Get the run mode to request at `now`, based on the actual temperature and the run mode currently in effect (e.g.
+    /// `Full` while boosting, or the mode selected in the menu).
+    ///
+    /// Requests `Off` while the actual temperature is stale, rather than running on an outdated reading.
+    pub fn control(
+        &mut self,
+        actual_temperature: &CachedTemperature,
+        current_mode: EKitUserRunMode,
+        now: Instant,
+    ) -> EKitUserRunMode {
+        let temperature = actual_temperature
+            .last_known_temperature()
+            .filter(|_| !actual_temperature.is_stale(self.max_temperature_age, now));
+        match temperature {
+            Some(temperature) => {
+                if self.sensor_fault {
+                    log::info!("fresh temperature readings returned, resuming");
+                    self.sensor_fault = false;
+                }
+                let decision = self.decide(
+                    temperature,
+                    actual_temperature.rate_of_change(),
+                    current_mode,
+                    now,
+                );
+                if self.decision.map(|previous| previous.reason) != Some(decision.reason) {
+                    log::info!(
+                        "{} strategy requests {:?}: {:?}",
+                        self.strategy.name(),
+                        decision.run_mode,
+                        decision.reason
+                    );
+                }
+                self.decision = Some(decision);
+                decision.run_mode
+            }
+            None => {
+                if !self.sensor_fault {
+                    log::warn!("no fresh temperature readings, turning off the e-kit");
+                    self.sensor_fault = true;
+                }
+                self.decision = None;
+                EKitUserRunMode::Off
             }
         }
     }
+
+    /// Decide on the run mode for the given actual temperature (and its rate of change, if known).
+    fn decide(
+        &self,
+        actual_temperature: Temperature,
+        rate_of_change: Option<TemperatureDelta>,
+        current_mode: EKitUserRunMode,
+        now: Instant,
+    ) -> ControlDecision {
+        self.strategy.decide(&ControlInputs {
+            setpoint: self.requested_temperature,
+            ambient: actual_temperature,
+            rate_of_change,
+            current_mode,
+            now,
+        })
+    }
+
+    /// Get the suggested run mode for the given actual temperature.
+    #[cfg(test)]
+    pub fn suggested_ekit_run_mode(&self, actual_temperature: Temperature) -> EKitUserRunMode {
+        self.decide(
+            actual_temperature,
+            None,
+            EKitUserRunMode::Off,
+            Instant::now(),
+        )
+        .run_mode
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use truma_ekit_core::util::celsius;
+    use assert_approx_eq::assert_approx_eq;
+    use std::{cell::Cell, rc::Rc};
+    use truma_ekit_core::{
+        control::ControlReason,
+        measurement::Measurement,
+        peripherals::temperature_sensor::TemperatureSensor,
+        testing::{secs, FakeClock, Scenario, ScriptedSensor},
+        types::UnitTemperature,
+        util::celsius,
+    };
 
     #[test]
     fn actual_temperature_lower_than_requested_temperature() {
@@ -102,4 +275,316 @@ mod tests {
             EKitUserRunMode::Off
         );
     }
+
+    #[test]
+    fn mixed_units() {
+        // 70 °F = 21.11 °C
+        let thermostat = Thermostat::new(Measurement::new(70.0, UnitTemperature::fahrenheit()));
+        assert_eq!(
+            thermostat.suggested_ekit_run_mode(celsius(20.0)),
+            EKitUserRunMode::Half
+        );
+        assert_eq!(
+            thermostat.suggested_ekit_run_mode(celsius(19.5)),
+            EKitUserRunMode::Full
+        );
+        assert_eq!(
+            thermostat.suggested_ekit_run_mode(celsius(21.2)),
+            EKitUserRunMode::Off
+        );
+    }
+
+    #[test]
+    fn adjusts_requested_temperature_in_steps() {
+        let mut thermostat = Thermostat::new(celsius(20.5));
+        thermostat.adjust_requested_temperature(1);
+        assert_eq!(thermostat.requested_temperature(), celsius(21.0));
+        thermostat.adjust_requested_temperature(-3);
+        assert_eq!(thermostat.requested_temperature(), celsius(19.5));
+
+        thermostat.set_temperature_scale(TemperatureScale::Fahrenheit);
+        let requested_temperature = thermostat.requested_temperature();
+        assert_eq!(requested_temperature.unit, UnitTemperature::fahrenheit());
+        assert_eq!(requested_temperature.value, 67.0);
+        thermostat.adjust_requested_temperature(1);
+        assert_eq!(thermostat.requested_temperature().value, 68.0);
+    }
+
+    #[test]
+    fn keeps_requested_temperature_within_limits() {
+        let mut thermostat = Thermostat::new(celsius(29.5));
+        thermostat.adjust_requested_temperature(3);
+        assert_eq!(thermostat.requested_temperature(), celsius(30.0));
+
+        thermostat.set_temperature_scale(TemperatureScale::Fahrenheit);
+        assert_eq!(thermostat.requested_temperature().value, 86.0);
+        thermostat.adjust_requested_temperature(-100);
+        assert_eq!(thermostat.requested_temperature().value, 41.0);
+    }
+
+    #[test]
+    fn clamps_requested_temperature_to_bounds() {
+        let mut thermostat = Thermostat::new(celsius(20.0));
+        thermostat.set_setpoint_bounds(SetpointBounds::new(celsius(10.0), celsius(25.0)));
+        thermostat.adjust_requested_temperature(20);
+        assert_eq!(thermostat.requested_temperature(), celsius(25.0));
+        thermostat.adjust_requested_temperature(-100);
+        assert_eq!(thermostat.requested_temperature(), celsius(10.0));
+
+        thermostat.set_requested_temperature(celsius(80.0));
+        assert_eq!(thermostat.requested_temperature(), celsius(25.0));
+        thermostat.set_requested_temperature(celsius(-40.0));
+        assert_eq!(thermostat.requested_temperature(), celsius(10.0));
+        thermostat.set_requested_temperature(celsius(18.0));
+        assert_eq!(thermostat.requested_temperature(), celsius(18.0));
+        thermostat.set_requested_temperature(celsius(f32::NAN));
+        assert_eq!(thermostat.requested_temperature(), celsius(10.0));
+
+        thermostat.set_requested_temperature(celsius(24.0));
+        thermostat.set_setpoint_bounds(SetpointBounds::new(celsius(10.0), celsius(22.0)));
+        assert_eq!(thermostat.requested_temperature(), celsius(22.0));
+    }
+
+    #[test]
+    fn uses_strategy() {
+        /// Heats at full capacity whenever below the requested temperature.
+        struct FullStrategy;
+
+        impl ControlStrategy for FullStrategy {
+            fn name(&self) -> &'static str {
+                "full"
+            }
+
+            fn decide(&self, inputs: &ControlInputs) -> ControlDecision {
+                let below = inputs.ambient < inputs.setpoint;
+                ControlDecision {
+                    run_mode: if below {
+                        EKitUserRunMode::Full
+                    } else {
+                        EKitUserRunMode::Off
+                    },
+                    reason: if below {
+                        ControlReason::FarBelowSetpoint
+                    } else {
+                        ControlReason::SetpointReached
+                    },
+                }
+            }
+        }
+
+        let thermostat = Thermostat::new(celsius(21.0)).with_strategy(FullStrategy);
+        assert_eq!(
+            thermostat.suggested_ekit_run_mode(celsius(20.9)),
+            EKitUserRunMode::Full
+        );
+        assert_eq!(
+            thermostat.suggested_ekit_run_mode(celsius(21.0)),
+            EKitUserRunMode::Off
+        );
+    }
+
+    #[test]
+    fn passes_current_mode_and_rate_of_change_to_strategy() {
+        /// Records the inputs it decides on.
+        struct RecordingStrategy(Rc<Cell<Option<ControlInputs>>>);
+
+        impl ControlStrategy for RecordingStrategy {
+            fn name(&self) -> &'static str {
+                "recording"
+            }
+
+            fn decide(&self, inputs: &ControlInputs) -> ControlDecision {
+                self.0.set(Some(*inputs));
+                ControlDecision {
+                    run_mode: EKitUserRunMode::Off,
+                    reason: ControlReason::SetpointReached,
+                }
+            }
+        }
+
+        let inputs = Rc::new(Cell::new(None));
+        let mut thermostat =
+            Thermostat::new(celsius(21.0)).with_strategy(RecordingStrategy(inputs.clone()));
+        let start = Instant::now();
+        let mut actual_temperature = CachedTemperature::new(None);
+        // rising by 1 °C per minute
+        for secs in (0..=120).step_by(10) {
+            let now = start + Duration::from_secs(secs);
+            actual_temperature.update(Some(celsius(18.0 + secs as f32 / 60.0)), now);
+            // boosting, whatever the thermostat decided on before
+            thermostat.control(&actual_temperature, EKitUserRunMode::Full, now);
+        }
+
+        let inputs = inputs.get().unwrap();
+        assert_eq!(inputs.current_mode, EKitUserRunMode::Full);
+        let rate = inputs.rate_of_change.unwrap();
+        assert_approx_eq!(
+            rate.converted_to(UnitTemperature::celsius().delta()).value,
+            1.0,
+            1e-3
+        );
+    }
+
+    /// The thermostat loop, requesting a run mode based on the measured ambient temperature and the selected mode.
+    struct Room {
+        thermostat: Thermostat,
+        sensor: ScriptedSensor,
+        actual_temperature: CachedTemperature,
+        mode: Mode,
+        requested: Option<EKitUserRunMode>,
+    }
+
+    impl Room {
+        fn new(thermostat: Thermostat) -> Self {
+            Room {
+                thermostat,
+                sensor: ScriptedSensor::default(),
+                actual_temperature: CachedTemperature::new(None),
+                mode: Mode::Auto,
+                requested: None,
+            }
+        }
+
+        fn tick(&mut self, now: Instant) {
+            let reading = self.sensor.measure_temperature().ok();
+            self.actual_temperature.update(reading, now);
+            self.requested = Some(self.thermostat.request(
+                self.mode,
+                &self.actual_temperature,
+                self.mode == Mode::Boost,
+                now,
+            ));
+        }
+
+        fn ambient(&mut self, temperature: Option<f32>, now: Instant) {
+            self.sensor.push(temperature.map(celsius));
+            self.tick(now);
+        }
+    }
+
+    #[test]
+    fn scenario_heats_up_room() {
+        let mut room = Room::new(Thermostat::new(celsius(21.0)));
+
+        Scenario::new()
+            .at(secs(0), |room: &mut Room, now| {
+                room.ambient(Some(18.0), now)
+            })
+            .expect(secs(0), |room| room.requested, Some(EKitUserRunMode::Full))
+            .at(secs(60), |room, now| room.ambient(Some(20.0), now))
+            .expect(secs(60), |room| room.requested, Some(EKitUserRunMode::Half))
+            // the user turns up the requested temperature by 1 °C
+            .at(secs(90), |room, now| {
+                room.thermostat.adjust_requested_temperature(2);
+                room.ambient(Some(21.0), now);
+            })
+            .expect(secs(90), |room| room.requested, Some(EKitUserRunMode::Half))
+            .at(secs(120), |room, now| room.ambient(Some(22.0), now))
+            .expect(secs(120), |room| room.requested, Some(EKitUserRunMode::Off))
+            // a failed reading keeps the previous request
+            .at(secs(130), |room, now| room.ambient(None, now))
+            .expect(secs(130), |room| room.requested, Some(EKitUserRunMode::Off))
+            .run(&mut room, &FakeClock::new());
+    }
+
+    #[test]
+    fn scenario_follows_selected_mode() {
+        let mut room = Room::new(Thermostat::new(celsius(21.0)));
+
+        Scenario::new()
+            .at(secs(0), |room: &mut Room, now| {
+                room.ambient(Some(20.0), now)
+            })
+            .expect(secs(0), |room| room.requested, Some(EKitUserRunMode::Half))
+            .at(secs(10), |room, now| {
+                room.mode = Mode::Cool;
+                room.ambient(Some(20.0), now);
+            })
+            .expect(secs(10), |room| room.requested, Some(EKitUserRunMode::Cool))
+            .at(secs(20), |room, now| {
+                room.mode = Mode::FrostProtection;
+                room.ambient(Some(20.0), now);
+            })
+            .expect(
+                secs(20),
+                |room| room.requested,
+                Some(EKitUserRunMode::FrostProtection),
+            )
+            .at(secs(30), |room, now| {
+                room.mode = Mode::Off;
+                room.ambient(Some(20.0), now);
+            })
+            .expect(secs(30), |room| room.requested, Some(EKitUserRunMode::Off))
+            // the thermostat decides again once back in auto mode
+            .at(secs(40), |room, now| {
+                room.mode = Mode::Auto;
+                room.ambient(Some(18.0), now);
+            })
+            .expect(secs(40), |room| room.requested, Some(EKitUserRunMode::Full))
+            .run(&mut room, &FakeClock::new());
+    }
+
+    #[test]
+    fn turns_off_on_stale_temperature() {
+        let mut room = Room::new(
+            Thermostat::new(celsius(21.0)).with_max_temperature_age(Duration::from_secs(60)),
+        );
+
+        Scenario::new()
+            .at(secs(0), |room: &mut Room, now| {
+                room.ambient(Some(18.0), now)
+            })
+            .expect(secs(0), |room| room.requested, Some(EKitUserRunMode::Full))
+            // the sensor dies while heating
+            .every(secs(10), secs(10), secs(120), |room, now| {
+                room.ambient(None, now)
+            })
+            .expect(secs(60), |room| room.requested, Some(EKitUserRunMode::Full))
+            .expect(secs(60), |room| room.thermostat.has_sensor_fault(), false)
+            .expect(secs(70), |room| room.requested, Some(EKitUserRunMode::Off))
+            .expect(secs(70), |room| room.thermostat.has_sensor_fault(), true)
+            .expect(secs(120), |room| room.requested, Some(EKitUserRunMode::Off))
+            // resumes once fresh readings return
+            .at(secs(130), |room, now| room.ambient(Some(20.0), now))
+            .expect(
+                secs(130),
+                |room| room.requested,
+                Some(EKitUserRunMode::Half),
+            )
+            .expect(secs(130), |room| room.thermostat.has_sensor_fault(), false)
+            .run(&mut room, &FakeClock::new());
+    }
+
+    #[test]
+    fn turns_off_on_dead_sensor() {
+        let mut room = Room::new(
+            Thermostat::new(celsius(21.0)).with_max_temperature_age(Duration::from_secs(60)),
+        );
+
+        Scenario::new()
+            .at(secs(0), |room: &mut Room, now| {
+                room.ambient(Some(18.0), now)
+            })
+            .expect(secs(0), |room| room.requested, Some(EKitUserRunMode::Full))
+            // a disconnected TMP36 reads -50 °C, which isn't heated on
+            .every(secs(10), secs(10), secs(120), |room, now| {
+                room.ambient(Some(-50.0), now)
+            })
+            .expect(secs(60), |room| room.requested, Some(EKitUserRunMode::Full))
+            .expect(secs(70), |room| room.requested, Some(EKitUserRunMode::Off))
+            .expect(secs(70), |room| room.thermostat.has_sensor_fault(), true)
+            .expect(secs(120), |room| room.requested, Some(EKitUserRunMode::Off))
+            .run(&mut room, &FakeClock::new());
+    }
+
+    #[test]
+    fn turns_off_without_temperature() {
+        let mut thermostat = Thermostat::new(celsius(21.0));
+        let actual_temperature = CachedTemperature::new(None);
+        assert_eq!(
+            thermostat.control(&actual_temperature, EKitUserRunMode::Off, Instant::now()),
+            EKitUserRunMode::Off
+        );
+        assert!(thermostat.has_sensor_fault());
+    }
 }